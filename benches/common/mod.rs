@@ -0,0 +1,25 @@
+//! Synthesizes T2B tables in memory for the benchmarks, so no multi-megabyte fixture
+//! needs to be checked in.
+
+use cpk_size_sync::{build_minimal_table, StringEncoding, ValueData, ValueLength};
+
+/// Builds a little-endian UTF-8 table with 4-byte values: one `CPK_HEAD` entry and
+/// `count` `CPK_ITEM` entries, each `[prefix, suffix, "", "", size, ratio]` where the
+/// size of item `i` is `i * size_step`.
+pub fn large_table(count: usize, size_step: u32) -> Vec<u8> {
+    let text = |s: String| ValueData::Str(Some(s));
+    let mut entries = vec![("CPK_HEAD".to_string(), vec![ValueData::Int(1)])];
+    for i in 0..count {
+        let values = vec![
+            text(format!("data/dir{:03}/", i % 500)),
+            text(format!("file{i:05}.bin")),
+            text(String::new()),
+            text(String::new()),
+            ValueData::Int(i as i64 * size_step as i64),
+            ValueData::Float(1.5),
+        ];
+        entries.push(("CPK_ITEM".to_string(), values));
+    }
+    build_minimal_table(&entries, ValueLength::Int, StringEncoding::Utf8)
+        .expect("generated table serializes")
+}
@@ -0,0 +1,37 @@
+//! Times `parse_t2b` on a generated table with many `CPK_ITEM` entries.
+//!
+//! Compare `cargo bench --bench parse` with `cargo bench --bench parse --features parallel`
+//! to see the effect of parallel value decoding.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use cpk_size_sync::parse_t2b;
+
+mod common;
+
+const ENTRY_COUNT: usize = 50_000;
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let table = common::large_table(ENTRY_COUNT, 1024);
+    let parsed = parse_t2b(&table).expect("generated table parses");
+    assert_eq!(parsed.entries.len(), ENTRY_COUNT + 1);
+
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        black_box(parse_t2b(black_box(&table)).unwrap());
+        total += start.elapsed();
+    }
+    let mode = if cfg!(feature = "parallel") {
+        "parallel"
+    } else {
+        "sequential"
+    };
+    println!(
+        "parse_t2b ({mode}): {ENTRY_COUNT} entries, {} KiB, {:?} per parse",
+        table.len() / 1024,
+        total / ITERATIONS
+    );
+}
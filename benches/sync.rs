@@ -0,0 +1,77 @@
+//! Times a size sync between two generated tables with many `CPK_ITEM` entries, where
+//! every item of the patched table but the first carries a new size.
+//!
+//! `apply_sizes` is timed in process; the full sync (reading, parsing, matching and
+//! writing, as `run` does it) is timed by invoking the built binary on files in a
+//! temporary directory. Run with `cargo bench --bench sync`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::hint::black_box;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use cpk_size_sync::{apply_sizes, normalize_path, parse_t2b, path_key};
+
+mod common;
+
+const ENTRY_COUNT: usize = 50_000;
+const ITERATIONS: u32 = 20;
+const SIZE_INDEX: usize = 4;
+
+fn main() {
+    let original = common::large_table(ENTRY_COUNT, 1024);
+    let patched = common::large_table(ENTRY_COUNT, 1000);
+    let parsed_a = parse_t2b(&original).expect("generated original parses");
+    let parsed_b = parse_t2b(&patched).expect("generated patched table parses");
+
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let mut target = parsed_a.clone();
+        let start = Instant::now();
+        let sizes: HashMap<String, i64> = parsed_b
+            .entries
+            .iter()
+            .filter(|e| e.name == "CPK_ITEM")
+            .filter_map(|e| {
+                let (prefix, suffix) = path_key(e)?;
+                Some((normalize_path(&prefix, &suffix), e.integer(SIZE_INDEX)?))
+            })
+            .collect();
+        let updated = apply_sizes(&mut target, &sizes, SIZE_INDEX).unwrap();
+        total += start.elapsed();
+        // Item 0 has size 0 in both tables, so it is already up to date.
+        assert_eq!(updated as usize, ENTRY_COUNT - 1);
+        black_box(target);
+    }
+    println!(
+        "apply_sizes: {ENTRY_COUNT} entries, {:?} per sync",
+        total / ITERATIONS
+    );
+
+    let dir = std::env::temp_dir().join(format!("cpk_size_sync-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("create temporary directory");
+    let (path_a, path_b, path_c) = (dir.join("a.bin"), dir.join("b.bin"), dir.join("c.bin"));
+    fs::write(&path_a, &original).expect("write original table");
+    fs::write(&path_b, &patched).expect("write patched table");
+
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        let status = Command::new(env!("CARGO_BIN_EXE_cpk_size_sync"))
+            .arg("-q")
+            .args([&path_a, &path_b, &path_c])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .expect("run cpk_size_sync");
+        total += start.elapsed();
+        assert!(status.success(), "sync failed: {status}");
+    }
+    fs::remove_dir_all(&dir).ok();
+    println!(
+        "cpk_size_sync a.bin b.bin c.bin: {ENTRY_COUNT} entries, {} KiB, {:?} per run",
+        original.len() / 1024,
+        total / ITERATIONS
+    );
+}
@@ -0,0 +1,25 @@
+//! Feeds arbitrary bytes to `read_header` and `parse_t2b`, which must not panic.
+//! Tables that parse must also pass `layout_mismatches` without panicking and survive
+//! a serialize-then-parse round trip unchanged.
+//!
+//! Run with `cargo +nightly fuzz run parse` from the repository root.
+
+#![no_main]
+
+use cpk_size_sync::{layout_mismatches, parse_t2b, read_header, serialize};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    read_header(data);
+    let Ok(parsed) = parse_t2b(data) else {
+        return;
+    };
+    layout_mismatches(&parsed);
+    // Serializing can fail legitimately, e.g. for strings Shift-JIS cannot encode.
+    let Ok(bytes) = serialize(&parsed) else {
+        return;
+    };
+    let reparsed = parse_t2b(&bytes).expect("serialized table parses");
+    let rebuilt = serialize(&reparsed).expect("re-parsed table serializes");
+    assert_eq!(bytes, rebuilt, "serialize-then-parse is not idempotent");
+});
@@ -0,0 +1,647 @@
+//! Reads members of CRI CPK archives, such as the `cpk_list.cfg.bin` packed into a
+//! game's data archive, so a table can be parsed without extracting it first.
+//!
+//! Only reading is supported: archives are never modified or written.
+
+use std::fmt;
+
+const MAGIC_CPK: &[u8; 4] = b"CPK ";
+const MAGIC_TOC: &[u8; 4] = b"TOC ";
+const MAGIC_UTF: &[u8; 4] = b"@UTF";
+const MAGIC_CRILAYLA: &[u8; 8] = b"CRILAYLA";
+
+/// Bytes at the start of a CRILAYLA member's output that are stored uncompressed, after
+/// the compressed data.
+const CRILAYLA_PREFIX: usize = 0x100;
+
+#[derive(Debug)]
+pub enum CpkError {
+    /// The archive does not start with `CPK `.
+    BadMagic,
+    /// A `@UTF` table is cut short or malformed; names which one.
+    BadTable(&'static str),
+    /// The archive header lacks a column every readable archive has.
+    MissingColumn(&'static str),
+    /// The archive has no table of contents, so its members have no names to look up.
+    NoToc,
+    /// No member has the requested name.
+    NotFound(String),
+    /// Several members have the requested file name; their full paths.
+    Ambiguous(Vec<String>),
+    /// A member's data lies past the end of the archive.
+    OutOfRange(String),
+    /// A member's CRILAYLA data does not decompress to its declared size.
+    BadCompression(String),
+}
+
+impl fmt::Display for CpkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpkError::BadMagic => f.write_str("not a CPK archive"),
+            CpkError::BadTable(table) => write!(f, "malformed {table} table"),
+            CpkError::MissingColumn(column) => write!(f, "archive header has no {column}"),
+            CpkError::NoToc => f.write_str("archive has no table of contents"),
+            CpkError::NotFound(name) => write!(f, "no member named {name} in the archive"),
+            CpkError::Ambiguous(paths) => {
+                write!(f, "several members match: {}", paths.join(", "))
+            }
+            CpkError::OutOfRange(path) => write!(f, "{path} lies past the end of the archive"),
+            CpkError::BadCompression(path) => write!(f, "{path} does not decompress"),
+        }
+    }
+}
+
+impl std::error::Error for CpkError {}
+
+/// A file listed in the archive's table of contents.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// Directory within the archive, empty at the root.
+    pub dir: String,
+    pub name: String,
+    /// Absolute offset of the stored data in the archive.
+    pub offset: u64,
+    /// Size of the stored data, compressed or not.
+    pub stored_size: u64,
+    /// Size of the data once decompressed.
+    pub extract_size: u64,
+}
+
+impl Member {
+    /// The member's path within the archive, `dir/name`.
+    pub fn path(&self) -> String {
+        if self.dir.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}/{}", self.dir, self.name)
+        }
+    }
+}
+
+/// Lists the members of `archive` from its table of contents.
+pub fn members(archive: &[u8]) -> Result<Vec<Member>, CpkError> {
+    if !archive.starts_with(MAGIC_CPK) {
+        return Err(CpkError::BadMagic);
+    }
+    let header = read_packet(archive, 0, "archive header")?;
+    let header = UtfTable::parse(&header).ok_or(CpkError::BadTable("archive header"))?;
+    let toc_offset = header.int(0, "TocOffset").ok_or(CpkError::NoToc)?;
+    if toc_offset == 0 {
+        return Err(CpkError::NoToc);
+    }
+    // Member offsets count from whichever of the content and the TOC comes first.
+    let base = match header.int(0, "ContentOffset") {
+        Some(content) => content.min(toc_offset),
+        None => toc_offset,
+    };
+
+    let toc_offset = usize::try_from(toc_offset).map_err(|_| CpkError::BadTable("TOC"))?;
+    if archive.get(toc_offset..toc_offset.saturating_add(4)) != Some(&MAGIC_TOC[..]) {
+        return Err(CpkError::BadTable("TOC"));
+    }
+    let toc = read_packet(archive, toc_offset, "TOC")?;
+    let toc = UtfTable::parse(&toc).ok_or(CpkError::BadTable("TOC"))?;
+    (0..toc.rows.len())
+        .map(|row| {
+            let column = |name| toc.int(row, name).ok_or(CpkError::MissingColumn(name));
+            Ok(Member {
+                dir: toc.string(row, "DirName").unwrap_or_default().to_string(),
+                name: toc.string(row, "FileName").unwrap_or_default().to_string(),
+                offset: base.saturating_add(column("FileOffset")?),
+                stored_size: column("FileSize")?,
+                extract_size: column("ExtractSize")?,
+            })
+        })
+        .collect()
+}
+
+/// Reads and, when it is CRILAYLA-compressed, decompresses the member `name` of
+/// `archive`. A `name` containing `/` is matched against the full member path, any
+/// other against the file name alone, which must then be unique in the archive.
+pub fn read_member(archive: &[u8], name: &str) -> Result<Vec<u8>, CpkError> {
+    let members = members(archive)?;
+    let matches = members
+        .iter()
+        .filter(|m| {
+            if name.contains('/') {
+                m.path() == name.trim_start_matches('/')
+            } else {
+                m.name == name
+            }
+        })
+        .collect::<Vec<_>>();
+    let member = match matches.as_slice() {
+        [] => return Err(CpkError::NotFound(name.to_string())),
+        [member] => *member,
+        _ => {
+            return Err(CpkError::Ambiguous(
+                matches.iter().map(|m| m.path()).collect(),
+            ))
+        }
+    };
+
+    let path = member.path();
+    let data = usize::try_from(member.offset)
+        .ok()
+        .zip(usize::try_from(member.stored_size).ok())
+        .and_then(|(start, len)| archive.get(start..start.checked_add(len)?))
+        .ok_or_else(|| CpkError::OutOfRange(path.clone()))?;
+    if !data.starts_with(MAGIC_CRILAYLA) {
+        return Ok(data.to_vec());
+    }
+    decompress(data, member.extract_size).ok_or(CpkError::BadCompression(path))
+}
+
+/// Reads the `@UTF` packet of the chunk at `offset`: a 4-byte magic, 4 unused bytes, the
+/// packet size as a little-endian `u64`, then the packet, which may be XOR-masked.
+fn read_packet(archive: &[u8], offset: usize, table: &'static str) -> Result<Vec<u8>, CpkError> {
+    let bad = || CpkError::BadTable(table);
+    let size = archive
+        .get(offset + 8..offset + 16)
+        .and_then(|b| b.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or_else(bad)?;
+    let size = usize::try_from(size).map_err(|_| bad())?;
+    let start = offset + 0x10;
+    let mut packet = archive
+        .get(start..start.checked_add(size).ok_or_else(bad)?)
+        .ok_or_else(bad)?
+        .to_vec();
+    if !packet.starts_with(MAGIC_UTF) {
+        unmask(&mut packet);
+    }
+    Ok(packet)
+}
+
+/// Removes the XOR mask some archives apply to their `@UTF` packets.
+fn unmask(packet: &mut [u8]) {
+    let mut mask: u32 = 0x655f;
+    for byte in packet {
+        *byte ^= mask as u8;
+        mask = mask.wrapping_mul(0x4115);
+    }
+}
+
+/// A value of a `@UTF` table cell. Integers of every width are widened to `u64`.
+#[derive(Debug, Clone)]
+enum Cell {
+    Int(u64),
+    Str(String),
+    /// No value, or a float or data value, which none of the columns read here hold.
+    Other,
+}
+
+/// A `@UTF` table, the big-endian row format CPK archives store their headers and
+/// tables of contents in.
+struct UtfTable {
+    columns: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl UtfTable {
+    fn parse(packet: &[u8]) -> Option<UtfTable> {
+        if !packet.starts_with(MAGIC_UTF) {
+            return None;
+        }
+        let u16_at = |pos: usize| {
+            Some(u16::from_be_bytes(
+                packet.get(pos..pos + 2)?.try_into().ok()?,
+            ))
+        };
+        let u32_at = |pos: usize| {
+            Some(u32::from_be_bytes(
+                packet.get(pos..pos + 4)?.try_into().ok()?,
+            ))
+        };
+        // Offsets within the table count from just after its size field.
+        let rows_pos = 8 + u16_at(0x0a)? as usize;
+        let strings_pos = 8 + u32_at(0x0c)? as usize;
+        let column_count = u16_at(0x18)? as usize;
+        let row_length = u16_at(0x1a)? as usize;
+        let row_count = u32_at(0x1c)? as usize;
+        // Rows past the end of the packet cannot be read, so do not allocate for them.
+        if row_count > packet.len().saturating_sub(rows_pos) / row_length.max(1) {
+            return None;
+        }
+
+        let string_at = |offset: u32| {
+            let start = strings_pos.checked_add(offset as usize)?;
+            let bytes = packet.get(start..)?;
+            let end = bytes.iter().position(|&b| b == 0)?;
+            Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+        };
+        // Reads a value of type `typ` at `pos`, returning it and its size.
+        let value_at = |typ: u8, pos: usize| -> Option<(Cell, usize)> {
+            let bytes = |len: usize| packet.get(pos..pos + len);
+            Some(match typ {
+                0 | 1 => (Cell::Int(u64::from(*bytes(1)?.first()?)), 1),
+                2 | 3 => (Cell::Int(u64::from(u16_at(pos)?)), 2),
+                4 | 5 => (Cell::Int(u64::from(u32_at(pos)?)), 4),
+                6 | 7 => (Cell::Int(u64::from_be_bytes(bytes(8)?.try_into().ok()?)), 8),
+                8 => (Cell::Other, 4),
+                9 => (Cell::Other, 8),
+                0x0a => (Cell::Str(string_at(u32_at(pos)?)?), 4),
+                // A data cell is an offset and a size.
+                0x0b => (Cell::Other, 8),
+                _ => return None,
+            })
+        };
+
+        // Each column is a flag byte and a name; a constant value follows inline.
+        let mut columns = Vec::with_capacity(column_count);
+        let mut schema = Vec::with_capacity(column_count);
+        let mut pos = 0x20;
+        for _ in 0..column_count {
+            let flags = *packet.get(pos)?;
+            let name = string_at(u32_at(pos + 1)?)?;
+            pos += 5;
+            let typ = flags & 0x0f;
+            // Per-row values are read with the rows; `constant` is then unused.
+            let (per_row, constant) = match flags & 0xf0 {
+                0x10 => (false, Cell::Other),
+                0x30 => {
+                    let (value, len) = value_at(typ, pos)?;
+                    pos += len;
+                    (false, value)
+                }
+                0x50 => (true, Cell::Other),
+                _ => return None,
+            };
+            schema.push((typ, per_row, constant));
+            columns.push(name);
+        }
+
+        let mut rows = Vec::with_capacity(row_count);
+        for row in 0..row_count {
+            let mut pos = rows_pos.checked_add(row.checked_mul(row_length)?)?;
+            let mut cells = Vec::with_capacity(column_count);
+            for (typ, per_row, constant) in &schema {
+                if *per_row {
+                    let (value, len) = value_at(*typ, pos)?;
+                    pos += len;
+                    cells.push(value);
+                } else {
+                    cells.push(constant.clone());
+                }
+            }
+            rows.push(cells);
+        }
+        Some(UtfTable { columns, rows })
+    }
+
+    fn cell(&self, row: usize, column: &str) -> Option<&Cell> {
+        let index = self.columns.iter().position(|c| c == column)?;
+        self.rows.get(row)?.get(index)
+    }
+
+    fn int(&self, row: usize, column: &str) -> Option<u64> {
+        match self.cell(row, column)? {
+            Cell::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn string(&self, row: usize, column: &str) -> Option<&str> {
+        match self.cell(row, column)? {
+            Cell::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the bits of CRILAYLA data from its last byte backwards, each byte from its
+/// most significant bit.
+struct BackBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    pool: u8,
+    left: u32,
+}
+
+impl BackBits<'_> {
+    fn take(&mut self, count: u32) -> Option<usize> {
+        let mut out = 0;
+        let mut produced = 0;
+        while produced < count {
+            if self.left == 0 {
+                self.pos = self.pos.checked_sub(1)?;
+                self.pool = self.data[self.pos];
+                self.left = 8;
+            }
+            let round = self.left.min(count - produced);
+            let bits = (self.pool >> (self.left - round)) as usize & ((1 << round) - 1);
+            out = (out << round) | bits;
+            self.left -= round;
+            produced += round;
+        }
+        Some(out)
+    }
+}
+
+/// Decompresses CRILAYLA data: the `CRILAYLA` magic, the size of the compressed output
+/// and the length of the compressed data as little-endian `u32`s, the compressed data,
+/// then the first [`CRILAYLA_PREFIX`] bytes of the output as is. The output is rebuilt
+/// from its end, each step either a literal byte or a copy of at least 3 bytes from
+/// up to 8194 bytes further on. Data whose declared output size is not `extract_size`
+/// is rejected before anything is allocated.
+fn decompress(data: &[u8], extract_size: u64) -> Option<Vec<u8>> {
+    let u32_at = |pos: usize| Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?));
+    let size = u32_at(8)? as usize;
+    if CRILAYLA_PREFIX.checked_add(size)? as u64 != extract_size {
+        return None;
+    }
+    let compressed = u32_at(12)? as usize;
+    let bits_end = 0x10usize.checked_add(compressed)?;
+    let prefix = data.get(bits_end..bits_end.checked_add(CRILAYLA_PREFIX)?)?;
+
+    let mut out = vec![0; CRILAYLA_PREFIX.checked_add(size)?];
+    out[..CRILAYLA_PREFIX].copy_from_slice(prefix);
+    let mut bits = BackBits {
+        data: data.get(0x10..bits_end)?,
+        pos: compressed,
+        pool: 0,
+        left: 0,
+    };
+    // Bytes below `next` are still to be written.
+    let mut next = out.len();
+    while next > CRILAYLA_PREFIX {
+        if bits.take(1)? == 0 {
+            next -= 1;
+            out[next] = bits.take(8)? as u8;
+            continue;
+        }
+        let distance = bits.take(13)? + 3;
+        let mut length = 3;
+        let mut extended = true;
+        for width in [2, 3, 5, 8] {
+            let part = bits.take(width)?;
+            length += part;
+            if part != (1 << width) - 1 {
+                extended = false;
+                break;
+            }
+        }
+        while extended {
+            let part = bits.take(8)?;
+            length += part;
+            extended = part == 0xff;
+        }
+        if length > next - CRILAYLA_PREFIX {
+            return None;
+        }
+        for _ in 0..length {
+            next -= 1;
+            out[next] = *out.get(next + distance)?;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a `@UTF` packet whose columns are all stored per row, typed after the
+    /// first row: `Cell::Int` as a `u64`, `Cell::Str` as a string.
+    fn utf_table(columns: &[&str], rows: &[Vec<Cell>]) -> Vec<u8> {
+        let mut strings = b"table\0".to_vec();
+        let mut add_string = |s: &str| {
+            let offset = strings.len() as u32;
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let mut schema = Vec::new();
+        for (column, cell) in columns.iter().zip(&rows[0]) {
+            let flags = match cell {
+                Cell::Int(_) => 0x56u8,
+                _ => 0x5a,
+            };
+            schema.push(flags);
+            schema.extend_from_slice(&add_string(column).to_be_bytes());
+        }
+        let mut row_bytes = Vec::new();
+        for row in rows {
+            for cell in row {
+                match cell {
+                    Cell::Int(n) => row_bytes.extend_from_slice(&n.to_be_bytes()),
+                    Cell::Str(s) => row_bytes.extend_from_slice(&add_string(s).to_be_bytes()),
+                    Cell::Other => unreachable!(),
+                }
+            }
+        }
+        let row_length = row_bytes.len() / rows.len();
+        let rows_pos = 0x20 + schema.len();
+        let strings_pos = rows_pos + row_bytes.len();
+        let end = strings_pos + strings.len();
+
+        let mut packet = MAGIC_UTF.to_vec();
+        packet.extend_from_slice(&(end as u32 - 8).to_be_bytes());
+        packet.extend_from_slice(&[0, 1]);
+        packet.extend_from_slice(&(rows_pos as u16 - 8).to_be_bytes());
+        packet.extend_from_slice(&(strings_pos as u32 - 8).to_be_bytes());
+        packet.extend_from_slice(&(end as u32 - 8).to_be_bytes());
+        packet.extend_from_slice(&0u32.to_be_bytes());
+        packet.extend_from_slice(&(columns.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&(row_length as u16).to_be_bytes());
+        packet.extend_from_slice(&(rows.len() as u32).to_be_bytes());
+        packet.extend(schema);
+        packet.extend(row_bytes);
+        packet.extend(strings);
+        packet
+    }
+
+    /// Writes bits most significant first, as `BackBits` reads them once the bytes are
+    /// reversed.
+    #[derive(Default)]
+    struct Bits {
+        bytes: Vec<u8>,
+        used: u32,
+    }
+
+    impl Bits {
+        fn put(&mut self, value: usize, count: u32) {
+            for bit in (0..count).rev() {
+                if self.used.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                let last = self.bytes.last_mut().unwrap();
+                *last |= (((value >> bit) & 1) as u8) << (7 - self.used % 8);
+                self.used += 1;
+            }
+        }
+    }
+
+    const PAYLOAD: &[u8] = b"xyzxyzxyzxyz";
+
+    fn prefix() -> Vec<u8> {
+        (0..CRILAYLA_PREFIX).map(|i| i as u8).collect()
+    }
+
+    /// CRILAYLA data for `prefix()` followed by `PAYLOAD`: three literals for the last
+    /// `zyx`, then one 9-byte copy from 3 bytes further on.
+    fn crilayla() -> Vec<u8> {
+        let mut bits = Bits::default();
+        for &byte in PAYLOAD.iter().rev().take(3) {
+            bits.put(0, 1);
+            bits.put(byte as usize, 8);
+        }
+        bits.put(1, 1);
+        bits.put(0, 13);
+        bits.put(3, 2);
+        bits.put(3, 3);
+        let compressed = bits.bytes.into_iter().rev().collect::<Vec<_>>();
+
+        let mut data = MAGIC_CRILAYLA.to_vec();
+        data.extend_from_slice(&(PAYLOAD.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        data.extend(compressed);
+        data.extend(prefix());
+        data
+    }
+
+    fn expanded() -> Vec<u8> {
+        [prefix(), PAYLOAD.to_vec()].concat()
+    }
+
+    /// Pads `bytes` with zeros to a multiple of 0x10.
+    fn pad(bytes: &mut Vec<u8>) {
+        bytes.resize(bytes.len().next_multiple_of(0x10), 0);
+    }
+
+    fn chunk(magic: &[u8; 4], packet: &[u8]) -> Vec<u8> {
+        let mut chunk = magic.to_vec();
+        chunk.extend_from_slice(&[0; 4]);
+        chunk.extend_from_slice(&(packet.len() as u64).to_le_bytes());
+        chunk.extend_from_slice(packet);
+        pad(&mut chunk);
+        chunk
+    }
+
+    /// An archive with a stored `data/stored.bin` and a CRILAYLA `packed.bin`, its TOC
+    /// packet masked.
+    fn archive() -> Vec<u8> {
+        let stored = b"plain member".to_vec();
+        let packed = crilayla();
+        let header = |toc: u64, content: u64| {
+            utf_table(
+                &["TocOffset", "ContentOffset"],
+                &[vec![Cell::Int(toc), Cell::Int(content)]],
+            )
+        };
+        let toc = |stored_at: u64, packed_at: u64| {
+            let row = |dir: &str, name: &str, offset, size, extract| {
+                vec![
+                    Cell::Str(dir.into()),
+                    Cell::Str(name.into()),
+                    Cell::Int(offset),
+                    Cell::Int(size),
+                    Cell::Int(extract),
+                ]
+            };
+            let mut packet = utf_table(
+                &[
+                    "DirName",
+                    "FileName",
+                    "FileOffset",
+                    "FileSize",
+                    "ExtractSize",
+                ],
+                &[
+                    row("data", "stored.bin", stored_at, stored.len() as u64, 12),
+                    row("", "packed.bin", packed_at, packed.len() as u64, 0x10c),
+                ],
+            );
+            unmask(&mut packet);
+            packet
+        };
+
+        // Packet sizes do not depend on the offsets, so lay out with placeholders first.
+        let toc_offset = chunk(MAGIC_CPK, &header(0, 0)).len();
+        let content = toc_offset + chunk(MAGIC_TOC, &toc(0, 0)).len();
+        let packed_at = (content + stored.len()).next_multiple_of(0x10);
+        let mut archive = chunk(MAGIC_CPK, &header(toc_offset as u64, content as u64));
+        archive.extend(chunk(
+            MAGIC_TOC,
+            &toc(
+                (content - toc_offset) as u64,
+                (packed_at - toc_offset) as u64,
+            ),
+        ));
+        archive.extend(stored);
+        pad(&mut archive);
+        archive.extend(packed);
+        archive
+    }
+
+    #[test]
+    fn utf_table_reads_ints_and_strings() {
+        let packet = utf_table(
+            &["Name", "Size"],
+            &[
+                vec![Cell::Str("a.bin".into()), Cell::Int(7)],
+                vec![Cell::Str("b.bin".into()), Cell::Int(u64::MAX)],
+            ],
+        );
+        let table = UtfTable::parse(&packet).unwrap();
+        assert_eq!(table.columns, ["Name", "Size"]);
+        assert_eq!(table.string(1, "Name"), Some("b.bin"));
+        assert_eq!(table.int(0, "Size"), Some(7));
+        assert_eq!(table.int(1, "Size"), Some(u64::MAX));
+        assert_eq!(table.int(2, "Size"), None);
+    }
+
+    #[test]
+    fn utf_table_rejects_rows_past_the_packet() {
+        let mut packet = utf_table(&["Size"], &[vec![Cell::Int(1)]]);
+        packet[0x1c..0x20].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(UtfTable::parse(&packet).is_none());
+    }
+
+    #[test]
+    fn unmask_undoes_itself() {
+        let packet = utf_table(&["Size"], &[vec![Cell::Int(1)]]);
+        let mut masked = packet.clone();
+        unmask(&mut masked);
+        assert!(!masked.starts_with(MAGIC_UTF));
+        unmask(&mut masked);
+        assert_eq!(masked, packet);
+    }
+
+    #[test]
+    fn decompress_rebuilds_literals_and_copies() {
+        assert_eq!(decompress(&crilayla(), 0x10c), Some(expanded()));
+    }
+
+    #[test]
+    fn decompress_rejects_a_size_other_than_the_extract_size() {
+        let mut data = crilayla();
+        data[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(decompress(&data, 0x10c), None);
+        assert_eq!(decompress(&crilayla(), 0x10b), None);
+    }
+
+    #[test]
+    fn read_member_reads_stored_and_compressed_members() {
+        let archive = archive();
+        let paths = members(&archive)
+            .unwrap()
+            .iter()
+            .map(Member::path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["data/stored.bin", "packed.bin"]);
+        assert_eq!(
+            read_member(&archive, "stored.bin").unwrap(),
+            b"plain member"
+        );
+        assert_eq!(
+            read_member(&archive, "/data/stored.bin").unwrap(),
+            b"plain member"
+        );
+        assert_eq!(read_member(&archive, "packed.bin").unwrap(), expanded());
+        assert!(matches!(
+            read_member(&archive, "missing.bin"),
+            Err(CpkError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,614 @@
+//! Text forms of a table and of the edits the command line reads: dumps, JSON and CSV
+//! exports, strings files, diffs, size edits and schemas.
+
+use std::collections::{HashMap, HashSet};
+
+use similar::TextDiff;
+
+use crate::{
+    path_key, read_i32, read_i64, Entry, ParsedT2b, PathKeys, StoredString, StringRegion,
+    ValueData, ValueLength, ValueType,
+};
+
+/// Names of value indices, shared per game so that dumps and exports document
+/// themselves.
+#[derive(Debug, Default)]
+pub struct Schema {
+    names: HashMap<usize, String>,
+}
+
+impl Schema {
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(&index).map(String::as_str)
+    }
+
+    /// The name of `index`, or the index itself when it has none.
+    pub fn label(&self, index: usize) -> String {
+        self.name(index)
+            .map_or_else(|| index.to_string(), str::to_string)
+    }
+
+    /// The lowest index named `name`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .filter(|(_, n)| *n == name)
+            .map(|(&i, _)| i)
+            .min()
+    }
+}
+
+/// Parses a schema: a TOML document of `index = "name"` pairs, keys being value
+/// indices and values their names.
+pub fn parse_schema(text: &str) -> Result<Schema, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let mut names = HashMap::new();
+    for (key, value) in table {
+        let index = key
+            .parse::<usize>()
+            .map_err(|_| format!("key {key:?} is not a value index"))?;
+        let toml::Value::String(name) = value else {
+            return Err(format!("index {index}: expected a quoted name"));
+        };
+        if names.insert(index, name).is_some() {
+            return Err(format!("index {index} is named twice"));
+        }
+    }
+    Ok(Schema { names })
+}
+
+/// Parses a flat JSON object mapping full paths to integer sizes. A path given twice
+/// keeps its last size.
+pub fn parse_size_edits(text: &str) -> Result<Vec<(String, i64)>, String> {
+    let edits: HashMap<String, i64> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    Ok(edits.into_iter().collect())
+}
+
+/// Splits RFC 4180 CSV text into rows of unquoted fields. Blank lines are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes())
+        .into_records()
+        .map(|row| {
+            row.map(|row| row.iter().map(str::to_string).collect())
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Reads `(path, size)` edits from the rows of a two-column `path,size` CSV. A first
+/// row whose size is not a number is taken as a header.
+pub fn parse_size_rows(rows: &[Vec<String>]) -> Result<Vec<(String, i64)>, String> {
+    let mut edits = Vec::with_capacity(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        let (path, size) = match row.as_slice() {
+            [path, size] => (path, size.trim()),
+            _ => return Err(format!("sizes row {}: expected 2 columns", i + 1)),
+        };
+        match size.parse::<i64>() {
+            Ok(size) => edits.push((path.clone(), size)),
+            Err(_) if i == 0 => {}
+            Err(_) => return Err(format!("sizes row {}: invalid size {size:?}", i + 1)),
+        }
+    }
+    Ok(edits)
+}
+
+/// Lists the value types of an entry for error messages, e.g. `[String, Integer]`.
+pub fn field_types(entry: &Entry) -> String {
+    let types = entry.values.iter().map(|v| v.typ).collect::<Vec<_>>();
+    format!("{types:?}")
+}
+
+/// Type and value `idx` of `entry`, or `(none)` when it has no such value.
+pub fn field_text(entry: &Entry, idx: usize) -> String {
+    match entry.values.get(idx) {
+        Some(field) => format!("{:?} {}", field.typ, value_text(entry, idx)),
+        None => "(none)".into(),
+    }
+}
+
+/// Value `idx` of `entry` as text: a quoted string, a number, or `null`.
+pub fn value_text(entry: &Entry, idx: usize) -> String {
+    if let Some(s) = entry.string(idx) {
+        format!("{s:?}")
+    } else if let Some(n) = entry.integer(idx) {
+        n.to_string()
+    } else if let Some(f) = entry.float(idx) {
+        format!("{f:?}")
+    } else {
+        "null".into()
+    }
+}
+
+/// A JSON string literal of `s`.
+pub fn json_string(s: &str) -> String {
+    serde_json::Value::from(s).to_string()
+}
+
+/// Renders a table for reading in a terminal: a summary line, then every entry's name
+/// followed by its values, each labelled with its `schema` name or index and its type.
+pub fn to_dump(parsed: &ParsedT2b, schema: &Schema) -> String {
+    let mut out = format!(
+        "value length: {:?} ({} bytes), encoding: {:?}, {:?} endian, {} entries\n",
+        parsed.value_length,
+        parsed.value_length as usize,
+        parsed.encoding,
+        parsed.endian,
+        parsed.entries.len()
+    );
+    for entry in &parsed.entries {
+        out.push_str(&format!("{}:\n", entry.name));
+        for (i, field) in entry.values.iter().enumerate() {
+            let typ = match field.typ {
+                ValueType::String => "Str",
+                ValueType::Integer => "Int",
+                ValueType::FloatingPoint => "Float",
+            };
+            let label = schema.label(i);
+            out.push_str(&format!("  [{label}] {typ} {}\n", value_text(entry, i)));
+        }
+    }
+    out
+}
+
+/// Renders every parsed entry as JSON, each value with its `schema` name if it has one.
+pub fn to_json(parsed: &ParsedT2b, schema: &Schema) -> String {
+    let mut out = String::new();
+    out.push_str("{\n");
+    out.push_str(&format!(
+        "  \"value_length\": \"{:?}\",\n",
+        parsed.value_length
+    ));
+    out.push_str(&format!("  \"endian\": \"{:?}\",\n", parsed.endian));
+    out.push_str(&format!("  \"encoding\": \"{:?}\",\n", parsed.encoding));
+    out.push_str("  \"entries\": [");
+    for (i, entry) in parsed.entries.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!(
+            "    {{\n      \"name\": {},\n      \"values\": [",
+            json_string(&entry.name)
+        ));
+        for (j, field) in entry.values.iter().enumerate() {
+            let data = if let Some(s) = entry.string(j) {
+                json_string(s)
+            } else if let Some(n) = entry.integer(j) {
+                n.to_string()
+            } else {
+                // JSON has no NaN/infinity; strings without data are null too.
+                entry
+                    .float(j)
+                    .filter(|f| f.is_finite())
+                    .map_or_else(|| "null".into(), |f| format!("{f:?}"))
+            };
+            let name = match schema.name(j) {
+                Some(name) => format!("\"name\": {}, ", json_string(name)),
+                None => String::new(),
+            };
+            out.push_str(if j == 0 { "\n" } else { ",\n" });
+            out.push_str(&format!(
+                "        {{ {name}\"type\": \"{:?}\", \"data\": {data}, \"offset\": {} }}",
+                field.typ, field.offset
+            ));
+        }
+        if !entry.values.is_empty() {
+            out.push_str("\n      ");
+        }
+        out.push_str("]\n    }");
+    }
+    if !parsed.entries.is_empty() {
+        out.push_str("\n  ");
+    }
+    out.push_str("]\n}\n");
+    out
+}
+
+/// Writes one CSV row per path row of `keys`: the full path followed by every value,
+/// under a header of `schema` names or `value<i>`. Returns the CSV and the row count.
+pub fn to_csv(
+    parsed: &ParsedT2b,
+    keys: &PathKeys,
+    schema: &Schema,
+) -> Result<(Vec<u8>, usize), String> {
+    let items = parsed
+        .cpk_items_with_keys(keys)
+        .filter_map(|e| path_key(e).map(|(prefix, suffix)| (keys.path(&prefix, &suffix), e)))
+        .collect::<Vec<_>>();
+    let columns = items.iter().map(|(_, e)| e.values.len()).max().unwrap_or(0);
+
+    let mut out = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::CRLF)
+        .flexible(true)
+        .from_writer(Vec::new());
+    let header = (0..columns).map(|i| match schema.name(i) {
+        Some(name) => name.to_string(),
+        None => format!("value{i}"),
+    });
+    let csv_error = |e: csv::Error| format!("write CSV: {e}");
+    out.write_record(std::iter::once("path".to_string()).chain(header))
+        .map_err(csv_error)?;
+    for (path, entry) in &items {
+        let values = (0..entry.values.len()).map(|i| {
+            entry
+                .string(i)
+                .map(str::to_string)
+                .or_else(|| entry.integer(i).map(|n| n.to_string()))
+                .or_else(|| entry.float(i).map(|f| f.to_string()))
+                .unwrap_or_default()
+        });
+        out.write_record(std::iter::once(path.clone()).chain(values))
+            .map_err(csv_error)?;
+    }
+    let out = out.into_inner().map_err(|e| format!("write CSV: {e}"))?;
+    Ok((out, items.len()))
+}
+
+/// A value that differs between two tables: its index and its [`field_text`] in A and
+/// in B.
+pub type FieldDiff = (usize, String, String);
+
+/// Differences between the path rows of two tables, matched by path.
+pub struct TableDiff {
+    /// Every path in both tables whose values differ, in A's order, with each differing
+    /// value.
+    pub changed: Vec<(String, Vec<FieldDiff>)>,
+    /// Paths only in A, in table order.
+    pub only_a: Vec<String>,
+    /// Paths only in B, in table order.
+    pub only_b: Vec<String>,
+}
+
+/// Compares the path rows of `a` and `b` value by value. A repeated path is compared
+/// by its first entry.
+pub fn diff_tables(a: &ParsedT2b, b: &ParsedT2b, keys: &PathKeys) -> TableDiff {
+    let items_a = items_by_path(a, keys);
+    let items_b = items_by_path(b, keys);
+    let index_b = items_b.iter().cloned().collect::<HashMap<_, _>>();
+    let index_a = items_a.iter().cloned().collect::<HashMap<_, _>>();
+
+    let mut changed = Vec::new();
+    for (path, entry_a) in &items_a {
+        let Some(entry_b) = index_b.get(path) else {
+            continue;
+        };
+        let count = entry_a.values.len().max(entry_b.values.len());
+        let diffs = (0..count)
+            .filter(|&i| match (entry_a.values.get(i), entry_b.values.get(i)) {
+                (Some(a), Some(b)) => {
+                    a.typ != b.typ || value_text(entry_a, i) != value_text(entry_b, i)
+                }
+                _ => true,
+            })
+            .map(|i| (i, field_text(entry_a, i), field_text(entry_b, i)))
+            .collect::<Vec<_>>();
+        if !diffs.is_empty() {
+            changed.push((path.clone(), diffs));
+        }
+    }
+
+    let only = |items: &[(String, &Entry)], other: &HashMap<String, &Entry>| {
+        items
+            .iter()
+            .filter(|(path, _)| !other.contains_key(path))
+            .map(|(path, _)| path.clone())
+            .collect()
+    };
+    TableDiff {
+        changed,
+        only_a: only(&items_a, &index_b),
+        only_b: only(&items_b, &index_a),
+    }
+}
+
+/// Path rows with their full paths, in table order, keeping the first entry for a
+/// repeated path.
+fn items_by_path<'a>(parsed: &'a ParsedT2b, keys: &'a PathKeys) -> Vec<(String, &'a Entry)> {
+    let mut seen = HashSet::new();
+    parsed
+        .cpk_items_with_keys(keys)
+        .filter_map(|e| path_key(e).map(|(prefix, suffix)| (keys.path(&prefix, &suffix), e)))
+        .filter(|(path, _)| seen.insert(path.clone()))
+        .collect()
+}
+
+/// Groups the lines that differ between `a` and `b` into unified diff hunks with
+/// `context` common lines around them, merging hunks whose context would overlap.
+pub fn unified_hunks(a: &str, b: &str, context: usize) -> Vec<String> {
+    TextDiff::from_lines(a, b)
+        .unified_diff()
+        .context_radius(context)
+        .iter_hunks()
+        .map(|hunk| hunk.to_string())
+        .collect()
+}
+
+/// The key a strings file gives `string`: `value:0x<offset>` or `name:0x<offset>`.
+fn string_key(string: &StoredString) -> String {
+    let region = match string.region {
+        StringRegion::Values => "value",
+        StringRegion::Names => "name",
+    };
+    format!("{region}:0x{:04x}", string.offset)
+}
+
+/// Formats stored strings as a strings file: one line per string with its key, a tab,
+/// and the string with [`escape_line`] applied.
+pub fn strings_file(strings: &[StoredString]) -> String {
+    let mut out = String::new();
+    for string in strings {
+        let text = escape_line(&string.text);
+        out.push_str(&format!("{}\t{text}\n", string_key(string)));
+    }
+    out
+}
+
+/// The strings a strings file changes, read by [`parse_strings_file`].
+#[derive(Debug, Default)]
+pub struct StringEdits {
+    /// New text of the changed value strings, by offset in the string data.
+    pub values: HashMap<usize, String>,
+    /// New names, by old name.
+    pub names: HashMap<String, String>,
+    /// Line number and new text of every changed string.
+    pub changed: Vec<(usize, String)>,
+}
+
+/// Reads a strings file, which must list `stored` in the same order, and returns the
+/// strings it changes.
+pub fn parse_strings_file(text: &str, stored: &[StoredString]) -> Result<StringEdits, String> {
+    let lines = text.lines().collect::<Vec<_>>();
+    if lines.len() != stored.len() {
+        return Err(format!(
+            "strings file has {} lines, but the table stores {} strings",
+            lines.len(),
+            stored.len()
+        ));
+    }
+
+    let mut edits = StringEdits::default();
+    for (i, (line, string)) in lines.iter().zip(stored).enumerate() {
+        let line_err = |msg: String| format!("strings line {}: {msg}", i + 1);
+        let expected = string_key(string);
+        let Some((key, text)) = line.split_once('\t') else {
+            return Err(line_err(format!(
+                "expected `{expected}`, a tab and the string"
+            )));
+        };
+        if key != expected {
+            return Err(line_err(format!("expected `{expected}`, found `{key}`")));
+        }
+        let text = unescape_line(text).map_err(line_err)?;
+        if text == string.text {
+            continue;
+        }
+        edits.changed.push((i + 1, text.clone()));
+        match string.region {
+            StringRegion::Values => edits.values.insert(string.offset, text),
+            StringRegion::Names => edits.names.insert(string.text.clone(), text),
+        };
+    }
+    Ok(edits)
+}
+
+/// Puts `edits` into the entries of `parsed`: changed value strings replace every
+/// string value that points to them, and changed names rename every entry with that
+/// name. The table needs [`crate::serialize`] afterwards.
+pub fn apply_string_edits(parsed: &mut ParsedT2b, edits: &StringEdits) {
+    let (value_length, endian) = (parsed.value_length, parsed.endian);
+    let bytes = &parsed.bytes;
+    for entry in &mut parsed.entries {
+        if let Some(name) = edits.names.get(&entry.name) {
+            entry.name = name.clone();
+        }
+        for field in &mut entry.values {
+            if field.typ != ValueType::String {
+                continue;
+            }
+            let raw = match value_length {
+                ValueLength::Int => read_i32(bytes, field.offset, endian).map(i64::from),
+                ValueLength::Long => read_i64(bytes, field.offset, endian),
+            };
+            let text = raw
+                .and_then(|raw| usize::try_from(raw).ok())
+                .and_then(|offset| edits.values.get(&offset));
+            if let Some(text) = text {
+                field.data = ValueData::Str(Some(text.clone()));
+            }
+        }
+    }
+}
+
+/// Reverses [`escape_line`]; fails on any other escape.
+fn unescape_line(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => return Err(format!("unknown escape `\\{c}`")),
+            None => return Err("line ends with a lone `\\`".into()),
+        }
+    }
+    Ok(out)
+}
+
+/// Escapes backslashes, tabs and line breaks as `\\`, `\t`, `\n` and `\r` so that a
+/// string fits on one line.
+fn escape_line(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_schema_reads_toml_index_names() {
+        let schema = parse_schema(
+            "# Layout\n0 = \"dir\"\n\"1\" = 'file' # quoted key\n4 = \"size\\u0021\"\n",
+        )
+        .unwrap();
+        assert_eq!(schema.name(0), Some("dir"));
+        assert_eq!(schema.name(1), Some("file"));
+        assert_eq!(schema.label(4), "size!");
+        assert_eq!(schema.label(2), "2");
+        assert_eq!(schema.index_of("file"), Some(1));
+    }
+
+    #[test]
+    fn parse_schema_rejects_non_index_keys_and_names() {
+        assert!(parse_schema("size = \"4\"").is_err());
+        assert!(parse_schema("4 = 4").is_err());
+        assert!(parse_schema("4 = \"size\"\n4 = \"other\"").is_err());
+        // Different TOML keys, same index.
+        assert!(parse_schema("4 = \"size\"\n04 = \"other\"").is_err());
+        assert!(parse_schema("4 = \"size").is_err());
+    }
+
+    #[test]
+    fn parse_size_edits_decodes_escapes_and_keeps_the_last_duplicate() {
+        let mut edits = parse_size_edits(
+            r#"{ "data/a\\b.bin": 1, "data/\u00e9\ud83d\ude00.bin": 2,
+                 "data/\"q\"\n": -1, "data/a\\b.bin": 3 }"#,
+        )
+        .unwrap();
+        edits.sort();
+        assert_eq!(
+            edits,
+            [
+                ("data/\"q\"\n".to_string(), -1),
+                ("data/a\\b.bin".to_string(), 3),
+                ("data/\u{e9}\u{1f600}.bin".to_string(), 2),
+            ]
+        );
+        assert_eq!(parse_size_edits("{}").unwrap(), []);
+    }
+
+    #[test]
+    fn parse_size_edits_rejects_nesting_and_non_integer_sizes() {
+        assert!(parse_size_edits(r#"{ "a": { "b": 1 } }"#).is_err());
+        assert!(parse_size_edits(r#"{ "a": [1] }"#).is_err());
+        assert!(parse_size_edits(r#"{ "a": 1.5 }"#).is_err());
+        assert!(parse_size_edits(r#"{ "a": "1" }"#).is_err());
+        assert!(parse_size_edits(r#"[["a", 1]]"#).is_err());
+        // A lone high surrogate is not a character.
+        assert!(parse_size_edits(r#"{ "\ud83d": 1 }"#).is_err());
+        assert!(parse_size_edits(r#"{ "a": 1 } x"#).is_err());
+    }
+
+    #[test]
+    fn parse_csv_unquotes_fields_across_lines_and_crlf() {
+        let text = "path,size\r\n\"a,\"\"b\"\"\",1\r\n\r\n\"two\nlines\",2\nc,3";
+        assert_eq!(
+            parse_csv(text).unwrap(),
+            [
+                vec!["path", "size"],
+                vec!["a,\"b\"", "1"],
+                vec!["two\nlines", "2"],
+                vec!["c", "3"],
+            ]
+        );
+        // Rows may have any number of fields; parse_size_rows checks for two.
+        assert_eq!(
+            parse_csv("a\nb,c,d\n").unwrap(),
+            [vec!["a"], vec!["b", "c", "d"]]
+        );
+    }
+
+    /// Lines `1` to `n`, each ending in a newline, with `edit` applied to the list.
+    fn numbered(n: usize, edit: impl FnOnce(&mut Vec<String>)) -> String {
+        let mut lines = (1..=n).map(|i| i.to_string()).collect::<Vec<_>>();
+        edit(&mut lines);
+        lines.iter().map(|l| format!("{l}\n")).collect()
+    }
+
+    #[test]
+    fn unified_hunks_is_empty_for_identical_input() {
+        let text = numbered(10, |_| {});
+        assert!(unified_hunks(&text, &text, 3).is_empty());
+        assert!(unified_hunks("", "", 3).is_empty());
+    }
+
+    #[test]
+    fn unified_hunks_reports_pure_insertions_and_deletions() {
+        let a = numbered(10, |_| {});
+        let b = numbered(10, |l| l.insert(5, "new".into()));
+        assert_eq!(
+            unified_hunks(&a, &b, 3),
+            ["@@ -3,6 +3,7 @@\n 3\n 4\n 5\n+new\n 6\n 7\n 8\n"]
+        );
+        assert_eq!(
+            unified_hunks(&b, &a, 3),
+            ["@@ -3,7 +3,6 @@\n 3\n 4\n 5\n-new\n 6\n 7\n 8\n"]
+        );
+        // Against nothing, a range starts at the line before it, 0.
+        assert_eq!(unified_hunks("", "x\n", 3), ["@@ -0,0 +1 @@\n+x\n"]);
+        assert_eq!(unified_hunks("x\n", "", 3), ["@@ -1 +0,0 @@\n-x\n"]);
+    }
+
+    #[test]
+    fn unified_hunks_merges_changes_whose_context_overlaps() {
+        let a = numbered(20, |_| {});
+        // Six common lines between the changes: the contexts touch, one hunk.
+        let b = numbered(20, |l| {
+            l[4] = "five".into();
+            l[11] = "twelve".into();
+        });
+        let hunks = unified_hunks(&a, &b, 3);
+        assert_eq!(hunks.len(), 1);
+        assert!(hunks[0].starts_with("@@ -2,14 +2,14 @@\n"));
+        // Seven common lines between them: two hunks.
+        let b = numbered(20, |l| {
+            l[4] = "five".into();
+            l[12] = "thirteen".into();
+        });
+        let hunks = unified_hunks(&a, &b, 3);
+        assert_eq!(
+            hunks,
+            [
+                "@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n",
+                "@@ -10,7 +10,7 @@\n 10\n 11\n 12\n-13\n+thirteen\n 14\n 15\n 16\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn unified_hunks_clips_context_at_the_start_and_end() {
+        let a = numbered(10, |_| {});
+        let b = numbered(10, |l| {
+            l[0] = "one".into();
+            l[9] = "ten".into();
+        });
+        assert_eq!(
+            unified_hunks(&a, &b, 3),
+            [
+                "@@ -1,4 +1,4 @@\n-1\n+one\n 2\n 3\n 4\n",
+                "@@ -7,4 +7,4 @@\n 7\n 8\n 9\n-10\n+ten\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_string_escapes_quotes_and_control_characters() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}é"), r#""a\"b\\c\n\u0001é""#);
+    }
+}
@@ -0,0 +1,2913 @@
+//! Reader and writer for LEVEL5 T2B tables (`*.cfg.bin`), such as `cpk_list.cfg.bin`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use encoding_rs::SHIFT_JIS;
+
+#[cfg(feature = "cpk")]
+pub mod cpk;
+pub mod formats;
+pub mod sync;
+
+const MAGIC_T2B: u32 = 0x6232_7401;
+
+/// Footer layout of a T2B revision, identified by the magic at the start of the footer.
+struct FooterFormat {
+    magic: u32,
+    /// Offset of the `i16` string encoding marker within the footer.
+    encoding_offset: usize,
+}
+
+/// Known footer layouts; a new revision is supported by adding its magic and offsets.
+const FOOTER_FORMATS: &[FooterFormat] = &[FooterFormat {
+    magic: MAGIC_T2B,
+    encoding_offset: 6,
+}];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    String = 0,
+    Integer = 1,
+    FloatingPoint = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueLength {
+    Int = 4,
+    Long = 8,
+}
+
+/// Byte order of every multi-byte field, detected from the footer magic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub fn u32_bytes(self, v: u32) -> [u8; 4] {
+        match self {
+            Endian::Little => v.to_le_bytes(),
+            Endian::Big => v.to_be_bytes(),
+        }
+    }
+
+    pub fn i32_bytes(self, v: i32) -> [u8; 4] {
+        self.u32_bytes(v as u32)
+    }
+
+    pub fn i64_bytes(self, v: i64) -> [u8; 8] {
+        match self {
+            Endian::Little => v.to_le_bytes(),
+            Endian::Big => v.to_be_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StringEncoding {
+    Sjis,
+    Utf8,
+    /// Little-endian UTF-16; strings end with a NUL code unit. String offsets and
+    /// lengths still count bytes.
+    Utf16Le,
+}
+
+impl StringEncoding {
+    /// Whether `s` can be stored in this encoding; Shift-JIS lacks many characters.
+    pub fn can_encode(self, s: &str) -> bool {
+        encode_string(s, self).is_ok()
+    }
+
+    /// The NUL terminator that ends each string.
+    fn terminator(self) -> &'static [u8] {
+        match self {
+            StringEncoding::Utf16Le => &[0, 0],
+            StringEncoding::Sjis | StringEncoding::Utf8 => &[0],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ValueData {
+    Str(Option<String>),
+    Int(i64),
+    Float(f64),
+}
+
+/// A size to write into a numeric field, in the type it was read as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    Int(i64),
+    Float(f64),
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Size::Int(n) => write!(f, "{n}"),
+            Size::Float(x) => write!(f, "{x:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueField {
+    pub typ: ValueType,
+    pub data: ValueData,
+    /// Absolute byte offset of the value in the table.
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    /// CRC32 of the name as stored in the entry, which identifies it on disk.
+    pub crc32: u32,
+    pub values: Vec<ValueField>,
+}
+
+impl Entry {
+    /// The value at `idx` if it is an integer.
+    pub fn integer(&self, idx: usize) -> Option<i64> {
+        match self.values.get(idx)?.data {
+            ValueData::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// The value at `idx` if it is a string with a resolvable offset.
+    pub fn string(&self, idx: usize) -> Option<&str> {
+        match &self.values.get(idx)?.data {
+            ValueData::Str(Some(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value at `idx` if it is a floating-point number.
+    pub fn float(&self, idx: usize) -> Option<f64> {
+        match self.values.get(idx)?.data {
+            ValueData::Float(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// The value at `idx` as a size: an integer, a float, or a string holding an
+    /// integer, optionally in `"` quotes.
+    pub fn size(&self, idx: usize) -> Option<Size> {
+        self.integer(idx)
+            .map(Size::Int)
+            .or_else(|| self.float(idx).map(Size::Float))
+            .or_else(|| {
+                let text = self.string(idx)?;
+                text.trim_matches('"').parse::<i64>().ok().map(Size::Int)
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedT2b {
+    /// The table exactly as it was parsed.
+    pub bytes: Vec<u8>,
+    pub value_length: ValueLength,
+    pub endian: Endian,
+    pub encoding: StringEncoding,
+    pub entries: Vec<Entry>,
+    /// Stored checksum for every entry name, as read from the checksum section.
+    pub name_crcs: HashMap<String, u32>,
+    /// Sorted CRC32s of entries whose name could not be resolved; only lenient
+    /// parsing keeps such entries, named `crc_0x…`.
+    pub unresolved_names: Vec<u32>,
+    /// Where the entry section stopped parsing, when recovery kept only the entries
+    /// before it; see [`ParseOptions::recover`].
+    pub recovery: Option<Recovery>,
+    /// Why the checksum section was ignored, when [`ParseOptions::force_parse`] ignored
+    /// it; [`serialize`] then rebuilds it from the entries.
+    pub checksum_error: Option<String>,
+    /// Alignments the table pads to, which [`serialize`] reproduces.
+    pub alignment: Alignment,
+    /// Name offset of the first checksum entry; all other offsets are relative to it.
+    name_offset_base: u32,
+    /// Names of the checksum entries in stored order.
+    checksum_names: Vec<String>,
+    /// The checksum section as stored, reused verbatim while the entry names match
+    /// `checksum_names`; empty once [`ParsedT2b::set_encoding`] changed the encoding.
+    checksum_section: Vec<u8>,
+    /// Byte used to fill alignment gaps.
+    padding: u8,
+    /// Bytes between sections as stored; see [`OpaqueRegions`].
+    opaque: OpaqueRegions,
+    footer: Vec<u8>,
+    /// End of the last entry's values in `bytes`.
+    entries_end: usize,
+}
+
+/// The first entry a recovering parse could not read. `entries` holds those before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// Index of the entry, which is also the number of entries recovered.
+    pub entry: usize,
+    /// Entry count the header declares.
+    pub declared: usize,
+    /// Absolute offset where the entry starts.
+    pub offset: usize,
+    pub reason: String,
+}
+
+/// Alignment of the values within an entry and of the sections, inferred from the
+/// table's padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alignment {
+    /// Alignment of each entry's values after its type bytes.
+    pub values: usize,
+    /// Alignment of the string data, the checksum section and the checksum names, each
+    /// padded from the end of the section before.
+    pub sections: usize,
+    /// Whether the stored gaps between sections are exactly the padding to `sections`.
+    /// When they fit no power of two, `sections` is the default.
+    pub regular: bool,
+}
+
+impl Default for Alignment {
+    /// The alignments of every known table.
+    fn default() -> Self {
+        Alignment {
+            values: 4,
+            sections: 0x10,
+            regular: true,
+        }
+    }
+}
+
+/// Value alignments to try, in order, when the entries do not parse with the default.
+const VALUE_ALIGNMENTS: [usize; 4] = [4, 8, 2, 1];
+
+/// Section alignments to try, in order, when looking for the checksum section.
+const SECTION_ALIGNMENTS: [usize; 5] = [0x10, 8, 4, 2, 1];
+
+/// Where the checksum section starts: the end of the string data padded to the first
+/// section alignment whose checksum header and sections fit before the footer, or to
+/// 0x10 if none does.
+fn checksum_position(bytes: &[u8], string_data_end: usize, endian: Endian) -> usize {
+    let footer_pos = bytes.len().saturating_sub(0x10);
+    // Fields past the end read as `usize::MAX` so that they fail the bounds check.
+    let read = |pos: usize| read_u32(bytes, pos, endian).map_or(usize::MAX, |v| v as usize);
+    let fits = |pos: usize| {
+        let header_end = pos + 0x10;
+        let entries_end = read(pos + 4).saturating_mul(8).saturating_add(header_end);
+        let names_end = read(pos + 8)
+            .saturating_add(pos)
+            .saturating_add(read(pos + 12));
+        header_end <= footer_pos && entries_end <= footer_pos && names_end <= footer_pos
+    };
+    SECTION_ALIGNMENTS
+        .iter()
+        .map(|&align| align_up(string_data_end, align))
+        .find(|&pos| fits(pos))
+        .unwrap_or_else(|| align_up(string_data_end, 0x10))
+}
+
+/// Bytes between the sections that the parser does not interpret. They are usually
+/// filler, but are kept verbatim so that [`serialize`] can re-emit them in case a tool
+/// stored something there.
+#[derive(Debug, Clone, Default)]
+struct OpaqueRegions {
+    /// The alignment gap from the end of the entries to the string data.
+    after_entries: Vec<u8>,
+    /// The alignment gap from the end of the string data to the checksum section.
+    after_strings: Vec<u8>,
+    /// Whatever lies between the end of the checksum names, aligned to 0x10, and the
+    /// footer.
+    after_checksum: Vec<u8>,
+}
+
+/// Error returned when a table cannot be parsed or serialized.
+#[derive(Debug)]
+pub enum T2bError {
+    FileTooSmall,
+    /// The footer does not start with a known T2B magic in either byte order.
+    BadMagic(u32),
+    UnknownEncoding(i16),
+    /// A section extends past the end of the table: it needs `needed` bytes in total,
+    /// the table has `have`.
+    OutOfRange {
+        section: &'static str,
+        needed: usize,
+        have: usize,
+    },
+    /// The entry header at the start of the table contradicts itself or the table size.
+    BadHeader {
+        entry_count: usize,
+        string_data_offset: usize,
+        string_data_length: usize,
+        table_len: usize,
+    },
+    /// A section declared by the headers runs into the footer, which usually means the
+    /// file was cut short.
+    Truncated {
+        section: &'static str,
+        /// Where the section ends.
+        end: usize,
+        footer_pos: usize,
+    },
+    /// Neither value length yields a consistent entry section.
+    UndetectedValueLength,
+    /// The entry section is inconsistent with the value length forced through
+    /// [`ParseOptions`].
+    WrongValueLength(ValueLength),
+    BadEntries,
+    /// Entry `entry`, starting at `offset`, declares more values than fit before the
+    /// string data even at 4-byte values.
+    BadValueCount {
+        entry: usize,
+        offset: usize,
+        count: u8,
+    },
+    NoChecksumEntries,
+    /// An entry's CRC32 has no entry in the checksum section.
+    MissingNameOffset(u32),
+    /// The name for a CRC32 could not be read from the checksum strings.
+    BadName(u32),
+    /// A value's data does not match its declared type.
+    TypeMismatch {
+        entry: String,
+    },
+    /// A value does not fit in the table's value length.
+    ValueOverflow {
+        entry: String,
+        value: i64,
+    },
+    /// An entry has no value at the index a write targets.
+    MissingValue {
+        entry: String,
+        index: usize,
+    },
+    /// A string is not valid in the table's encoding.
+    BadString {
+        offset: usize,
+    },
+    /// More than 255 values in one entry.
+    TooManyValues {
+        entry: String,
+    },
+    /// A string cannot be represented in the table's encoding.
+    Unencodable(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for T2bError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            T2bError::FileTooSmall => f.write_str("file too small"),
+            T2bError::BadMagic(magic) => write!(f, "invalid magic 0x{magic:08x}"),
+            T2bError::UnknownEncoding(raw) => write!(f, "unknown encoding {raw}"),
+            T2bError::OutOfRange { section, .. } => write!(f, "{section} out of range"),
+            T2bError::BadHeader {
+                entry_count,
+                string_data_offset,
+                string_data_length,
+                table_len,
+            } => write!(
+                f,
+                "invalid entry header: {entry_count} entries with string data at \
+                 0x{string_data_offset:x} ({string_data_length} bytes) in a {table_len}-byte table"
+            ),
+            T2bError::Truncated {
+                section,
+                end,
+                footer_pos,
+            } => write!(
+                f,
+                "file truncated at section {section}: it reaches 0x{end:x}, but the footer \
+                 starts at 0x{footer_pos:x}"
+            ),
+            T2bError::UndetectedValueLength => f.write_str("failed to detect value length"),
+            T2bError::WrongValueLength(len) => write!(
+                f,
+                "entries do not parse with forced value length {len:?} ({} bytes)",
+                *len as usize
+            ),
+            T2bError::BadEntries => f.write_str("failed to parse entries"),
+            T2bError::BadValueCount {
+                entry,
+                offset,
+                count,
+            } => write!(
+                f,
+                "entry {entry} at 0x{offset:x} declares {count} values, more than fit before \
+                 the string data"
+            ),
+            T2bError::NoChecksumEntries => f.write_str("no checksum entries"),
+            T2bError::MissingNameOffset(_) => f.write_str("missing name offset"),
+            T2bError::BadName(_) => f.write_str("name read failed"),
+            T2bError::TypeMismatch { entry } => write!(f, "value type mismatch in {entry}"),
+            T2bError::ValueOverflow { entry, value } => {
+                write!(f, "value {value} does not fit in {entry}")
+            }
+            T2bError::MissingValue { entry, index } => write!(f, "{entry} has no value {index}"),
+            T2bError::TooManyValues { entry } => write!(f, "too many values in {entry}"),
+            T2bError::BadString { offset } => {
+                write!(
+                    f,
+                    "string at 0x{offset:x} is not valid in the table's encoding"
+                )
+            }
+            T2bError::Unencodable(s) => write!(f, "string not representable in Shift-JIS: {s}"),
+            T2bError::Io(err) => write!(f, "read file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for T2bError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            T2bError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for T2bError {
+    fn from(err: std::io::Error) -> Self {
+        T2bError::Io(err)
+    }
+}
+
+/// Shorthand for a section that does not fit in the table.
+fn out_of_range(section: &'static str, needed: usize, have: usize) -> T2bError {
+    T2bError::OutOfRange {
+        section,
+        needed,
+        have,
+    }
+}
+
+/// Walks the sections the entry and checksum headers declare, in file order, and
+/// fails with [`T2bError::Truncated`] at the first one that does not end before the
+/// footer. Runs before any section is read, so a cut-short file gets one clear error
+/// instead of whichever read happens to fail first.
+fn check_sections(bytes: &[u8], endian: Endian) -> Result<(), T2bError> {
+    let footer_pos = bytes.len() - 0x10;
+    let truncated = |section, end| T2bError::Truncated {
+        section,
+        end,
+        footer_pos,
+    };
+    // Fields past the end read as `usize::MAX` so that they fail the bounds check.
+    let read = |pos: usize| read_u32(bytes, pos, endian).map_or(usize::MAX, |v| v as usize);
+
+    let string_data_offset = read(4);
+    if string_data_offset < 0x10 {
+        // Not a cut-short file but a broken header, which the header check reports.
+        return Ok(());
+    }
+    let string_data_end = string_data_offset.saturating_add(read(8));
+    if string_data_end > footer_pos {
+        return Err(truncated("string data", string_data_end));
+    }
+    let checksum_pos = checksum_position(bytes, string_data_end, endian);
+    let checksum_header_end = checksum_pos + 0x10;
+    if checksum_header_end > footer_pos {
+        return Err(truncated("checksum header", checksum_header_end));
+    }
+    let checksum_entries_end = read(checksum_pos + 4)
+        .saturating_mul(8)
+        .saturating_add(checksum_header_end);
+    if checksum_entries_end > footer_pos {
+        return Err(truncated("checksum entries", checksum_entries_end));
+    }
+    let checksum_names_end = read(checksum_pos + 8)
+        .saturating_add(checksum_pos)
+        .saturating_add(read(checksum_pos + 12));
+    if checksum_names_end > footer_pos {
+        return Err(truncated("checksum names", checksum_names_end));
+    }
+    Ok(())
+}
+
+/// Splits a `CPK_ITEM`-style entry into its (prefix, suffix) path strings.
+pub fn path_key(entry: &Entry) -> Option<(String, String)> {
+    if entry.values.len() < 2 {
+        return None;
+    }
+    let prefix = entry.string(0)?;
+    let suffix = entry.string(1).unwrap_or_default();
+    Some((prefix.to_string(), suffix.to_string()))
+}
+
+/// Whether `field` is an empty string: one without data, or holding nothing but the `"`
+/// quotes some tables wrap their paths in. Patched `CPK_ITEM` entries have such 3rd and
+/// 4th values.
+pub fn is_empty_string(field: &ValueField) -> bool {
+    match &field.data {
+        ValueData::Str(None) => true,
+        ValueData::Str(Some(s)) => s.trim_matches('"').is_empty(),
+        _ => false,
+    }
+}
+
+/// Separator form [`normalize_path_with`] gives `CPK_ITEM` paths, so that tables
+/// regenerated on different systems still match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathSep {
+    /// Separators as stored.
+    Keep,
+    /// Backslashes become forward slashes.
+    #[default]
+    Unix,
+    /// Forward slashes become backslashes.
+    Win,
+}
+
+/// Joins a `CPK_ITEM` prefix and suffix into the path entries are matched on, each part
+/// trimmed of whitespace and of the `"` quotes some tables wrap their paths in, with
+/// backslashes turned into forward slashes.
+pub fn normalize_path(prefix: &str, suffix: &str) -> String {
+    normalize_path_with(prefix, suffix, PathSep::Unix)
+}
+
+/// Like [`normalize_path`], with separators converted as `sep` says.
+pub fn normalize_path_with(prefix: &str, suffix: &str, sep: PathSep) -> String {
+    let clean = |part: &str| part.trim().trim_matches('"').trim().to_string();
+    let path = clean(prefix) + &clean(suffix);
+    match sep {
+        PathSep::Keep => path,
+        PathSep::Unix => path.replace('\\', "/"),
+        PathSep::Win => path.replace('/', "\\"),
+    }
+}
+
+/// Which entries are path rows and how their paths are joined, for matching the entries
+/// of two tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathKeys {
+    /// Names of the path rows.
+    pub names: Vec<String>,
+    pub sep: PathSep,
+}
+
+impl Default for PathKeys {
+    /// `CPK_ITEM` rows with [`normalize_path`] paths.
+    fn default() -> Self {
+        PathKeys {
+            names: vec!["CPK_ITEM".to_string()],
+            sep: PathSep::default(),
+        }
+    }
+}
+
+impl PathKeys {
+    /// Whether `entry` is a path row.
+    pub fn is_item(&self, entry: &Entry) -> bool {
+        self.names.contains(&entry.name)
+    }
+
+    /// Joins a prefix and suffix with [`normalize_path_with`].
+    pub fn path(&self, prefix: &str, suffix: &str) -> String {
+        normalize_path_with(prefix, suffix, self.sep)
+    }
+
+    /// The path `entry` is matched on, if it is a path row with a prefix.
+    pub fn key(&self, entry: &Entry) -> Option<String> {
+        if !self.is_item(entry) {
+            return None;
+        }
+        let (prefix, suffix) = path_key(entry)?;
+        Some(self.path(&prefix, &suffix))
+    }
+}
+
+/// Whether `entry` is marked as patched: its 3rd and 4th values are empty strings under
+/// [`is_empty_string`].
+pub fn is_patched(entry: &Entry) -> bool {
+    let empty = |idx| entry.values.get(idx).is_some_and(is_empty_string);
+    empty(2) && empty(3)
+}
+
+impl ParsedT2b {
+    /// Iterates over the entries in table order.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// Iterates over the `CPK_ITEM` entries in table order.
+    pub fn cpk_items(&self) -> impl Iterator<Item = &Entry> {
+        self.entries().filter(|e| e.name == "CPK_ITEM")
+    }
+
+    /// Finds the first entry with the stored name CRC32 `crc`.
+    pub fn find_by_crc(&self, crc: u32) -> Option<&Entry> {
+        self.entries().find(|e| e.crc32 == crc)
+    }
+
+    /// Iterates over the path rows of `keys` (see [`PathKeys::is_item`]) in table order.
+    pub fn cpk_items_with_keys<'a>(
+        &'a self,
+        keys: &'a PathKeys,
+    ) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries().filter(|e| keys.is_item(e))
+    }
+
+    /// Finds the first `CPK_ITEM` whose prefix and suffix (see [`path_key`]) join to
+    /// `path` under [`normalize_path`].
+    pub fn find_by_path(&self, path: &str) -> Option<&Entry> {
+        self.find_by_path_with_keys(path, &PathKeys::default())
+    }
+
+    /// Finds the first path row of `keys` whose [`PathKeys::key`] is `path`.
+    pub fn find_by_path_with_keys(&self, path: &str, keys: &PathKeys) -> Option<&Entry> {
+        self.entries()
+            .find(|e| keys.key(e).as_deref() == Some(path))
+    }
+
+    /// Mutable access to value `value_idx` of entry `entry_idx`, or `None` if there is
+    /// no such value. Edits only change `entries`: integer and float edits keep their
+    /// width and are written into `bytes` in place by [`ParsedT2b::commit_values`], while
+    /// string edits change the string data and need [`serialize`].
+    pub fn value_mut(&mut self, entry_idx: usize, value_idx: usize) -> Option<&mut ValueData> {
+        let field = self.entries.get_mut(entry_idx)?.values.get_mut(value_idx)?;
+        Some(&mut field.data)
+    }
+
+    /// Writes every integer and float value of `entries` into `bytes` at its stored
+    /// offset and the table's value length, and returns how many fields changed there.
+    /// String values are left alone, as edited strings need [`serialize`].
+    ///
+    /// Fails on the first value whose data no longer matches its type or does not fit
+    /// in a 32-bit table; the values before it are written by then.
+    pub fn commit_values(&mut self) -> Result<usize, T2bError> {
+        let mut changed = 0;
+        for entry in &self.entries {
+            for field in &entry.values {
+                let Some(bytes) = number_bytes(field, self.value_length, self.endian, &entry.name)?
+                else {
+                    continue;
+                };
+                let end = field.offset + bytes.len();
+                let have = self.bytes.len();
+                let stored = self
+                    .bytes
+                    .get_mut(field.offset..end)
+                    .ok_or_else(|| out_of_range("value", end, have))?;
+                if *stored != *bytes {
+                    stored.copy_from_slice(&bytes);
+                    changed += 1;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Writes `size` into value `value_idx` of entry `entry_idx`, in both `bytes` and
+    /// `entries`, at the table's value length: integer fields take integer sizes and float
+    /// fields either, as their nearest float. Returns whether the stored bytes changed; a
+    /// field that already holds the size is left alone.
+    ///
+    /// Fails if there is no such value, if it is a string, or an integer field given a
+    /// float size, or if the size does not fit in a 32-bit table.
+    pub fn set_size(
+        &mut self,
+        entry_idx: usize,
+        value_idx: usize,
+        size: Size,
+    ) -> Result<bool, T2bError> {
+        let (value_length, endian) = (self.value_length, self.endian);
+        let Some(entry) = self.entries.get_mut(entry_idx) else {
+            return Err(T2bError::MissingValue {
+                entry: format!("entry {entry_idx}"),
+                index: value_idx,
+            });
+        };
+        let Some(field) = entry.values.get_mut(value_idx) else {
+            return Err(T2bError::MissingValue {
+                entry: entry.name.clone(),
+                index: value_idx,
+            });
+        };
+        write_size(
+            &mut self.bytes,
+            field,
+            size,
+            value_length,
+            endian,
+            &entry.name,
+        )
+    }
+
+    /// Switches the table to the string encoding `enc`: [`serialize`] then encodes every
+    /// string and entry name in it and rebuilds the checksum section, and the footer
+    /// declares it. Name CRC32s are recomputed in `enc`, except stored ones that did not
+    /// match their name to begin with.
+    pub fn set_encoding(&mut self, enc: StringEncoding) {
+        let old = self.encoding;
+        let recompute = |name: &str, crc: &mut u32| {
+            if *crc == crc32_of_name(name, old) {
+                *crc = crc32_of_name(name, enc);
+            }
+        };
+        for (name, crc) in &mut self.name_crcs {
+            recompute(name, crc);
+        }
+        for entry in &mut self.entries {
+            recompute(&entry.name, &mut entry.crc32);
+        }
+        self.encoding = enc;
+        // The stored section holds the names in the old encoding.
+        self.checksum_section.clear();
+
+        let magic = read_u32(&self.footer, 0, Endian::Little);
+        if let Some((format, endian)) = magic.and_then(footer_format) {
+            let marker = encoding_marker(enc);
+            let bytes = match endian {
+                Endian::Little => marker.to_le_bytes(),
+                Endian::Big => marker.to_be_bytes(),
+            };
+            let at = format.encoding_offset;
+            if let Some(field) = self.footer.get_mut(at..at + 2) {
+                field.copy_from_slice(&bytes);
+            }
+        }
+    }
+}
+
+/// The stored bytes of an integer or float field at `value_length`, or `None` for a
+/// string field. `entry` names the entry in errors.
+fn number_bytes(
+    field: &ValueField,
+    value_length: ValueLength,
+    endian: Endian,
+    entry: &str,
+) -> Result<Option<Vec<u8>>, T2bError> {
+    let raw = match (&field.data, field.typ, value_length) {
+        (ValueData::Str(_), ValueType::String, _) => return Ok(None),
+        (ValueData::Int(n), ValueType::Integer, _) => *n,
+        (ValueData::Float(f), ValueType::FloatingPoint, ValueLength::Int) => {
+            (*f as f32).to_bits() as i32 as i64
+        }
+        (ValueData::Float(f), ValueType::FloatingPoint, ValueLength::Long) => f.to_bits() as i64,
+        _ => {
+            return Err(T2bError::TypeMismatch {
+                entry: entry.to_string(),
+            })
+        }
+    };
+    Ok(Some(match value_length {
+        ValueLength::Int => {
+            let v = i32::try_from(raw).map_err(|_| T2bError::ValueOverflow {
+                entry: entry.to_string(),
+                value: raw,
+            })?;
+            endian.i32_bytes(v).to_vec()
+        }
+        ValueLength::Long => endian.i64_bytes(raw).to_vec(),
+    }))
+}
+
+/// Writes `size` into `field` and at its offset in `bytes`, as [`ParsedT2b::set_size`]
+/// describes. `label` names the entry in errors.
+fn write_size(
+    bytes: &mut [u8],
+    field: &mut ValueField,
+    size: Size,
+    value_length: ValueLength,
+    endian: Endian,
+    label: &str,
+) -> Result<bool, T2bError> {
+    let data = match (field.typ, size, value_length) {
+        (ValueType::Integer, Size::Int(n), _) => ValueData::Int(n),
+        (ValueType::FloatingPoint, Size::Int(n), ValueLength::Int) => {
+            ValueData::Float(n as f32 as f64)
+        }
+        (ValueType::FloatingPoint, Size::Int(n), ValueLength::Long) => ValueData::Float(n as f64),
+        (ValueType::FloatingPoint, Size::Float(x), ValueLength::Int) => {
+            ValueData::Float(x as f32 as f64)
+        }
+        (ValueType::FloatingPoint, Size::Float(x), ValueLength::Long) => ValueData::Float(x),
+        (ValueType::Integer | ValueType::String, _, _) => {
+            return Err(T2bError::TypeMismatch {
+                entry: label.to_string(),
+            })
+        }
+    };
+    let sized = ValueField {
+        typ: field.typ,
+        data,
+        offset: field.offset,
+    };
+    let new = number_bytes(&sized, value_length, endian, label)?.ok_or_else(|| {
+        T2bError::TypeMismatch {
+            entry: label.to_string(),
+        }
+    })?;
+    let end = field.offset + new.len();
+    let have = bytes.len();
+    let stored = bytes
+        .get_mut(field.offset..end)
+        .ok_or_else(|| out_of_range("value", end, have))?;
+    if *stored == *new {
+        return Ok(false);
+    }
+    stored.copy_from_slice(&new);
+    *field = sized;
+    Ok(true)
+}
+
+/// Writes each size in `sizes`, keyed by [`normalize_path`], into value `dst_index` of
+/// the matching `CPK_ITEM` entries, updating both `parsed.bytes` and `parsed.entries`.
+/// Integer fields take the size as is and float fields its nearest float, each at the
+/// table's value length. Returns the number of entries updated; entries that already
+/// store the size are left alone and not counted.
+///
+/// Fails on the first matching entry whose value `dst_index` is missing or a string, or
+/// whose size does not fit in a 32-bit table; earlier entries are already updated then.
+pub fn apply_sizes(
+    parsed: &mut ParsedT2b,
+    sizes: &HashMap<String, i64>,
+    dst_index: usize,
+) -> Result<u32, T2bError> {
+    let size_of = |path: &str| sizes.get(path).map(|&n| Size::Int(n));
+    let keys = PathKeys::default();
+    write_size_fields(parsed, &keys, &size_of, dst_index, &mut |_, _, _| {})
+}
+
+/// [`apply_sizes`] for the path rows of `keys`, with the size of each path looked up by
+/// `size_of`, calling `on_update` with the path, old value and new size of each entry
+/// whose stored value changes.
+fn write_size_fields(
+    parsed: &mut ParsedT2b,
+    keys: &PathKeys,
+    size_of: &dyn Fn(&str) -> Option<Size>,
+    dst_index: usize,
+    on_update: &mut dyn FnMut(&str, Size, Size),
+) -> Result<u32, T2bError> {
+    let (value_length, endian) = (parsed.value_length, parsed.endian);
+    let mut updated = 0;
+    for entry in &mut parsed.entries {
+        let Some(path) = keys.key(entry) else {
+            continue;
+        };
+        let Some(size) = size_of(&path) else {
+            continue;
+        };
+        let Some(field) = entry.values.get_mut(dst_index) else {
+            return Err(T2bError::MissingValue {
+                entry: path,
+                index: dst_index,
+            });
+        };
+        let old = match field.data {
+            // The command line skips these too; a float size has no exact integer.
+            ValueData::Int(_) if matches!(size, Size::Float(_)) => continue,
+            ValueData::Int(n) => Size::Int(n),
+            ValueData::Float(f) => Size::Float(f),
+            ValueData::Str(_) => return Err(T2bError::TypeMismatch { entry: path }),
+        };
+        if write_size(&mut parsed.bytes, field, size, value_length, endian, &path)? {
+            on_update(&path, old, size);
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+/// Syncs sizes from patched table `b` into a copy of `a` and returns the table bytes.
+///
+/// Sizes are read from value 4 of `b`'s patched `CPK_ITEM` entries (see [`is_patched`]),
+/// as integers, floats or numeric strings (see [`Entry::size`]), and written into value
+/// 4 of the `a` entries with the same [`normalize_path`] path; integer fields skip
+/// float sizes. `on_update` is called
+/// with the path, old value and new value of each entry whose size changes, in table
+/// order; float values are passed truncated.
+pub fn sync_with<F>(a: &ParsedT2b, b: &ParsedT2b, on_update: F) -> Result<Vec<u8>, T2bError>
+where
+    F: FnMut(&str, i64, i64),
+{
+    sync_with_keys(a, b, &PathKeys::default(), on_update)
+}
+
+/// Like [`sync_with`], matching the path rows of `keys` instead of `CPK_ITEM` entries.
+pub fn sync_with_keys<F>(
+    a: &ParsedT2b,
+    b: &ParsedT2b,
+    keys: &PathKeys,
+    mut on_update: F,
+) -> Result<Vec<u8>, T2bError>
+where
+    F: FnMut(&str, i64, i64),
+{
+    const SIZE_INDEX: usize = 4;
+
+    let sizes = b
+        .entries()
+        .filter(|e| is_patched(e))
+        .filter_map(|e| Some((keys.key(e)?, e.size(SIZE_INDEX)?)))
+        .collect::<HashMap<_, _>>();
+    let size_of = |path: &str| sizes.get(path).copied();
+    let as_integer = |size| match size {
+        Size::Int(n) => n,
+        Size::Float(f) => f as i64,
+    };
+    let mut synced = a.clone();
+    write_size_fields(
+        &mut synced,
+        keys,
+        &size_of,
+        SIZE_INDEX,
+        &mut |path, old, new| on_update(path, as_integer(old), as_integer(new)),
+    )?;
+    Ok(synced.bytes)
+}
+
+/// Parses a T2B table from memory.
+pub fn parse_t2b(bytes: &[u8]) -> Result<ParsedT2b, T2bError> {
+    parse_t2b_traced(bytes, &mut |_| {})
+}
+
+/// Like [`parse_t2b`], but reports each parse step (section offsets, detected value
+/// length, per-entry positions) to `trace`.
+pub fn parse_t2b_traced(
+    bytes: &[u8],
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Result<ParsedT2b, T2bError> {
+    parse_t2b_with(bytes, ParseOptions::default(), trace)
+}
+
+/// Overrides for values [`parse_t2b`] otherwise reads from the table itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// String encoding to use instead of the one declared in the footer, for tables
+    /// whose footer is wrong.
+    pub encoding: Option<StringEncoding>,
+    /// Value length to parse with instead of detecting it, for tables that parse under
+    /// the wrong width.
+    pub value_length: Option<ValueLength>,
+    /// Keep entries whose name cannot be resolved from the checksum section, naming
+    /// them `crc_0x…` after their CRC32, instead of failing.
+    pub lenient: bool,
+    /// Keep the entries before the first one that cannot be read, recording where and
+    /// why parsing stopped in [`ParsedT2b::recovery`], instead of failing. An entry
+    /// count too large for the entry section is also read up to the first bad entry.
+    pub recover: bool,
+    /// Parse a table whose checksum section is out of range or cannot be read from the
+    /// entries alone: every entry gets a `crc_0x…` name, as with `lenient`, and
+    /// [`ParsedT2b::checksum_error`] says what was wrong.
+    pub force_parse: bool,
+}
+
+/// The footer layout whose magic matches `magic` in either byte order, with that order.
+fn footer_format(magic: u32) -> Option<(&'static FooterFormat, Endian)> {
+    FOOTER_FORMATS.iter().find_map(|format| {
+        if magic == format.magic {
+            Some((format, Endian::Little))
+        } else if magic == format.magic.swap_bytes() {
+            Some((format, Endian::Big))
+        } else {
+            None
+        }
+    })
+}
+
+/// The footer encoding field that declares `enc`.
+fn encoding_marker(enc: StringEncoding) -> i16 {
+    match enc {
+        StringEncoding::Sjis => 0,
+        StringEncoding::Utf8 => 1,
+        StringEncoding::Utf16Le => 2,
+    }
+}
+
+/// The string encoding a footer's encoding field declares, if it is a known value.
+fn footer_encoding(raw: i16) -> Option<StringEncoding> {
+    match raw {
+        0 => Some(StringEncoding::Sjis),
+        1 | 256 | 257 => Some(StringEncoding::Utf8),
+        2 => Some(StringEncoding::Utf16Le),
+        _ => None,
+    }
+}
+
+/// Entries take at least 8 bytes each and sit between the header and the string data,
+/// which must end before the footer.
+fn header_fits(
+    entry_count: usize,
+    string_data_offset: usize,
+    string_data_length: usize,
+    footer_pos: usize,
+) -> bool {
+    string_data_offset >= 0x10
+        && entry_count <= (string_data_offset - 0x10) / 8
+        && string_data_offset
+            .checked_add(string_data_length)
+            .is_some_and(|end| end <= footer_pos)
+}
+
+/// Header and footer fields of a table as stored, read by [`read_header`] without
+/// parsing the entries. A field is `None` where it lies outside the table.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderInfo {
+    pub table_len: usize,
+    pub magic: Option<u32>,
+    /// Byte order implied by the footer magic; `None` when the magic is not a T2B
+    /// magic in either byte order, in which case the other fields are read as
+    /// little-endian.
+    pub endian: Option<Endian>,
+    pub encoding_raw: Option<i16>,
+    /// The encoding `encoding_raw` declares, if it is a known value.
+    pub encoding: Option<StringEncoding>,
+    pub entry_count: Option<u32>,
+    pub string_data_offset: Option<u32>,
+    pub string_data_length: Option<u32>,
+    pub string_count: Option<u32>,
+    /// The detected value length; `None` when the header is inconsistent or neither
+    /// width yields a consistent entry section.
+    pub value_length: Option<ValueLength>,
+    /// Where the checksum section starts: the end of the string data, aligned to 0x10.
+    pub checksum_pos: Option<usize>,
+    pub checksum_size: Option<u32>,
+    pub checksum_count: Option<u32>,
+    /// Offset of the checksum names, relative to `checksum_pos`.
+    pub checksum_names_offset: Option<u32>,
+    pub checksum_names_size: Option<u32>,
+}
+
+/// Reads whatever header and footer fields a table has, without checking that they
+/// agree, so that a table [`parse_t2b`] rejects can still be examined.
+pub fn read_header(bytes: &[u8]) -> HeaderInfo {
+    let len = bytes.len();
+    let footer_pos = len.checked_sub(0x10);
+    let magic = footer_pos.and_then(|pos| read_u32(bytes, pos, Endian::Little));
+    let found = magic.and_then(footer_format);
+    let endian = found.map_or(Endian::Little, |(_, endian)| endian);
+    let encoding_offset = found.map_or(FOOTER_FORMATS[0].encoding_offset, |(f, _)| {
+        f.encoding_offset
+    });
+    let encoding_raw = footer_pos.and_then(|pos| read_i16(bytes, pos + encoding_offset, endian));
+    let read = |pos: usize| read_u32(bytes, pos, endian);
+
+    let mut info = HeaderInfo {
+        table_len: len,
+        magic,
+        endian: found.map(|(_, endian)| endian),
+        encoding_raw,
+        encoding: encoding_raw.and_then(footer_encoding),
+        entry_count: read(0),
+        string_data_offset: read(4),
+        string_data_length: read(8),
+        string_count: read(12),
+        ..HeaderInfo::default()
+    };
+    let (Some(count), Some(offset), Some(length)) = (
+        info.entry_count.map(|v| v as usize),
+        info.string_data_offset.map(|v| v as usize),
+        info.string_data_length.map(|v| v as usize),
+    ) else {
+        return info;
+    };
+    if footer_pos.is_some_and(|footer_pos| header_fits(count, offset, length, footer_pos)) {
+        info.value_length = VALUE_ALIGNMENTS.iter().find_map(|&align| {
+            detect_value_length(bytes, count, offset, length, align, endian, &mut |_| {})
+        });
+    }
+    let end = offset.checked_add(length);
+    if let Some(checksum_pos) = end.map(|end| checksum_position(bytes, end, endian)) {
+        info.checksum_pos = Some(checksum_pos);
+        info.checksum_size = read(checksum_pos);
+        info.checksum_count = read(checksum_pos + 4);
+        info.checksum_names_offset = read(checksum_pos + 8);
+        info.checksum_names_size = read(checksum_pos + 12);
+    }
+    info
+}
+
+/// Like [`parse_t2b_traced`], with `options` overriding what the table declares.
+pub fn parse_t2b_with(
+    bytes: &[u8],
+    options: ParseOptions,
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Result<ParsedT2b, T2bError> {
+    parse_t2b_owned(bytes.to_vec(), options, trace)
+}
+
+/// Like [`parse_t2b_with`], but takes ownership of the table so it becomes
+/// [`ParsedT2b::bytes`] without being copied.
+pub fn parse_t2b_owned(
+    bytes: Vec<u8>,
+    options: ParseOptions,
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Result<ParsedT2b, T2bError> {
+    if bytes.len() < 0x30 {
+        return Err(T2bError::FileTooSmall);
+    }
+
+    let footer_pos = bytes.len() - 0x10;
+    let len = bytes.len();
+    let magic = read_u32(&bytes, footer_pos, Endian::Little)
+        .ok_or_else(|| out_of_range("footer", footer_pos + 4, len))?;
+    let (format, endian) = footer_format(magic).ok_or(T2bError::BadMagic(magic))?;
+    let encoding_pos = footer_pos + format.encoding_offset;
+    let encoding_raw = read_i16(&bytes, encoding_pos, endian)
+        .ok_or_else(|| out_of_range("footer", encoding_pos + 2, len))?;
+    let encoding = match options.encoding {
+        Some(encoding) => encoding,
+        None => footer_encoding(encoding_raw).ok_or(T2bError::UnknownEncoding(encoding_raw))?,
+    };
+    let forced = if options.encoding.is_some() {
+        ", forced"
+    } else {
+        ""
+    };
+    trace(format_args!(
+        "footer at 0x{footer_pos:x}: {endian:?} endian, {encoding:?} (raw {encoding_raw}{forced})"
+    ));
+
+    match check_sections(&bytes, endian) {
+        // The checksum section is read, and ignored if need be, below.
+        Err(T2bError::Truncated { section, .. })
+            if options.force_parse && section.starts_with("checksum") => {}
+        result => result?,
+    }
+
+    // Entry header
+    let header =
+        |pos| read_u32(&bytes, pos, endian).ok_or_else(|| out_of_range("entry header", 0x10, len));
+    let declared_count = header(0)? as usize;
+    let mut entry_count = declared_count;
+    let string_data_offset = header(4)? as usize;
+    let string_data_length = header(8)? as usize;
+    if options.recover && string_data_offset >= 0x10 {
+        // A corrupt count is cut to the most entries that fit; the bad entry stops parsing.
+        entry_count = entry_count.min((string_data_offset - 0x10) / 8);
+    }
+    if !header_fits(
+        entry_count,
+        string_data_offset,
+        string_data_length,
+        footer_pos,
+    ) {
+        return Err(T2bError::BadHeader {
+            entry_count: declared_count,
+            string_data_offset,
+            string_data_length,
+            table_len: len,
+        });
+    }
+    trace(format_args!(
+        "header: {entry_count} entries, string data at 0x{string_data_offset:x} \
+         ({string_data_length} bytes)"
+    ));
+
+    // Detect value length unless it is forced, trying other value alignments only when
+    // the entries do not parse with the default.
+    let detected = VALUE_ALIGNMENTS.iter().find_map(|&align| {
+        let value_length = match options.value_length {
+            Some(forced) => {
+                let (count, offset) = (entry_count, string_data_offset);
+                try_parse_entries(&bytes, count, offset, forced, align, endian, &mut |_| {})
+                    .is_ok()
+                    .then_some(forced)
+            }
+            None => detect_value_length(
+                &bytes,
+                entry_count,
+                string_data_offset,
+                string_data_length,
+                align,
+                endian,
+                trace,
+            ),
+        }?;
+        Some((value_length, align))
+    });
+    let (value_length, value_align) = match (detected, options.value_length) {
+        (Some(found), _) => found,
+        // Parsing the entries below reports why the forced length does not fit.
+        (None, Some(forced)) => (forced, Alignment::default().values),
+        (None, None) if options.recover => {
+            let align = Alignment::default().values;
+            let len = recover_value_length(
+                &bytes,
+                entry_count,
+                string_data_offset,
+                string_data_length,
+                align,
+                endian,
+            );
+            (len, align)
+        }
+        (None, None) => {
+            return Err(
+                value_count_error(&bytes, entry_count, string_data_offset, endian)
+                    .unwrap_or(T2bError::UndetectedValueLength),
+            )
+        }
+    };
+    let forced = if options.value_length.is_some() {
+        ", forced"
+    } else {
+        ""
+    };
+    trace(format_args!(
+        "value length: {value_length:?} ({} bytes{forced})",
+        value_length as usize
+    ));
+    if value_align != Alignment::default().values {
+        trace(format_args!("values aligned to {value_align} bytes"));
+    }
+
+    let parsed_entries = parse_entries(
+        &bytes,
+        entry_count,
+        string_data_offset,
+        value_length,
+        value_align,
+        endian,
+        trace,
+    );
+    let (mut recovery, mut checksum_error) = (None, None);
+    let (entries_raw, entries_end_pos) = match parsed_entries {
+        Ok(parsed) => parsed,
+        Err(stop) if options.recover => {
+            trace(format_args!(
+                "recovering {} of {declared_count} entries: entry {} at 0x{:x}: {}",
+                stop.entries.len(),
+                stop.entries.len(),
+                stop.pos,
+                stop.reason
+            ));
+            recovery = Some(Recovery {
+                entry: stop.entries.len(),
+                declared: declared_count,
+                offset: stop.pos,
+                reason: stop.reason,
+            });
+            (stop.entries, stop.pos)
+        }
+        Err(_) => {
+            let error = value_count_error(&bytes, entry_count, string_data_offset, endian);
+            return Err(error.unwrap_or(match options.value_length {
+                Some(forced) => T2bError::WrongValueLength(forced),
+                None => T2bError::BadEntries,
+            }));
+        }
+    };
+
+    let string_data_end = string_data_offset + string_data_length;
+    let value_string_data = bytes
+        .get(string_data_offset..string_data_end)
+        .ok_or_else(|| out_of_range("string data", string_data_end, len))?;
+
+    let checksum_pos = checksum_position(&bytes, string_data_end, endian);
+    let checksum =
+        read_checksum_section(&bytes, checksum_pos, endian, encoding, trace).and_then(|section| {
+            // A section that reads but cannot name the entries is just as damaged.
+            if options.force_parse && !options.lenient {
+                for raw in &entries_raw {
+                    let offset = *section
+                        .crc_to_name_offset
+                        .get(&raw.crc32)
+                        .ok_or(T2bError::MissingNameOffset(raw.crc32))?;
+                    read_string(section.names_data, offset, encoding)
+                        .ok_or(T2bError::BadName(raw.crc32))?;
+                }
+            }
+            Ok(section)
+        });
+    let checksum = match checksum {
+        Ok(section) => Some(section),
+        Err(err) if options.force_parse => {
+            trace(format_args!("ignoring the checksum section: {err}"));
+            checksum_error = Some(err.to_string());
+            None
+        }
+        Err(err) => return Err(err),
+    };
+
+    // The largest section alignment every stored gap is exactly the padding to. A
+    // recovered table's entries stop short of the string data by design, and an ignored
+    // checksum section has no trustworthy layout.
+    let entries_gap = recovery
+        .is_none()
+        .then_some((entries_end_pos, string_data_offset));
+    let checksum_gaps = checksum.as_ref().map(|section| {
+        [
+            (string_data_end, checksum_pos),
+            (section.entries_end, section.names_pos),
+        ]
+    });
+    let fits = |align: usize| {
+        entries_gap
+            .iter()
+            .chain(checksum_gaps.iter().flatten())
+            .all(|&(end, start)| start >= end && align_up(end, align) == start)
+    };
+    let alignment = match SECTION_ALIGNMENTS.into_iter().find(|&a| fits(a)) {
+        Some(sections) => Alignment {
+            values: value_align,
+            sections,
+            regular: true,
+        },
+        None => Alignment {
+            values: value_align,
+            regular: false,
+            ..Alignment::default()
+        },
+    };
+    if alignment.sections != Alignment::default().sections || !alignment.regular {
+        trace(format_args!(
+            "sections aligned to 0x{:x}{}",
+            alignment.sections,
+            if alignment.regular {
+                ""
+            } else {
+                ", but the padding fits no alignment"
+            }
+        ));
+    }
+
+    // Without a checksum section every entry gets a `crc_0x…` name, as in lenient mode.
+    let no_names = HashMap::new();
+    let lenient = options.lenient || checksum.is_none();
+    let decoder = EntryDecoder {
+        crc_to_name_offset: checksum
+            .as_ref()
+            .map_or(&no_names, |section| &section.crc_to_name_offset),
+        checksum_string_data: checksum.as_ref().map_or(&[], |section| section.names_data),
+        value_string_data,
+        encoding,
+        value_length,
+        lenient,
+    };
+    let entries = decode_entries(&decoder, &entries_raw)?;
+    let mut unresolved_names = Vec::new();
+    if lenient {
+        unresolved_names = entries_raw
+            .iter()
+            .map(|raw| raw.crc32)
+            .filter(|&crc| decoder.name(crc).is_err())
+            .collect::<Vec<_>>();
+        unresolved_names.sort_unstable();
+        unresolved_names.dedup();
+    }
+    let name_crcs = entries
+        .iter()
+        .map(|entry| (entry.name.clone(), entry.crc32))
+        .collect();
+
+    // Reuse whatever filler the original used for its alignment gaps, looking first
+    // where `serialize` pads too.
+    let gap = |start: usize, end: usize| bytes.get(start..end).unwrap_or_default();
+    let padding = entries_raw
+        .iter()
+        .flat_map(|raw| gap(raw.types_end, align_up(raw.types_end, value_align)))
+        .chain(gap(entries_end_pos, string_data_offset))
+        .chain(gap(string_data_end, checksum_pos))
+        .next()
+        .copied()
+        .unwrap_or(0xFF);
+    let footer = gap(footer_pos, len).to_vec();
+    let mut opaque = OpaqueRegions {
+        after_entries: gap(entries_end_pos, string_data_offset).to_vec(),
+        ..OpaqueRegions::default()
+    };
+    // An ignored checksum section is rebuilt from the entries by `serialize`.
+    let mut checksum_section = Vec::new();
+    let (mut name_offset_base, mut checksum_names) = (0, Vec::new());
+    if let Some(section) = checksum {
+        opaque.after_strings = gap(string_data_end, checksum_pos).to_vec();
+        opaque.after_checksum = gap(align_up(section.end, alignment.sections), footer_pos).to_vec();
+        checksum_section = bytes
+            .get(checksum_pos..footer_pos)
+            .ok_or_else(|| out_of_range("checksum section", checksum_pos, footer_pos))?
+            .to_vec();
+        (name_offset_base, checksum_names) = (section.base_offset, section.names);
+    }
+
+    Ok(ParsedT2b {
+        bytes,
+        value_length,
+        endian,
+        encoding,
+        entries,
+        name_crcs,
+        unresolved_names,
+        recovery,
+        checksum_error,
+        alignment,
+        name_offset_base,
+        checksum_names,
+        checksum_section,
+        padding,
+        opaque,
+        footer,
+        entries_end: entries_end_pos,
+    })
+}
+
+/// The parts of a checksum section the parser needs.
+struct ChecksumSection<'a> {
+    /// End of the (crc, name offset) pairs.
+    entries_end: usize,
+    names_pos: usize,
+    /// End of the pairs or the names, whichever is later.
+    end: usize,
+    /// Name offset of the first checksum entry; all other offsets are relative to it.
+    base_offset: u32,
+    crc_to_name_offset: HashMap<u32, usize>,
+    /// Names of the checksum entries in stored order.
+    names: Vec<String>,
+    names_data: &'a [u8],
+}
+
+/// Reads the checksum section at `checksum_pos`: its header, the (crc, name offset)
+/// pairs and the names they point to.
+fn read_checksum_section<'a>(
+    bytes: &'a [u8],
+    checksum_pos: usize,
+    endian: Endian,
+    encoding: StringEncoding,
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Result<ChecksumSection<'a>, T2bError> {
+    let len = bytes.len();
+    let checksum_header = |pos| {
+        read_u32(bytes, pos, endian)
+            .ok_or_else(|| out_of_range("checksum header", checksum_pos + 0x10, len))
+    };
+    let _checksum_size = checksum_header(checksum_pos)? as usize;
+    let checksum_count = checksum_header(checksum_pos + 4)? as usize;
+    let checksum_string_offset = checksum_header(checksum_pos + 8)? as usize;
+    let checksum_string_size = checksum_header(checksum_pos + 12)? as usize;
+
+    let checksum_entries_pos = checksum_pos + 0x10;
+    let checksum_strings_pos = checksum_pos + checksum_string_offset;
+
+    let checksum_strings_end = checksum_strings_pos + checksum_string_size;
+    let checksum_end = (checksum_entries_pos + checksum_count * 8).max(checksum_strings_end);
+    if checksum_end > len {
+        return Err(out_of_range("checksum section", checksum_end, len));
+    }
+    trace(format_args!(
+        "checksum section at 0x{checksum_pos:x}..0x{checksum_end:x}: {checksum_count} \
+         entries, names at 0x{checksum_strings_pos:x} ({checksum_string_size} bytes)"
+    ));
+
+    let mut checksum_entries = Vec::with_capacity(checksum_count);
+    for i in 0..checksum_count {
+        let p = checksum_entries_pos + i * 8;
+        let entry_range = || out_of_range("checksum section", p + 8, len);
+        let crc = read_u32(bytes, p, endian).ok_or_else(entry_range)?;
+        let str_off = read_u32(bytes, p + 4, endian).ok_or_else(entry_range)?;
+        checksum_entries.push((crc, str_off));
+    }
+
+    let checksum_string_data = bytes
+        .get(checksum_strings_pos..checksum_strings_end)
+        .ok_or_else(|| out_of_range("checksum section", checksum_strings_end, len))?;
+
+    // Map crc -> name offset (relative to first string offset)
+    let base_offset = checksum_entries
+        .first()
+        .map(|e| e.1)
+        .ok_or(T2bError::NoChecksumEntries)?;
+    let mut crc_to_name_offset = HashMap::new();
+    let mut checksum_names = Vec::with_capacity(checksum_count);
+    for (crc, off) in &checksum_entries {
+        let name_offset = (*off as i64 - base_offset as i64) as usize;
+        crc_to_name_offset.insert(*crc, name_offset);
+        checksum_names.extend(read_string(checksum_string_data, name_offset, encoding));
+    }
+
+    Ok(ChecksumSection {
+        entries_end: checksum_entries_pos + checksum_count * 8,
+        names_pos: checksum_strings_pos,
+        end: checksum_end,
+        base_offset,
+        crc_to_name_offset,
+        names: checksum_names,
+        names_data: checksum_string_data,
+    })
+}
+
+/// Rebuilds the complete T2B layout from `parsed.entries`, ignoring `parsed.bytes`.
+///
+/// Strings are deduplicated in order of first appearance. While the entries use
+/// exactly the names of the parsed checksum section, that section is copied verbatim;
+/// otherwise each distinct name gets one checksum entry, in stored order for names the
+/// table already had and then in order of first appearance.
+///
+/// Alignment gaps are filled with the table's padding byte, except that the stored
+/// bytes between sections are re-emitted where the rebuilt gap has the same length, and
+/// anything stored after the checksum section is kept in front of the footer. An
+/// unchanged table therefore round-trips byte for byte.
+pub fn serialize(parsed: &ParsedT2b) -> Result<Vec<u8>, T2bError> {
+    let value_length = parsed.value_length;
+    let endian = parsed.endian;
+    let pad = parsed.padding;
+    let Alignment {
+        values: value_align,
+        sections: section_align,
+        ..
+    } = parsed.alignment;
+
+    let mut out = vec![0u8; 0x10];
+    let mut strings = StringTable::default();
+    let mut entry_names = Vec::new();
+    let mut seen_names = HashSet::new();
+    // Keep stored checksums for known names so untouched tables round-trip exactly.
+    let crc_of = |name: &str| {
+        parsed
+            .name_crcs
+            .get(name)
+            .copied()
+            .unwrap_or_else(|| crc32_of_name(name, parsed.encoding))
+    };
+
+    for entry in &parsed.entries {
+        let crc = crc_of(&entry.name);
+        if seen_names.insert(entry.name.as_str()) {
+            entry_names.push(entry.name.as_str());
+        }
+
+        let value_count =
+            u8::try_from(entry.values.len()).map_err(|_| T2bError::TooManyValues {
+                entry: entry.name.clone(),
+            })?;
+        out.extend_from_slice(&endian.u32_bytes(crc));
+        out.push(value_count);
+        for chunk in entry.values.chunks(4) {
+            let mut type_chunk = 0u8;
+            for (h, field) in chunk.iter().enumerate() {
+                type_chunk |= (field.typ as u8) << (h * 2);
+            }
+            out.push(type_chunk);
+        }
+        pad_to(&mut out, value_align, pad);
+
+        for field in &entry.values {
+            let raw = match (&field.data, field.typ) {
+                (ValueData::Str(None), ValueType::String) => -1,
+                (ValueData::Str(Some(s)), ValueType::String) => {
+                    strings.insert(s, parsed.encoding)? as i64
+                }
+                (ValueData::Int(n), ValueType::Integer) => *n,
+                (ValueData::Float(f), ValueType::FloatingPoint) => match value_length {
+                    ValueLength::Int => (*f as f32).to_bits() as i32 as i64,
+                    ValueLength::Long => f.to_bits() as i64,
+                },
+                _ => {
+                    return Err(T2bError::TypeMismatch {
+                        entry: entry.name.clone(),
+                    })
+                }
+            };
+            match value_length {
+                ValueLength::Int => {
+                    let v = i32::try_from(raw).map_err(|_| T2bError::ValueOverflow {
+                        entry: entry.name.clone(),
+                        value: raw,
+                    })?;
+                    out.extend_from_slice(&endian.i32_bytes(v));
+                }
+                ValueLength::Long => out.extend_from_slice(&endian.i64_bytes(raw)),
+            }
+        }
+    }
+    pad_or_reuse(&mut out, section_align, pad, &parsed.opaque.after_entries);
+
+    let string_data_offset = out.len();
+    out.extend_from_slice(&strings.data);
+    write_u32(&mut out, 0, endian, parsed.entries.len() as u32);
+    write_u32(&mut out, 4, endian, string_data_offset as u32);
+    write_u32(&mut out, 8, endian, strings.data.len() as u32);
+    write_u32(&mut out, 12, endian, strings.count as u32);
+    pad_or_reuse(&mut out, section_align, pad, &parsed.opaque.after_strings);
+
+    let stored_names = parsed
+        .checksum_names
+        .iter()
+        .map(String::as_str)
+        .collect::<HashSet<_>>();
+    if seen_names == stored_names && !parsed.checksum_section.is_empty() {
+        out.extend_from_slice(&parsed.checksum_section);
+        out.extend_from_slice(&parsed.footer);
+        return Ok(out);
+    }
+
+    let mut names = StringTable::default();
+    let mut checksums = Vec::new();
+    let new_names = entry_names
+        .into_iter()
+        .filter(|name| !stored_names.contains(name));
+    let ordered_names = parsed
+        .checksum_names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| seen_names.contains(name))
+        .chain(new_names);
+    for name in ordered_names {
+        let name_offset = names.insert(name, parsed.encoding)?;
+        if names.count > checksums.len() {
+            checksums.push((crc_of(name), name_offset));
+        }
+    }
+    // `parse_t2b` needs at least one checksum entry for its name offset base.
+    if checksums.is_empty() {
+        return Err(T2bError::NoChecksumEntries);
+    }
+
+    // Checksum section: header, (crc, name offset) pairs, then the name strings.
+    let checksum_pos = out.len();
+    out.extend_from_slice(&[0u8; 0x10]);
+    for (crc, name_offset) in &checksums {
+        out.extend_from_slice(&endian.u32_bytes(*crc));
+        out.extend_from_slice(&endian.u32_bytes(parsed.name_offset_base + name_offset));
+    }
+    pad_to(&mut out, section_align, pad);
+    let checksum_string_offset = out.len() - checksum_pos;
+    out.extend_from_slice(&names.data);
+    pad_to(&mut out, section_align, pad);
+    let checksum_size = out.len() - checksum_pos;
+    write_u32(&mut out, checksum_pos, endian, checksum_size as u32);
+    write_u32(&mut out, checksum_pos + 4, endian, checksums.len() as u32);
+    let string_offset = checksum_string_offset as u32;
+    write_u32(&mut out, checksum_pos + 8, endian, string_offset);
+    write_u32(&mut out, checksum_pos + 12, endian, names.data.len() as u32);
+
+    out.extend_from_slice(&parsed.opaque.after_checksum);
+    out.extend_from_slice(&parsed.footer);
+    Ok(out)
+}
+
+/// Builds a little-endian table from scratch with one entry per `(name, values)` pair,
+/// in order. Each value's type follows its data, and `ValueData::Str(None)` is stored
+/// as a string value without data. The checksum section lists every distinct name once
+/// with its CRC32 in `enc`, and the footer declares `enc`.
+///
+/// Fails for the same reasons as [`serialize`]: without entries, with more than 255
+/// values in an entry, or with a value `value_length` or `enc` cannot hold.
+pub fn build_minimal_table(
+    entries: &[(String, Vec<ValueData>)],
+    value_length: ValueLength,
+    enc: StringEncoding,
+) -> Result<Vec<u8>, T2bError> {
+    if entries.is_empty() {
+        return Err(T2bError::NoChecksumEntries);
+    }
+    let entries = entries
+        .iter()
+        .map(|(name, values)| Entry {
+            name: name.clone(),
+            crc32: crc32_of_name(name, enc),
+            values: values
+                .iter()
+                .map(|data| ValueField {
+                    typ: match data {
+                        ValueData::Str(_) => ValueType::String,
+                        ValueData::Int(_) => ValueType::Integer,
+                        ValueData::Float(_) => ValueType::FloatingPoint,
+                    },
+                    data: data.clone(),
+                    offset: 0,
+                })
+                .collect(),
+        })
+        .collect();
+    let format = &FOOTER_FORMATS[0];
+    let mut footer = vec![0u8; 0x10];
+    footer[0..4].copy_from_slice(&format.magic.to_le_bytes());
+    let at = format.encoding_offset;
+    footer[at..at + 2].copy_from_slice(&encoding_marker(enc).to_le_bytes());
+
+    // No stored checksum section, so `serialize` builds one from the entry names.
+    let table = ParsedT2b {
+        bytes: Vec::new(),
+        value_length,
+        endian: Endian::Little,
+        encoding: enc,
+        entries,
+        name_crcs: HashMap::new(),
+        unresolved_names: Vec::new(),
+        recovery: None,
+        checksum_error: None,
+        alignment: Alignment::default(),
+        name_offset_base: 0,
+        checksum_names: Vec::new(),
+        checksum_section: Vec::new(),
+        padding: 0xFF,
+        opaque: OpaqueRegions::default(),
+        footer,
+        entries_end: 0,
+    };
+    serialize(&table)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// CRC32 (IEEE, reflected) of an entry name in the table's string encoding, as
+/// stored in the checksum section.
+pub fn crc32_of_name(name: &str, enc: StringEncoding) -> u32 {
+    let bytes = match enc {
+        StringEncoding::Utf8 => name.as_bytes().to_vec(),
+        StringEncoding::Sjis => SHIFT_JIS.encode(name).0.into_owned(),
+        StringEncoding::Utf16Le => utf16le_bytes(name),
+    };
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in bytes {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// An entry name whose stored checksum differs from the CRC32 of the name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcMismatch {
+    pub name: String,
+    pub stored: u32,
+    pub computed: u32,
+}
+
+/// Recomputes the CRC32 of every entry name and returns those that disagree with the
+/// stored checksum, sorted by name. A non-empty result usually means the table comes
+/// from a different game variant or was edited by hand.
+pub fn crc_mismatches(parsed: &ParsedT2b) -> Vec<CrcMismatch> {
+    let mut mismatches = parsed
+        .name_crcs
+        .iter()
+        .filter_map(|(name, &stored)| {
+            let computed = crc32_of_name(name, parsed.encoding);
+            (computed != stored).then(|| CrcMismatch {
+                name: name.clone(),
+                stored,
+                computed,
+            })
+        })
+        .collect::<Vec<_>>();
+    mismatches.sort_by(|a, b| a.name.cmp(&b.name));
+    mismatches
+}
+
+/// A stored size or offset that disagrees with where the surrounding sections put it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutMismatch {
+    pub field: &'static str,
+    pub stored: usize,
+    pub expected: usize,
+}
+
+/// Cross-checks the stored string data length, checksum section size and footer
+/// position of `parsed.bytes` against each other. The parser trusts the stored
+/// values, so a mismatch usually means the table was edited by hand and some
+/// section may hold stale bytes.
+pub fn layout_mismatches(parsed: &ParsedT2b) -> Vec<LayoutMismatch> {
+    let bytes = &parsed.bytes;
+    let read = |pos| read_u32(bytes, pos, parsed.endian).map_or(0, |v| v as usize);
+    let string_data_offset = read(4);
+    let string_data_end = string_data_offset + read(8);
+    let align = parsed.alignment.sections;
+    let checksum_pos = checksum_position(bytes, string_data_end, parsed.endian);
+    let footer_pos = bytes.len() - 0x10;
+    let checksum_names_end = checksum_pos + read(checksum_pos + 8) + read(checksum_pos + 12);
+
+    // The last string must end inside the string data; otherwise it runs on to the
+    // next NUL.
+    let string_data = bytes
+        .get(string_data_offset..string_data_end)
+        .unwrap_or_default();
+    let string_data_length = match string_data.last() {
+        Some(0) | None => string_data.len(),
+        Some(_) => bytes
+            .get(string_data_end..footer_pos)
+            .unwrap_or_default()
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(string_data.len(), |nul| string_data.len() + nul + 1),
+    };
+
+    // A recovered table's entries stop short of the string data by design.
+    let entries_end = match &parsed.recovery {
+        Some(_) => string_data_offset,
+        None => align_up(parsed.entries_end, align),
+    };
+    [
+        ("string data offset", string_data_offset, entries_end),
+        ("string data length", string_data.len(), string_data_length),
+        (
+            "checksum section size",
+            read(checksum_pos),
+            footer_pos - checksum_pos,
+        ),
+        (
+            "footer offset",
+            footer_pos,
+            align_up(checksum_names_end, align),
+        ),
+    ]
+    .into_iter()
+    .filter(|&(_, stored, expected)| stored != expected)
+    .map(|(field, stored, expected)| LayoutMismatch {
+        field,
+        stored,
+        expected,
+    })
+    .collect()
+}
+
+/// Which string blob of a table a [`StoredString`] is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringRegion {
+    /// The string data that string values point into.
+    Values,
+    /// The entry names of the checksum section.
+    Names,
+}
+
+/// A string as stored in a table, before any entry refers to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredString {
+    pub region: StringRegion,
+    /// Byte offset of the string within its region.
+    pub offset: usize,
+    pub text: String,
+}
+
+/// Lists every NUL-terminated string of `parsed.bytes` in stored order, first the
+/// value string data and then the checksum names, decoded in `parsed.encoding`. Fails
+/// with [`T2bError::BadString`] on a string that does not decode, at its offset within
+/// the region.
+pub fn stored_strings(parsed: &ParsedT2b) -> Result<Vec<StoredString>, T2bError> {
+    let bytes = &parsed.bytes;
+    let read = |pos| read_u32(bytes, pos, parsed.endian).map_or(0, |v| v as usize);
+    let string_data_offset = read(4);
+    let string_data_end = string_data_offset + read(8);
+    let checksum_pos = checksum_position(bytes, string_data_end, parsed.endian);
+    let names_offset = checksum_pos + read(checksum_pos + 8);
+    let regions = [
+        (StringRegion::Values, string_data_offset, string_data_end),
+        (
+            StringRegion::Names,
+            names_offset,
+            names_offset + read(checksum_pos + 12),
+        ),
+    ];
+
+    let mut strings = Vec::new();
+    for (region, start, end) in regions {
+        let have = bytes.len();
+        let data = bytes
+            .get(start..end)
+            .ok_or_else(|| out_of_range("string data", end, have))?;
+        // Strings have no multi-byte integers, so the byte order does not matter here.
+        let mut reader = Reader::new(data, Endian::Little);
+        while reader.pos() < data.len() {
+            let offset = reader.pos();
+            let text = reader.read_cstr(parsed.encoding)?;
+            strings.push(StoredString {
+                region,
+                offset,
+                text,
+            });
+        }
+    }
+    Ok(strings)
+}
+
+/// NUL-terminated string blob where each distinct string is stored once.
+#[derive(Default)]
+struct StringTable {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+    count: usize,
+}
+
+impl StringTable {
+    fn insert(&mut self, s: &str, enc: StringEncoding) -> Result<u32, T2bError> {
+        if let Some(off) = self.offsets.get(s) {
+            return Ok(*off);
+        }
+        let off = self.data.len() as u32;
+        self.data.extend_from_slice(&encode_string(s, enc)?);
+        self.data.extend_from_slice(enc.terminator());
+        self.offsets.insert(s.to_string(), off);
+        self.count += 1;
+        Ok(off)
+    }
+}
+
+#[derive(Debug)]
+struct RawEntry {
+    crc32: u32,
+    /// End of the type bytes, where the padding up to the 4-byte aligned values starts.
+    types_end: usize,
+    types: Vec<ValueType>,
+    values: Vec<i64>,
+    value_offsets: Vec<usize>,
+}
+
+/// Read-only views needed to decode a raw entry; entries decode independently of each
+/// other.
+struct EntryDecoder<'a> {
+    crc_to_name_offset: &'a HashMap<u32, usize>,
+    checksum_string_data: &'a [u8],
+    value_string_data: &'a [u8],
+    encoding: StringEncoding,
+    value_length: ValueLength,
+    /// Name entries whose name cannot be resolved with [`unresolved_name`].
+    lenient: bool,
+}
+
+/// Name given to entries whose CRC32 has no readable name in lenient parsing.
+fn unresolved_name(crc32: u32) -> String {
+    format!("crc_0x{crc32:08x}")
+}
+
+impl EntryDecoder<'_> {
+    fn name(&self, crc32: u32) -> Result<String, T2bError> {
+        let name_offset = *self
+            .crc_to_name_offset
+            .get(&crc32)
+            .ok_or(T2bError::MissingNameOffset(crc32))?;
+        read_string(self.checksum_string_data, name_offset, self.encoding)
+            .ok_or(T2bError::BadName(crc32))
+    }
+
+    fn decode(&self, raw: &RawEntry) -> Result<Entry, T2bError> {
+        let name = match self.name(raw.crc32) {
+            Ok(name) => name,
+            Err(_) if self.lenient => unresolved_name(raw.crc32),
+            Err(err) => return Err(err),
+        };
+
+        // `try_parse_entries` reads one value and offset per type.
+        if raw.values.len() != raw.types.len() || raw.value_offsets.len() != raw.types.len() {
+            return Err(T2bError::BadEntries);
+        }
+        let mut values = Vec::with_capacity(raw.types.len());
+        for ((typ, &value), &offset) in raw.types.iter().zip(&raw.values).zip(&raw.value_offsets) {
+            let val = match typ {
+                ValueType::String => {
+                    let val_off = value;
+                    let have = self.value_string_data.len();
+                    if val_off < 0 {
+                        ValueData::Str(None)
+                    } else if val_off as u64 >= have as u64 {
+                        // Counted within the string data rather than the table.
+                        return Err(out_of_range("string value", val_off as usize + 1, have));
+                    } else {
+                        let v =
+                            read_string(self.value_string_data, val_off as usize, self.encoding);
+                        ValueData::Str(v)
+                    }
+                }
+                ValueType::Integer => ValueData::Int(value),
+                ValueType::FloatingPoint => match self.value_length {
+                    ValueLength::Int => {
+                        let bits = value as u32;
+                        ValueData::Float(f32::from_bits(bits) as f64)
+                    }
+                    ValueLength::Long => {
+                        let bits = value as u64;
+                        ValueData::Float(f64::from_bits(bits))
+                    }
+                },
+            };
+            values.push(ValueField {
+                typ: *typ,
+                data: val,
+                offset,
+            });
+        }
+
+        Ok(Entry {
+            name,
+            crc32: raw.crc32,
+            values,
+        })
+    }
+}
+
+/// Tables with at least this many entries are decoded in parallel with the `parallel`
+/// feature.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 4096;
+
+#[cfg(feature = "parallel")]
+fn decode_entries(decoder: &EntryDecoder<'_>, raw: &[RawEntry]) -> Result<Vec<Entry>, T2bError> {
+    use rayon::prelude::*;
+
+    if raw.len() < PARALLEL_THRESHOLD {
+        return raw.iter().map(|r| decoder.decode(r)).collect();
+    }
+    // Collecting the per-entry results first keeps both the entry order and the
+    // reported error (the first failing entry) deterministic.
+    let decoded = raw
+        .par_iter()
+        .map(|r| decoder.decode(r))
+        .collect::<Vec<_>>();
+    decoded.into_iter().collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn decode_entries(decoder: &EntryDecoder<'_>, raw: &[RawEntry]) -> Result<Vec<Entry>, T2bError> {
+    raw.iter().map(|r| decoder.decode(r)).collect()
+}
+
+/// Picks the value length under which the entry section parses. Both widths parse on
+/// some tables, e.g. ones with few values; the values that look more plausible under
+/// [`plausibility`] then decide, with ties going to 4-byte values.
+fn detect_value_length(
+    bytes: &[u8],
+    entry_count: usize,
+    string_offset: usize,
+    string_length: usize,
+    value_align: usize,
+    endian: Endian,
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Option<ValueLength> {
+    let parse = |len| {
+        let (count, offset) = (entry_count, string_offset);
+        try_parse_entries(bytes, count, offset, len, value_align, endian, &mut |_| {}).ok()
+    };
+    match (parse(ValueLength::Int), parse(ValueLength::Long)) {
+        (Some(_), None) => Some(ValueLength::Int),
+        (None, Some(_)) => Some(ValueLength::Long),
+        (None, None) => None,
+        (Some((int, _)), Some((long, _))) => {
+            let int_score = plausibility(&int, string_length, ValueLength::Int);
+            let long_score = plausibility(&long, string_length, ValueLength::Long);
+            let chosen = if long_score > int_score {
+                ValueLength::Long
+            } else {
+                ValueLength::Int
+            };
+            trace(format_args!(
+                "both value lengths parse: plausibility Int {int_score}, Long {long_score}; \
+                 using {chosen:?}"
+            ));
+            Some(chosen)
+        }
+    }
+}
+
+/// The value length whose entries before the first bad one look more plausible, for
+/// recovering a table neither width parses completely. Counting the entries instead
+/// would favour whichever width reads more garbage. Ties go to 4-byte values.
+fn recover_value_length(
+    bytes: &[u8],
+    entry_count: usize,
+    string_offset: usize,
+    string_length: usize,
+    value_align: usize,
+    endian: Endian,
+) -> ValueLength {
+    let score = |len| {
+        let entries = match try_parse_entries(
+            bytes,
+            entry_count,
+            string_offset,
+            len,
+            value_align,
+            endian,
+            &mut |_| {},
+        ) {
+            Ok((entries, _)) => entries,
+            Err(stop) => stop.entries,
+        };
+        plausibility(&entries, string_length, len)
+    };
+    if score(ValueLength::Long) > score(ValueLength::Int) {
+        ValueLength::Long
+    } else {
+        ValueLength::Int
+    }
+}
+
+/// Scores values read as `value_length`: each one counts +1 if it looks like what its
+/// type stores and -1 otherwise. Plausible are string offsets inside the string data
+/// (or -1, unset), integers within 32 bits, and floats that are zero or between 1e-9
+/// and 1e9 in magnitude.
+fn plausibility(entries: &[RawEntry], string_length: usize, value_length: ValueLength) -> i64 {
+    let plausible_float = |f: f64| f == 0.0 || (1e-9..=1e9).contains(&f.abs());
+    entries
+        .iter()
+        .flat_map(|entry| entry.types.iter().zip(&entry.values))
+        .map(|(typ, &value)| {
+            let plausible = match typ {
+                ValueType::String => value == -1 || (0..string_length as i64).contains(&value),
+                ValueType::Integer => i32::try_from(value).is_ok(),
+                ValueType::FloatingPoint => match value_length {
+                    ValueLength::Int => plausible_float(f32::from_bits(value as u32) as f64),
+                    ValueLength::Long => plausible_float(f64::from_bits(value as u64)),
+                },
+            };
+            if plausible {
+                1
+            } else {
+                -1
+            }
+        })
+        .sum()
+}
+
+fn parse_entries(
+    bytes: &[u8],
+    entry_count: usize,
+    string_offset: usize,
+    value_length: ValueLength,
+    value_align: usize,
+    endian: Endian,
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Result<(Vec<RawEntry>, usize), EntryStop> {
+    try_parse_entries(
+        bytes,
+        entry_count,
+        string_offset,
+        value_length,
+        value_align,
+        endian,
+        trace,
+    )
+}
+
+/// Where and why [`try_parse_entries`] stopped, with the entries read before.
+struct EntryStop {
+    entries: Vec<RawEntry>,
+    /// Start of the entry that could not be read, or the end of the entries when they
+    /// all parse but do not end next to the string data.
+    pos: usize,
+    reason: String,
+    /// The value count of the entry, when it declares more values than could fit.
+    value_count: Option<u8>,
+}
+
+/// The [`T2bError::BadValueCount`] for the first entry whose value count cannot fit
+/// before the string data at 4-byte values, in which case no value length or alignment
+/// parses the entries. The entries before it are read with the default layout.
+fn value_count_error(
+    bytes: &[u8],
+    entry_count: usize,
+    string_offset: usize,
+    endian: Endian,
+) -> Option<T2bError> {
+    let (len, align) = (ValueLength::Int, Alignment::default().values);
+    let stop = try_parse_entries(
+        bytes,
+        entry_count,
+        string_offset,
+        len,
+        align,
+        endian,
+        &mut |_| {},
+    )
+    .err()?;
+    Some(T2bError::BadValueCount {
+        entry: stop.entries.len(),
+        offset: stop.pos,
+        count: stop.value_count?,
+    })
+}
+
+fn try_parse_entries(
+    bytes: &[u8],
+    entry_count: usize,
+    string_offset: usize,
+    value_length: ValueLength,
+    value_align: usize,
+    endian: Endian,
+    trace: &mut dyn FnMut(fmt::Arguments<'_>),
+) -> Result<(Vec<RawEntry>, usize), EntryStop> {
+    // Every entry takes at least 8 bytes, so an untrusted count cannot over-allocate.
+    let mut entries = Vec::with_capacity(entry_count.min(string_offset / 8));
+    // Entries may not run into the string data.
+    let mut reader = Reader::new(bytes.get(..string_offset).unwrap_or(bytes), endian);
+    reader.seek(0x10); // after entry header
+
+    for i in 0..entry_count {
+        let entry_pos = reader.pos();
+        let stop = |entries, reason: &str| EntryStop {
+            entries,
+            pos: entry_pos,
+            reason: reason.to_string(),
+            value_count: None,
+        };
+        let Ok(crc32) = reader.read_u32() else {
+            return Err(stop(entries, "entry header runs into the string data"));
+        };
+        let Ok(value_count) = reader.read_u8() else {
+            return Err(stop(entries, "entry header runs into the string data"));
+        };
+        // A corrupt count is caught here rather than partway through its values.
+        let needed =
+            (value_count as usize).div_ceil(4) + value_count as usize * value_length as usize;
+        let remaining = string_offset.saturating_sub(reader.pos());
+        if needed > remaining {
+            let reason = format!(
+                "value count {value_count} needs {needed} bytes, only {remaining} remain \
+                 before the string data"
+            );
+            return Err(EntryStop {
+                value_count: Some(value_count),
+                ..stop(entries, &reason)
+            });
+        }
+
+        let mut types = Vec::with_capacity(value_count as usize);
+        for j in (0..value_count).step_by(4) {
+            let Ok(type_chunk) = reader.read_u8() else {
+                return Err(stop(entries, "value types run into the string data"));
+            };
+            for h in 0..4 {
+                if j + h >= value_count {
+                    break;
+                }
+                types.push(match (type_chunk >> (h * 2)) & 0x3 {
+                    0 => ValueType::String,
+                    1 => ValueType::Integer,
+                    2 => ValueType::FloatingPoint,
+                    _ => return Err(stop(entries, "unknown value type 3")),
+                });
+            }
+        }
+
+        let types_end = reader.pos();
+        reader.align(value_align);
+
+        let mut values = Vec::with_capacity(types.len());
+        let mut value_offsets = Vec::with_capacity(types.len());
+        for _ in 0..types.len() {
+            value_offsets.push(reader.pos());
+            let v = match value_length {
+                ValueLength::Int => reader.read_i32().map(i64::from),
+                ValueLength::Long => reader.read_i64(),
+            };
+            let Ok(v) = v else {
+                return Err(stop(entries, "values run into the string data"));
+            };
+            values.push(v);
+        }
+
+        trace(format_args!(
+            "entry {i} at 0x{entry_pos:x}: crc32 {crc32:08x}, {value_count} values"
+        ));
+        entries.push(RawEntry {
+            crc32,
+            types_end,
+            types,
+            values,
+            value_offsets,
+        });
+    }
+
+    let pos = reader.pos();
+    if pos > string_offset || string_offset.saturating_sub(pos) >= 0x10 {
+        return Err(EntryStop {
+            entries,
+            pos,
+            reason: format!(
+                "entries end at 0x{pos:x}, too far before the string data at 0x{string_offset:x}"
+            ),
+            value_count: None,
+        });
+    }
+    trace(format_args!("entries end at 0x{pos:x}"));
+
+    Ok((entries, pos))
+}
+
+/// A cursor over table bytes. Each read checks bounds against `data` and advances `pos`
+/// past what it read; a failed read leaves `pos` unchanged.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    endian: Endian,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], endian: Endian) -> Self {
+        Reader {
+            data,
+            pos: 0,
+            endian,
+        }
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: usize) -> &mut Self {
+        self.pos = pos;
+        self
+    }
+
+    /// Moves `pos` forward to the next multiple of `align`, which must be a power of two.
+    fn align(&mut self, align: usize) {
+        self.pos = align_up(self.pos, align);
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], T2bError> {
+        let bytes = read_array(self.data, self.pos)
+            .ok_or_else(|| out_of_range("table", self.pos.saturating_add(N), self.data.len()))?;
+        self.pos += N;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, T2bError> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, T2bError> {
+        let bytes = self.take()?;
+        Ok(match self.endian {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn read_i32(&mut self) -> Result<i32, T2bError> {
+        self.read_u32().map(|v| v as i32)
+    }
+
+    fn read_i64(&mut self) -> Result<i64, T2bError> {
+        let bytes = self.take()?;
+        Ok(match self.endian {
+            Endian::Little => i64::from_le_bytes(bytes),
+            Endian::Big => i64::from_be_bytes(bytes),
+        })
+    }
+
+    /// Reads a string up to its NUL terminator, or to the end of `data` if it has none,
+    /// and moves `pos` past the terminator.
+    fn read_cstr(&mut self, enc: StringEncoding) -> Result<String, T2bError> {
+        let tail = self
+            .data
+            .get(self.pos..)
+            .filter(|tail| !tail.is_empty())
+            .ok_or_else(|| out_of_range("string", self.pos.saturating_add(1), self.data.len()))?;
+        // The terminator is a NUL code unit; zero bytes also occur inside UTF-16 characters.
+        let unit = enc.terminator().len();
+        let len = tail
+            .chunks_exact(unit)
+            .position(|chunk| chunk == enc.terminator())
+            .map_or(tail.len() - tail.len() % unit, |n| n * unit);
+        let raw = &tail[..len];
+        let decoded = match enc {
+            StringEncoding::Utf8 => std::str::from_utf8(raw).ok().map(str::to_string),
+            StringEncoding::Sjis => SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(raw)
+                .map(|s| s.into_owned()),
+            StringEncoding::Utf16Le => {
+                let units = raw
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .ok()
+            }
+        };
+        let string = decoded.ok_or(T2bError::BadString { offset: self.pos })?;
+        self.pos += (len + unit).min(tail.len());
+        Ok(string)
+    }
+}
+
+pub fn read_string(data: &[u8], offset: usize, enc: StringEncoding) -> Option<String> {
+    // Strings have no multi-byte integers, so the byte order does not matter here.
+    Reader::new(data, Endian::Little)
+        .seek(offset)
+        .read_cstr(enc)
+        .ok()
+}
+
+fn encode_string(s: &str, enc: StringEncoding) -> Result<Vec<u8>, T2bError> {
+    match enc {
+        StringEncoding::Utf8 => Ok(s.as_bytes().to_vec()),
+        StringEncoding::Sjis => {
+            let (bytes, _, unmappable) = SHIFT_JIS.encode(s);
+            if unmappable {
+                return Err(T2bError::Unencodable(s.to_string()));
+            }
+            Ok(bytes.into_owned())
+        }
+        StringEncoding::Utf16Le => Ok(utf16le_bytes(s)),
+    }
+}
+
+fn utf16le_bytes(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn pad_to(out: &mut Vec<u8>, align: usize, pad: u8) {
+    out.resize(align_up(out.len(), align), pad);
+}
+
+/// Like [`pad_to`], but appends `stored` instead when it is exactly the gap needed.
+fn pad_or_reuse(out: &mut Vec<u8>, align: usize, pad: u8, stored: &[u8]) {
+    if align_up(out.len(), align) - out.len() == stored.len() {
+        out.extend_from_slice(stored);
+    } else {
+        pad_to(out, align, pad);
+    }
+}
+
+fn write_u32(data: &mut [u8], offset: usize, endian: Endian, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&endian.u32_bytes(value));
+}
+
+pub fn align_up(pos: usize, align: usize) -> usize {
+    (pos + (align - 1)) & !(align - 1)
+}
+
+pub fn read_u32(data: &[u8], offset: usize, endian: Endian) -> Option<u32> {
+    let bytes = read_array(data, offset)?;
+    Some(match endian {
+        Endian::Little => u32::from_le_bytes(bytes),
+        Endian::Big => u32::from_be_bytes(bytes),
+    })
+}
+
+pub fn read_i32(data: &[u8], offset: usize, endian: Endian) -> Option<i32> {
+    read_u32(data, offset, endian).map(|v| v as i32)
+}
+
+pub fn read_i16(data: &[u8], offset: usize, endian: Endian) -> Option<i16> {
+    let bytes = read_array(data, offset)?;
+    Some(match endian {
+        Endian::Little => i16::from_le_bytes(bytes),
+        Endian::Big => i16::from_be_bytes(bytes),
+    })
+}
+
+pub fn read_i64(data: &[u8], offset: usize, endian: Endian) -> Option<i64> {
+    let bytes = read_array(data, offset)?;
+    Some(match endian {
+        Endian::Little => i64::from_le_bytes(bytes),
+        Endian::Big => i64::from_be_bytes(bytes),
+    })
+}
+
+/// Copies `N` bytes at `offset`, or `None` if they run past the end of `data`.
+fn read_array<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &[u8] = include_bytes!("self_test/original.cfg.bin");
+    const PATCHED: &[u8] = include_bytes!("self_test/patched.cfg.bin");
+
+    #[test]
+    fn serialize_round_trips_fixtures() {
+        for fixture in [ORIGINAL, PATCHED] {
+            let parsed = parse_t2b(fixture).unwrap();
+            assert_eq!(serialize(&parsed).unwrap(), fixture);
+        }
+    }
+
+    #[test]
+    fn crc32_of_name_matches_stored_checksums() {
+        let parsed = parse_t2b(ORIGINAL).unwrap();
+        assert!(crc_mismatches(&parsed).is_empty());
+        assert!(!parsed.name_crcs.is_empty());
+        for (name, &crc) in &parsed.name_crcs {
+            assert_eq!(crc32_of_name(name, parsed.encoding), crc, "{name}");
+        }
+    }
+
+    #[test]
+    fn serialize_rebuilds_checksums_in_stored_order() {
+        let mut parsed = parse_t2b(ORIGINAL).unwrap();
+        let crcs = parsed
+            .checksum_names
+            .iter()
+            .map(|name| parsed.name_crcs[name])
+            .collect::<Vec<_>>();
+        assert!(!crcs.is_sorted(), "fixture checksums are in CRC order");
+        // Re-declaring the same encoding drops the stored section, so it is rebuilt.
+        parsed.set_encoding(parsed.encoding);
+        assert!(parsed.checksum_section.is_empty());
+        assert_eq!(serialize(&parsed).unwrap(), ORIGINAL);
+    }
+
+    /// Each entry's name and values, with the values in `Debug` form as `ValueData` has
+    /// no `PartialEq`.
+    fn contents(parsed: &ParsedT2b) -> Vec<(String, String)> {
+        let values = |entry: &Entry| {
+            let data = entry.values.iter().map(|v| &v.data).collect::<Vec<_>>();
+            format!("{data:?}")
+        };
+        parsed
+            .entries
+            .iter()
+            .map(|entry| (entry.name.clone(), values(entry)))
+            .collect()
+    }
+
+    /// `table` with every field in big-endian byte order.
+    fn big_endian(table: &[u8]) -> Vec<u8> {
+        let mut parsed = parse_t2b(table).unwrap();
+        parsed.endian = Endian::Big;
+        parsed.checksum_section.clear();
+        parsed.footer[0..4].reverse();
+        let at = FOOTER_FORMATS[0].encoding_offset;
+        parsed.footer[at..at + 2].reverse();
+        serialize(&parsed).unwrap()
+    }
+
+    #[test]
+    fn big_endian_tables_parse_and_round_trip() {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        let entries = [
+            (
+                "CPK_HEAD".to_string(),
+                vec![text("v1"), ValueData::Float(0.5)],
+            ),
+            (
+                "CPK_ITEM".to_string(),
+                vec![
+                    text("data/"),
+                    text("a.bin"),
+                    ValueData::Int(-2),
+                    ValueData::Int(70000),
+                ],
+            ),
+        ];
+        for value_length in [ValueLength::Int, ValueLength::Long] {
+            let little = build_minimal_table(&entries, value_length, StringEncoding::Sjis).unwrap();
+            let big = big_endian(&little);
+            assert_ne!(big, little);
+
+            let parsed = parse_t2b(&big).unwrap();
+            assert_eq!(parsed.endian, Endian::Big);
+            assert_eq!(parsed.value_length, value_length);
+            assert!(matches!(parsed.encoding, StringEncoding::Sjis));
+            assert_eq!(contents(&parsed), contents(&parse_t2b(&little).unwrap()));
+            assert!(crc_mismatches(&parsed).is_empty());
+            assert_eq!(serialize(&parsed).unwrap(), big);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_types_without_values() {
+        let names = HashMap::from([(1, 0)]);
+        let decoder = EntryDecoder {
+            crc_to_name_offset: &names,
+            checksum_string_data: b"CPK_ITEM\0",
+            value_string_data: &[],
+            encoding: StringEncoding::Utf8,
+            value_length: ValueLength::Int,
+            lenient: false,
+        };
+        let raw = RawEntry {
+            crc32: 1,
+            types_end: 0,
+            types: vec![ValueType::Integer, ValueType::Integer],
+            values: vec![7],
+            value_offsets: vec![0x18, 0x1c],
+        };
+        assert!(matches!(decoder.decode(&raw), Err(T2bError::BadEntries)));
+    }
+
+    #[test]
+    fn parse_rejects_string_offsets_past_the_string_data() {
+        let mut table = ORIGINAL.to_vec();
+        // The prefix of the first `CPK_ITEM`, pointed at the end of the 0x2a bytes of
+        // string data.
+        table[0x28..0x2c].copy_from_slice(&0x2au32.to_le_bytes());
+        assert!(matches!(
+            parse_t2b(&table),
+            Err(T2bError::OutOfRange {
+                section: "string value",
+                needed: 0x2b,
+                have: 0x2a,
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_truncated_checksum_sections() {
+        let mut table = ORIGINAL.to_vec();
+        // The checksum entry count, far more than fit before the footer.
+        table[0xd4..0xd8].copy_from_slice(&0x100u32.to_le_bytes());
+        assert!(matches!(
+            parse_t2b(&table),
+            Err(T2bError::Truncated {
+                section: "checksum entries",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_value_counts_that_do_not_fit() {
+        let table = include_bytes!("self_test/bad_value_count.cfg.bin");
+        assert!(matches!(
+            parse_t2b(table),
+            Err(T2bError::BadValueCount { .. })
+        ));
+    }
+
+    #[test]
+    fn recover_keeps_the_entries_before_a_bad_value_count() {
+        let table = include_bytes!("self_test/bad_value_count.cfg.bin");
+        let options = ParseOptions {
+            recover: true,
+            ..ParseOptions::default()
+        };
+        let parsed = parse_t2b_with(table, options, &mut |_| {}).unwrap();
+        assert!(matches!(parsed.value_length, ValueLength::Int));
+        assert_eq!(parsed.entries.len(), 2);
+        let recovery = parsed.recovery.unwrap();
+        assert_eq!((recovery.entry, recovery.offset), (2, 0x40));
+    }
+
+    #[test]
+    fn utf16le_tables_count_offsets_in_bytes_and_round_trip() {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        // `a\u{100}` is stored as 61 00 00 01: a zero byte pair that is not a code unit.
+        let entries = [(
+            "CPK_ITEM".to_string(),
+            vec![text("a\u{100}"), text("\u{1f600}b"), ValueData::Int(5)],
+        )];
+        let table =
+            build_minimal_table(&entries, ValueLength::Int, StringEncoding::Utf16Le).unwrap();
+        let parsed = parse_t2b(&table).unwrap();
+        assert!(matches!(parsed.encoding, StringEncoding::Utf16Le));
+        let entry = &parsed.entries[0];
+        assert_eq!(entry.string(0), Some("a\u{100}"));
+        assert_eq!(entry.string(1), Some("\u{1f600}b"));
+        assert_eq!(entry.integer(2), Some(5));
+        assert!(crc_mismatches(&parsed).is_empty());
+
+        let offset = |i: usize| read_i32(&table, entry.values[i].offset, Endian::Little).unwrap();
+        // Two code units and a NUL unit, in bytes.
+        assert_eq!((offset(0), offset(1)), (0, 6));
+        let start = read_u32(&table, 4, Endian::Little).unwrap() as usize;
+        let length = read_u32(&table, 8, Endian::Little).unwrap() as usize;
+        assert_eq!(length, 6 + 8);
+        let data = &table[start..start + length];
+        assert_eq!(&data[..6], [0x61, 0, 0, 1, 0, 0]);
+        assert_eq!(&data[12..], [0, 0]);
+
+        assert_eq!(serialize(&parsed).unwrap(), table);
+    }
+
+    #[test]
+    fn build_minimal_table_parses_back_in_every_layout() {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        let entries = [
+            (
+                "CPK_HEAD".to_string(),
+                vec![text("テスト"), ValueData::Float(0.5), ValueData::Str(None)],
+            ),
+            (
+                "CPK_ITEM".to_string(),
+                vec![text("data/"), text("a.bin"), ValueData::Int(-3)],
+            ),
+            (
+                "CPK_ITEM".to_string(),
+                vec![text("data/"), ValueData::Int(9)],
+            ),
+        ];
+        let expected = entries
+            .iter()
+            .map(|(name, values)| {
+                (
+                    name.clone(),
+                    format!("{:?}", values.iter().collect::<Vec<_>>()),
+                )
+            })
+            .collect::<Vec<_>>();
+        for value_length in [ValueLength::Int, ValueLength::Long] {
+            for enc in [
+                StringEncoding::Sjis,
+                StringEncoding::Utf8,
+                StringEncoding::Utf16Le,
+            ] {
+                let table = build_minimal_table(&entries, value_length, enc).unwrap();
+                let parsed = parse_t2b(&table).unwrap();
+                let layout = format!("{value_length:?} {enc:?}");
+                assert_eq!(parsed.value_length, value_length, "{layout}");
+                assert_eq!(contents(&parsed), expected, "{layout}");
+                assert!(crc_mismatches(&parsed).is_empty(), "{layout}");
+                assert!(layout_mismatches(&parsed).is_empty(), "{layout}");
+            }
+        }
+    }
+
+    /// A table with 8-byte section alignment, zero padding, bytes between the checksum
+    /// names and the footer, and a footer whose last field is set.
+    fn unusual_layout_table() -> Vec<u8> {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        let entries = [
+            (
+                "CPK_HEAD".to_string(),
+                vec![text("abc"), ValueData::Int(1), ValueData::Int(2)],
+            ),
+            ("CPK_ITEM".to_string(), vec![text("data/"), text("a.bin")]),
+        ];
+        let table = build_minimal_table(&entries, ValueLength::Int, StringEncoding::Utf8);
+        let mut parsed = parse_t2b(&table.unwrap()).unwrap();
+        parsed.alignment.sections = 8;
+        parsed.padding = 0;
+        parsed.checksum_section.clear();
+        parsed.opaque.after_checksum = vec![0xde, 0xad, 0xbe, 0xef];
+        parsed.footer[0xc..].copy_from_slice(&[1, 2, 3, 4]);
+        serialize(&parsed).unwrap()
+    }
+
+    #[test]
+    fn serialize_keeps_unusual_padding_and_trailing_bytes() {
+        let table = unusual_layout_table();
+        let parsed = parse_t2b(&table).unwrap();
+        assert_eq!(parsed.alignment.sections, 8);
+        assert!(parsed.alignment.regular);
+        assert_eq!(parsed.padding, 0);
+        assert_eq!(parsed.opaque.after_checksum, [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(parsed.footer[0xc..], [1, 2, 3, 4]);
+        assert_eq!(serialize(&parsed).unwrap(), table);
+    }
+
+    #[test]
+    fn normalize_path_strips_quotes_and_whitespace() {
+        assert_eq!(normalize_path("\"foo\"", ""), normalize_path("foo", ""));
+        assert_eq!(normalize_path("\"data/\"", "\"a.bin\""), "data/a.bin");
+        assert_eq!(normalize_path(" data/ ", " \" a.bin \" "), "data/a.bin");
+        assert_eq!(normalize_path("data\\sub\\", "a.bin"), "data/sub/a.bin");
+        assert_eq!(
+            normalize_path_with("data/sub/", "a.bin", PathSep::Win),
+            "data\\sub\\a.bin"
+        );
+    }
+
+    #[test]
+    fn normalize_path_treats_empty_suffix_variants_alike() {
+        let bare = normalize_path("data/", "");
+        for suffix in ["\"\"", " ", "\" \"", "\"", "  \"\"  "] {
+            assert_eq!(normalize_path("data/", suffix), bare, "suffix {suffix:?}");
+        }
+        assert_eq!(normalize_path("\"data/\"", "\"\""), "data/");
+    }
+
+    #[test]
+    fn is_empty_string_ignores_quotes() {
+        let field = |data| ValueField {
+            typ: ValueType::String,
+            data,
+            offset: 0,
+        };
+        let text = |s: &str| field(ValueData::Str(Some(s.to_string())));
+        assert!(is_empty_string(&field(ValueData::Str(None))));
+        assert!(is_empty_string(&text("")));
+        assert!(is_empty_string(&text("\"\"")));
+        assert!(!is_empty_string(&text("x")));
+        assert!(!is_empty_string(&text("\"x\"")));
+        assert!(!is_empty_string(&ValueField {
+            typ: ValueType::Integer,
+            data: ValueData::Int(0),
+            offset: 0,
+        }));
+    }
+
+    fn item(prefix: &str, suffix: &str, marks: &str, size: i64) -> (String, Vec<ValueData>) {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        let values = vec![
+            text(prefix),
+            text(suffix),
+            text(marks),
+            text(marks),
+            ValueData::Int(size),
+        ];
+        ("CPK_ITEM".to_string(), values)
+    }
+
+    #[test]
+    fn sync_with_matches_quoted_paths_and_empty_fields() {
+        let a = build_minimal_table(
+            &[item("data/", "a.bin", "x", 100)],
+            ValueLength::Int,
+            StringEncoding::Utf8,
+        )
+        .unwrap();
+        let b = build_minimal_table(
+            &[item("\"data/\"", "\"a.bin\"", "\"\"", 111)],
+            ValueLength::Int,
+            StringEncoding::Utf8,
+        )
+        .unwrap();
+        let (a, b) = (parse_t2b(&a).unwrap(), parse_t2b(&b).unwrap());
+        let mut updates = Vec::new();
+        let synced = sync_with(&a, &b, |path, old, new| {
+            updates.push((path.to_string(), old, new))
+        })
+        .unwrap();
+        assert_eq!(updates, [("data/a.bin".to_string(), 100, 111)]);
+        assert_eq!(parse_t2b(&synced).unwrap().entries[0].integer(4), Some(111));
+    }
+
+    #[test]
+    fn sync_with_keys_uses_entry_names_separators_and_float_sizes() {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        let row = |name: &str, prefix: &str, size| {
+            let values = vec![text(prefix), text("a.bin"), text(""), text(""), size];
+            (name.to_string(), values)
+        };
+        let a = [
+            row("FILE", "data/", ValueData::Int(1)),
+            row("CPK_ITEM", "data/", ValueData::Int(2)),
+        ];
+        let b = [row("FILE", "data\\", ValueData::Float(7.0))];
+        let build = |entries: &[(String, Vec<ValueData>)]| {
+            let table = build_minimal_table(entries, ValueLength::Int, StringEncoding::Utf8);
+            parse_t2b(&table.unwrap()).unwrap()
+        };
+        let (a, b) = (build(&a), build(&b));
+
+        let keys = PathKeys {
+            names: vec!["FILE".to_string()],
+            sep: PathSep::Unix,
+        };
+        let mut updates = Vec::new();
+        let synced = sync_with_keys(&a, &b, &keys, |path, old, new| {
+            updates.push((path.to_string(), old, new))
+        })
+        .unwrap();
+        // An integer field takes no float size.
+        assert!(updates.is_empty());
+        assert_eq!(synced, a.bytes);
+
+        let b = build(&[row("FILE", "data\\", ValueData::Str(Some("\"9\"".into())))]);
+        let synced = sync_with_keys(&a, &b, &keys, |path, old, new| {
+            updates.push((path.to_string(), old, new))
+        })
+        .unwrap();
+        assert_eq!(updates, [("data/a.bin".to_string(), 1, 9)]);
+        let synced = parse_t2b(&synced).unwrap();
+        assert_eq!(synced.entries[0].integer(4), Some(9));
+        assert_eq!(synced.entries[1].integer(4), Some(2));
+        // With separators kept, `data\` does not match `data/`.
+        let kept = PathKeys {
+            sep: PathSep::Keep,
+            ..keys
+        };
+        let unchanged = sync_with_keys(&a, &b, &kept, |_, _, _| panic!("no update")).unwrap();
+        assert_eq!(unchanged, a.bytes);
+    }
+
+    #[test]
+    fn find_by_path_with_keys_finds_custom_entry_names() {
+        let text = |s: &str| ValueData::Str(Some(s.to_string()));
+        let row = |name: &str, prefix: &str, size| {
+            let values = vec![text(prefix), text("a.bin"), text(""), text(""), size];
+            (name.to_string(), values)
+        };
+        let entries = [
+            row("CPK_ITEM", "data/", ValueData::Int(1)),
+            row("FILE", "data\\", ValueData::Int(2)),
+        ];
+        let table = build_minimal_table(&entries, ValueLength::Int, StringEncoding::Utf8);
+        let parsed = parse_t2b(&table.unwrap()).unwrap();
+
+        let keys = PathKeys {
+            names: vec!["FILE".to_string()],
+            sep: PathSep::Unix,
+        };
+        let names = |items: Vec<&Entry>| items.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(parsed.cpk_items_with_keys(&keys).collect()), ["FILE"]);
+        assert_eq!(names(parsed.cpk_items().collect()), ["CPK_ITEM"]);
+        let found = parsed.find_by_path_with_keys("data/a.bin", &keys).unwrap();
+        assert_eq!((found.name.as_str(), found.integer(4)), ("FILE", Some(2)));
+        assert_eq!(
+            parsed.find_by_path("data/a.bin").unwrap().integer(4),
+            Some(1)
+        );
+
+        let kept = PathKeys {
+            sep: PathSep::Keep,
+            ..keys
+        };
+        assert!(parsed.find_by_path_with_keys("data/a.bin", &kept).is_none());
+        assert!(parsed
+            .find_by_path_with_keys("data\\a.bin", &kept)
+            .is_some());
+    }
+}
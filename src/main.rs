@@ -1,604 +1,2749 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use cpk_size_sync::formats::{
+    apply_string_edits, diff_tables, json_string, parse_csv, parse_schema, parse_size_edits,
+    parse_size_rows, parse_strings_file, strings_file, to_csv, to_dump, to_json, unified_hunks,
+    Schema,
+};
+use cpk_size_sync::sync::{
+    add_missing, check_all_updated, collect_patched, guess_size_index, sort_entries, sort_table,
+    sync_sizes, verify_updates, write_edits, write_sizes, write_strings, IndexSync, MatchBy,
+    OnDuplicate, Patched, SizeWrite, SyncError, SyncLog, SyncOptions, TableSync, UnknownSize,
+    UNKNOWN_SIZE,
+};
+use cpk_size_sync::{
+    crc32_of_name, crc_mismatches, is_patched, layout_mismatches, parse_t2b, parse_t2b_owned,
+    parse_t2b_with, read_header, serialize, stored_strings, ParseOptions, ParsedT2b, PathKeys,
+    PathSep, Size, StringEncoding, T2bError, ValueLength,
+};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+#[cfg(feature = "cpk")]
+use memmap2::Mmap;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+/// Exit statuses, so scripts can tell why a run failed.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_PARSE: i32 = 2;
+const EXIT_NO_MATCHES: i32 = 3;
+const EXIT_IO: i32 = 4;
+const EXIT_STRICT: i32 = 5;
+
+/// ANSI SGR codes used by [`Cli::paint`].
+const BOLD: &str = "1";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const CYAN: &str = "36";
+
+/// Prints to stderr when the verbosity of `cli` is at least `level`.
+macro_rules! verbose {
+    ($cli:expr, $level:expr, $($arg:tt)*) => {
+        if $cli.verbosity >= $level {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// An error that ends the run, with the exit status it ends it with.
+#[derive(Debug)]
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Prefixes the message with what was being done, keeping the exit status.
+    fn context(self, what: impl fmt::Display) -> Self {
+        Failure {
+            message: format!("{what}: {}", self.message),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::new(EXIT_FAILURE, message)
+    }
+}
+
+/// Gives a sync error its exit status, first printing the entries it lists to stderr.
+impl From<SyncError> for Failure {
+    fn from(err: SyncError) -> Self {
+        let code = match &err {
+            SyncError::NoSizes { .. } => EXIT_NO_MATCHES,
+            SyncError::Strict(_) => EXIT_STRICT,
+            SyncError::NotUpdated(problems) => {
+                for problem in problems {
+                    eprintln!("Not updated: {problem}");
+                }
+                EXIT_STRICT
+            }
+            SyncError::Duplicates { table, keys } => {
+                for (key, count) in keys {
+                    eprintln!("Duplicate: {key} occurs {count} times in {table}");
+                }
+                EXIT_FAILURE
+            }
+            SyncError::Field(_) | SyncError::Table { .. } => EXIT_FAILURE,
+        };
+        Failure::new(code, err.to_string())
+    }
+}
+
+/// Settings every command shares, taken from the command line, and what the run has
+/// gathered along the way.
+struct Cli {
+    /// `1` reports per-entry match decisions, `2` also traces parsing.
+    verbosity: u8,
+    /// Set by `-q`/`--quiet` to hide warnings and the sync progress indicator.
+    quiet: bool,
+    /// Whether `dump` and `diff` color their output; see `--color`.
+    color: bool,
+    /// Set by `--output-format json`: stdout then only carries the JSON summary of a sync.
+    json_summary: bool,
+    /// Set by `--print-hash` to print the SHA-256 of every table written.
+    print_hash: bool,
+    /// Set by `--timings` to print how long each phase of a sync took.
+    timings: bool,
+    /// Parse overrides from the command line, applied to every table read.
+    parse: ParseOptions,
+    /// Path rows and the separator form of the paths entries are matched on; see
+    /// `--entry-name` and `--path-sep`.
+    keys: PathKeys,
+    /// String encoding every written table is converted to; see `--out-encoding`.
+    out_encoding: Option<StringEncoding>,
+    /// Member read from `.cpk` inputs; see `--cpk-member`.
+    #[cfg_attr(not(feature = "cpk"), allow(dead_code))]
+    cpk_member: String,
+    /// Value index names from `--schema`, used to label dumps and exports.
+    schema: Schema,
+    /// `(subject, reason)` of every [`Cli::warn`]ing while a sync collects them for its
+    /// JSON summary; `None` while warnings go to stderr.
+    warnings: RefCell<Option<Vec<(String, String)>>>,
+    /// Wall-clock time of each phase [`Cli::timed`] measured, in the order the phases
+    /// first ran.
+    phase_times: RefCell<Vec<(&'static str, Duration)>>,
+    /// Set once any input was gzip-compressed, so that a `.gz` output is compressed too.
+    gzip_input: Cell<bool>,
+    /// The progress bar of the size loop running now.
+    progress: RefCell<Option<Progress>>,
+}
+
+fn main() {
+    let mut raw_args = std::env::args();
+    let bin_name = raw_args
+        .next()
+        .and_then(|p| {
+            std::path::Path::new(&p)
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+        })
+        .unwrap_or_else(|| "cpk_size_sync".into());
+    let mut args = raw_args.collect::<Vec<_>>();
+
+    // Before the parse overrides are read, so that they cannot apply to the self-test.
+    if args.iter().any(|a| a == "--self-test") {
+        or_exit(run_self_test().map_err(Failure::from));
+        return;
+    }
+
+    let filter = take_filter(&bin_name, &mut args);
+    let schema = take_schema(&bin_name, &mut args);
+    let sort_by = take_sort_by(&bin_name, &mut args);
+    let parse = take_parse_options(&bin_name, &mut args);
+    let out_encoding = take_out_encoding(&bin_name, &mut args);
+    let sep = take_path_sep(&bin_name, &mut args);
+    let names = take_entry_names(&bin_name, &mut args);
+    let cpk_member = take_cpk_member(&bin_name, &mut args);
+    let color = take_color(&bin_name, &mut args);
+    let json_summary = take_output_format(&bin_name, &mut args);
+
+    // CPK_DEBUG is kept as an alias for -vv.
+    let mut verbosity = if std::env::var("CPK_DEBUG").is_ok() {
+        2
+    } else {
+        0
+    };
+    let (mut quiet, mut print_hash, mut timings) = (false, false, false);
+    args.retain(|a| match a.as_str() {
+        "-v" | "--verbose" => {
+            verbosity += 1;
+            false
+        }
+        "-vv" => {
+            verbosity += 2;
+            false
+        }
+        "-q" | "--quiet" => {
+            quiet = true;
+            false
+        }
+        "--print-hash" => {
+            print_hash = true;
+            false
+        }
+        "--timings" => {
+            timings = true;
+            false
+        }
+        _ => true,
+    });
+    let cli = Cli {
+        verbosity,
+        quiet,
+        color,
+        json_summary,
+        print_hash,
+        timings,
+        parse,
+        keys: PathKeys { names, sep },
+        out_encoding,
+        cpk_member,
+        schema,
+        warnings: RefCell::new(None),
+        phase_times: RefCell::new(Vec::new()),
+        gzip_input: Cell::new(false),
+        progress: RefCell::new(None),
+    };
+
+    if args.iter().any(|a| a == "-V" || a == "--version") {
+        print_version(&bin_name);
+        std::process::exit(0);
+    }
+
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        print_usage(&bin_name);
+        std::process::exit(0);
+    }
+
+    if args[0] == "dump" {
+        let paths = command_paths(&bin_name, "dump", &args[1..], 1, 1);
+        let mut parsed = or_exit(cli.read_t2b(&paths[0]));
+        apply_filter(&mut parsed, filter.as_ref(), MatchBy::Path, &cli.keys);
+        for line in to_dump(&parsed, &cli.schema).lines() {
+            println!("{}", cli.paint_dump_line(line));
+        }
+        return;
+    }
+
+    if args[0] == "dump-json" {
+        let paths = command_paths(&bin_name, "dump-json", &args[1..], 1, 1);
+        let mut parsed = or_exit(cli.read_t2b(&paths[0]));
+        apply_filter(&mut parsed, filter.as_ref(), MatchBy::Path, &cli.keys);
+        print!("{}", to_json(&parsed, &cli.schema));
+        return;
+    }
+
+    if args[0] == "inspect" {
+        let paths = command_paths(&bin_name, "inspect", &args[1..], 1, 1);
+        or_exit(run_inspect(&cli, &paths[0]));
+        return;
+    }
+
+    if args[0] == "apply-json" {
+        let (mut opts, rest) = edit_args(&cli, &bin_name, &args[1..]);
+        cli.apply_schema(&mut opts, false);
+        let paths = command_paths(&bin_name, "apply-json", &rest, 3, 2);
+        let updated = or_exit(apply_json(&cli, &paths[0], &paths[1], &paths[2], &opts));
+        cli.print_summary(
+            &paths[2],
+            &format!("Updated {updated} entries. Output: {}", paths[2].display()),
+        );
+        return;
+    }
+
+    if args[0] == "export-csv" {
+        let paths = command_paths(&bin_name, "export-csv", &args[1..], 2, 1);
+        let rows = or_exit(export_csv(&cli, &paths[0], &paths[1], filter.as_ref()));
+        cli.print_summary(
+            &paths[1],
+            &format!("Exported {rows} entries. Output: {}", paths[1].display()),
+        );
+        return;
+    }
+
+    if args[0] == "extract-strings" {
+        let paths = command_paths(&bin_name, "extract-strings", &args[1..], 2, 1);
+        let count = or_exit(extract_strings(&cli, &paths[0], &paths[1]));
+        cli.print_summary(
+            &paths[1],
+            &format!("Extracted {count} strings. Output: {}", paths[1].display()),
+        );
+        return;
+    }
+
+    if args[0] == "import-strings" {
+        let paths = command_paths(&bin_name, "import-strings", &args[1..], 3, 2);
+        let changed = or_exit(import_strings(&cli, &paths[0], &paths[1], &paths[2]));
+        cli.print_summary(
+            &paths[2],
+            &format!("Replaced {changed} strings. Output: {}", paths[2].display()),
+        );
+        return;
+    }
+
+    if args[0] == "import-csv" {
+        let (mut opts, rest) = edit_args(&cli, &bin_name, &args[1..]);
+        cli.apply_schema(&mut opts, false);
+        let paths = command_paths(&bin_name, "import-csv", &rest, 3, 2);
+        let updated = or_exit(import_csv(&cli, &paths[0], &paths[1], &paths[2], &opts));
+        cli.print_summary(
+            &paths[2],
+            &format!("Updated {updated} entries. Output: {}", paths[2].display()),
+        );
+        return;
+    }
+
+    if args[0] == "sync-from-dir" {
+        let (mut opts, rest) = edit_args(&cli, &bin_name, &args[1..]);
+        cli.apply_schema(&mut opts, false);
+        let paths = command_paths(&bin_name, "sync-from-dir", &rest, 3, 2);
+        let updated = or_exit(sync_from_dir(&cli, &paths[0], &paths[1], &paths[2], &opts));
+        cli.print_summary(
+            &paths[2],
+            &format!("Updated {updated} entries. Output: {}", paths[2].display()),
+        );
+        return;
+    }
+
+    if args[0] == "set-sizes" {
+        let (mut opts, mut rest) = edit_args(&cli, &bin_name, &args[1..]);
+        cli.apply_schema(&mut opts, false);
+        let clamp = match rest.iter().position(|a| a == "--clamp") {
+            Some(pos) => {
+                rest.remove(pos);
+                true
+            }
+            None => false,
+        };
+        let paths = command_paths(&bin_name, "set-sizes", &rest, 3, 1);
+        let Ok(size) = rest[1].parse::<i64>() else {
+            eprintln!("Error: invalid size `{}`.", rest[1]);
+            std::process::exit(1);
+        };
+        let setting = if clamp {
+            SizeSetting::Clamp(size)
+        } else {
+            SizeSetting::Fixed(size)
+        };
+        let updated = or_exit(set_sizes(&cli, &paths[0], setting, &paths[2], &opts));
+        cli.print_summary(
+            &paths[2],
+            &format!("Updated {updated} entries. Output: {}", paths[2].display()),
+        );
+        return;
+    }
+
+    if args[0] == "remove" {
+        let paths = command_paths(&bin_name, "remove", &args[1..], 3, 1);
+        let pattern = match Regex::new(&args[2]) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                eprintln!("Error: invalid remove pattern: {err}");
+                std::process::exit(1);
+            }
+        };
+        let removed = or_exit(remove_entries(
+            &cli, &paths[0], &pattern, &paths[2], sort_by,
+        ));
+        cli.print_summary(
+            &paths[2],
+            &format!("Removed {removed} entries. Output: {}", paths[2].display()),
+        );
+        return;
+    }
+
+    if args[0] == "diff" {
+        let mut unified = false;
+        let mut rest = Vec::new();
+        for arg in &args[1..] {
+            match arg.strip_prefix("--format=") {
+                Some("unified") => unified = true,
+                Some(format) => {
+                    eprintln!("Error: unknown diff format `{format}` (expected `unified`).");
+                    std::process::exit(1);
+                }
+                None => rest.push(arg.clone()),
+            }
+        }
+        let paths = command_paths(&bin_name, "diff", &rest, 2, 2);
+        if unified {
+            or_exit(run_unified_diff(&cli, &paths[0], &paths[1]));
+        } else {
+            or_exit(run_diff(&cli, &paths[0], &paths[1]));
+        }
+        return;
+    }
+
+    if args[0] == "batch" {
+        if cli.json_summary {
+            eprintln!("Error: --output-format json applies to a single sync, not batch.");
+            std::process::exit(1);
+        }
+        let (mut opts, positional) = sync_args(&cli, &bin_name, &args[1..]);
+        opts.sync.filter = filter;
+        opts.sync.sort_by = sort_by;
+        cli.apply_schema(&mut opts.sync, opts.auto_index);
+        if positional.len() < 2 {
+            eprintln!("Error: batch requires a patched table and at least one original.");
+            print_usage(&bin_name);
+            std::process::exit(1);
+        }
+        let path_b = PathBuf::from(positional[0]);
+        if !path_b.exists() {
+            eprintln!("Modified file not found: {}", path_b.display());
+            std::process::exit(EXIT_IO);
+        }
+        let mut originals = Vec::new();
+        for pattern in &positional[1..] {
+            originals.extend(or_exit(expand_glob(pattern).map_err(Failure::from)));
+        }
+        let failed = run_batch(&cli, &path_b, &originals, &opts);
+        cli.print_timings();
+        if or_exit(failed) > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let (mut opts, positional) = sync_args(&cli, &bin_name, &args);
+    opts.sync.filter = filter;
+    opts.sync.sort_by = sort_by;
+    cli.apply_schema(&mut opts.sync, opts.auto_index);
+
+    if !(2..=3).contains(&positional.len()) {
+        eprintln!("Error: requires 2 or 3 arguments.");
+        print_usage(&bin_name);
+        std::process::exit(1);
+    }
+
+    let path_a = PathBuf::from(positional[0]);
+    let path_b = PathBuf::from(positional[1]);
+    // Without an output path the original is updated in place.
+    let path_c = PathBuf::from(positional.get(2).unwrap_or(&positional[0]));
+    if is_cpk(&path_c) && !opts.dry_run && !opts.check {
+        eprintln!("Error: {}.", cpk_output_error(&path_c));
+        std::process::exit(1);
+    }
+
+    if !is_stdio(&path_a) && !path_a.exists() {
+        eprintln!("Original file not found: {}", path_a.display());
+        std::process::exit(EXIT_IO);
+    }
+    if !is_stdio(&path_b) && !path_b.exists() {
+        eprintln!("Modified file not found: {}", path_b.display());
+        std::process::exit(EXIT_IO);
+    }
+    let json = cli.json_summary;
+    if json && is_stdio(&path_c) && !opts.dry_run {
+        eprintln!(
+            "Error: --output-format json needs an output file, as stdout carries the summary."
+        );
+        std::process::exit(1);
+    }
+
+    if opts.check {
+        let matched = or_exit(run_check(&cli, &path_a, &path_b, &opts));
+        println!("{matched} matching entries");
+        std::process::exit(if matched > 0 { 0 } else { EXIT_NO_MATCHES });
+    }
+
+    if json {
+        cli.collect_warnings();
+    }
+    match run(&cli, &path_a, &path_b, &path_c, &opts) {
+        Ok(mut synced) if json => {
+            synced.warnings = cli.take_warnings();
+            print!("{}", synced.to_json(&path_a, &path_c, opts.dry_run));
+        }
+        Ok(synced) if opts.dry_run => {
+            let (updated, info, limit) = (synced.updated, synced.table_info(), synced.limit_note());
+            println!("Would update {updated} entries {info}{limit} (dry run)");
+        }
+        Ok(synced) => {
+            let (updated, info, limit) = (synced.updated, synced.table_info(), synced.limit_note());
+            let output = path_c.display();
+            cli.print_summary(
+                &path_c,
+                &format!("Updated {updated} entries {info}{limit}. Output: {output}"),
+            );
+        }
+        Err(err) => {
+            // A failed run prints no JSON, so what it collected goes to stderr.
+            for (subject, reason) in cli.take_warnings() {
+                cli.warn(subject, reason);
+            }
+            eprintln!("Failed: {err}");
+            cli.print_timings();
+            std::process::exit(err.code);
+        }
+    }
+    cli.print_timings();
+}
+
+impl Cli {
+    /// Runs `f` and, with `--timings`, adds the wall-clock time it took to `phase`.
+    fn timed<T>(&self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.timings {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        let mut times = self.phase_times.borrow_mut();
+        match times.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => times.push((phase, elapsed)),
+        }
+        result
+    }
+
+    /// Prints the time of every phase [`Cli::timed`] measured to stderr, with
+    /// `--timings`.
+    fn print_timings(&self) {
+        if !self.timings {
+            return;
+        }
+        let times = self.phase_times.borrow();
+        eprintln!("Timings:");
+        for (phase, time) in times.iter() {
+            eprintln!("  {phase:<12} {time:.3?}");
+        }
+        let total = times.iter().map(|(_, time)| *time).sum::<Duration>();
+        eprintln!("  {:<12} {total:.3?}", "total");
+    }
+
+    /// Options for a sync with the path keys and parse overrides of the command line.
+    fn sync_options(&self) -> SyncOptions {
+        SyncOptions {
+            keys: self.keys.clone(),
+            parse: self.parse,
+            ..SyncOptions::default()
+        }
+    }
+
+    /// Makes the schema's `size` index the default A size field, unless an index was
+    /// given or is to be guessed.
+    fn apply_schema(&self, opts: &mut SyncOptions, auto_index: bool) {
+        if opts.dst_index.is_some() || auto_index {
+            return;
+        }
+        if let Some(index) = self.schema.index_of("size") {
+            verbose!(self, 1, "A size index {index} from --schema");
+            opts.dst_index = Some(index);
+        }
+    }
+
+    /// Wraps `text` in the ANSI SGR sequence `code` when color is on.
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Colors one line of [`to_dump`] output: entry names bold, value types cyan.
+    fn paint_dump_line(&self, line: &str) -> String {
+        if let Some((index, rest)) = line.split_once("] ").filter(|_| line.starts_with("  [")) {
+            let (typ, value) = rest.split_once(' ').unwrap_or((rest, ""));
+            format!("{index}] {} {value}", self.paint(typ, CYAN))
+        } else if line.ends_with(':') && !line.starts_with(' ') {
+            self.paint(line, BOLD)
+        } else {
+            line.to_string()
+        }
+    }
+
+    /// Writes `bytes` to `path`, or to stdout when `path` is `-`. A path ending in `.gz`
+    /// is gzip-compressed when an input was. Returns the bytes as written.
+    fn write_output<'a>(&self, path: &Path, bytes: &'a [u8]) -> Result<Cow<'a, [u8]>, Failure> {
+        let bytes = if self.gzip_input.get() && path.extension().is_some_and(|ext| ext == "gz") {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            let compressed = encoder
+                .write_all(bytes)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| Failure::new(EXIT_IO, format!("compress output: {e}")))?;
+            Cow::Owned(compressed)
+        } else {
+            Cow::Borrowed(bytes)
+        };
+        let result = if is_stdio(path) {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&bytes).and_then(|_| stdout.flush())
+        } else {
+            write_atomically(path, &bytes)
+        };
+        result.map_err(|e| Failure::new(EXIT_IO, format!("write output: {e}")))?;
+        Ok(bytes)
+    }
+
+    /// Writes the table `bytes` like [`Cli::write_output`], first converted to the
+    /// `--out-encoding` if one is given, and prints the SHA-256 of the written file with
+    /// `--print-hash`.
+    fn write_table(&self, path: &Path, bytes: &[u8]) -> Result<(), Failure> {
+        if is_cpk(path) {
+            return Err(cpk_output_error(path).into());
+        }
+        let converted;
+        let bytes = match self.out_encoding {
+            Some(encoding) => {
+                converted = self.reencode(bytes, encoding)?;
+                &converted
+            }
+            None => bytes,
+        };
+        let written = self.write_output(path, bytes)?;
+        if self.print_hash {
+            // The hash of the file as written, after any gzip compression, so that it
+            // matches `sha256sum` of the output.
+            let hash = Sha256::digest(&written)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>();
+            self.print_summary(path, &format!("sha256 {hash}  {}", path.display()));
+        }
+        Ok(())
+    }
+
+    /// Converts the table `bytes` to `encoding`. Fails, listing them all, on strings the
+    /// encoding cannot hold.
+    fn reencode(&self, bytes: &[u8], encoding: StringEncoding) -> Result<Vec<u8>, Failure> {
+        // The table was written in the input's encoding, which may have been overridden.
+        let mut parsed = parse_t2b_owned(bytes.to_vec(), self.parse, &mut |_| {})
+            .map_err(|e| format!("re-read output: {e}"))?;
+        let mut unencodable = Vec::new();
+        for entry in &parsed.entries {
+            let strings = (0..entry.values.len()).filter_map(|i| entry.string(i));
+            for s in std::iter::once(entry.name.as_str()).chain(strings) {
+                if !encoding.can_encode(s) && !unencodable.iter().any(|u| u == s) {
+                    unencodable.push(s.to_string());
+                }
+            }
+        }
+        if !unencodable.is_empty() {
+            let count = unencodable.len();
+            eprintln!("{count} strings cannot be encoded as {encoding:?}:");
+            for s in &unencodable {
+                eprintln!("  {s:?}");
+            }
+            return Err(format!("{count} strings cannot be encoded as {encoding:?}").into());
+        }
+        parsed.set_encoding(encoding);
+        Ok(serialize(&parsed).map_err(|e| format!("re-encode output: {e}"))?)
+    }
+
+    /// Prints a summary line, on stderr when the output itself or the JSON summary went
+    /// to stdout.
+    fn print_summary(&self, output: &Path, summary: &str) {
+        if is_stdio(output) || self.json_summary {
+            eprintln!("{summary}");
+        } else {
+            println!("{summary}");
+        }
+    }
+
+    /// Reports a problem that does not stop the run as a `warning: [subject] reason` line
+    /// on stderr, where `subject` is the entry key or file concerned. While a JSON summary
+    /// collects warnings it gets them instead, and `-q` hides the stderr line.
+    fn warn(&self, subject: impl fmt::Display, reason: impl fmt::Display) {
+        match self.warnings.borrow_mut().as_mut() {
+            Some(warnings) => warnings.push((subject.to_string(), reason.to_string())),
+            None if self.quiet => {}
+            None => eprintln!("warning: [{subject}] {reason}"),
+        }
+    }
+
+    /// Starts collecting warnings for a JSON summary.
+    fn collect_warnings(&self) {
+        *self.warnings.borrow_mut() = Some(Vec::new());
+    }
+
+    /// Stops collecting warnings and returns those collected so far.
+    fn take_warnings(&self) -> Vec<(String, String)> {
+        self.warnings.borrow_mut().take().unwrap_or_default()
+    }
+
+    /// Prints a line of a dry-run report, on stderr when stdout carries the JSON summary.
+    fn print_report(&self, line: &str) {
+        if self.json_summary {
+            eprintln!("{line}");
+        } else {
+            println!("{line}");
+        }
+    }
+
+    fn report_crc_mismatches(&self, label: &str, parsed: &ParsedT2b) {
+        for m in crc_mismatches(parsed) {
+            let reason = format!(
+                "{label} CRC32 mismatch: stored {:08x}, computed {:08x}",
+                m.stored, m.computed
+            );
+            self.warn(m.name, reason);
+        }
+    }
+
+    fn read_t2b(&self, path: &Path) -> Result<ParsedT2b, Failure> {
+        self.parse_input(path, self.read_input(path)?)
+    }
+
+    /// Reads a table file, or stdin for `-`.
+    fn read_input(&self, path: &Path) -> Result<Vec<u8>, Failure> {
+        let io_err = |e| Failure::new(EXIT_IO, T2bError::Io(e).to_string());
+        if is_stdio(path) {
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes).map_err(io_err)?;
+            return Ok(bytes);
+        }
+        if is_cpk(path) {
+            return self.read_cpk_member(path);
+        }
+
+        let mut file = fs::File::open(path).map_err(io_err)?;
+        let len = file.metadata().map_err(io_err)?.len();
+        let mut bytes = Vec::with_capacity(len as usize);
+        file.read_to_end(&mut bytes).map_err(io_err)?;
+        Ok(bytes)
+    }
+
+    /// Reads the `--cpk-member` table out of the CPK archive at `path`. The archive is
+    /// mapped rather than read, as it may be far larger than the table.
+    #[cfg(feature = "cpk")]
+    fn read_cpk_member(&self, path: &Path) -> Result<Vec<u8>, Failure> {
+        let io_err = |e| Failure::new(EXIT_IO, T2bError::Io(e).to_string());
+        let file = fs::File::open(path).map_err(io_err)?;
+        // SAFETY: the map is only read while the member is copied out, and is dropped right
+        // after. Like any mmap it relies on no other process truncating the file meanwhile.
+        let map = unsafe { Mmap::map(&file) }.map_err(io_err)?;
+        let member = &self.cpk_member;
+        let bytes = cpk_size_sync::cpk::read_member(&map, member)
+            .map_err(|e| Failure::new(EXIT_PARSE, format!("read {member} from CPK: {e}")))?;
+        verbose!(
+            self,
+            1,
+            "{}: read {member} ({} bytes) from the archive",
+            path.display(),
+            bytes.len()
+        );
+        Ok(bytes)
+    }
+
+    #[cfg(not(feature = "cpk"))]
+    fn read_cpk_member(&self, path: &Path) -> Result<Vec<u8>, Failure> {
+        Err(format!(
+            "{}: reading CPK archives needs a build with `--features cpk`",
+            path.display()
+        )
+        .into())
+    }
+
+    fn parse_input(&self, path: &Path, bytes: Vec<u8>) -> Result<ParsedT2b, Failure> {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            verbose!(self, 1, "{}: gzip-compressed", path.display());
+            self.gzip_input.set(true);
+        }
+        let bytes = gunzip(path, bytes)?;
+        let options = self.parse;
+        if let Some(encoding) = options.encoding {
+            verbose!(
+                self,
+                1,
+                "{}: decoding strings as {encoding:?}",
+                path.display()
+            );
+        }
+        let parsed = if self.verbosity >= 2 {
+            eprintln!("Parsing {}", path.display());
+            parse_t2b_owned(bytes, options, &mut |step| eprintln!("  {step}"))
+        } else {
+            parse_t2b_owned(bytes, options, &mut |_| {})
+        };
+        let mut parsed = parsed.map_err(|e| Failure::new(EXIT_PARSE, e.to_string()))?;
+        if parsed.checksum_error.is_some() {
+            // Path rows are still recognized by the CRC32 of their name.
+            for name in &self.keys.names {
+                let crc = crc32_of_name(name, parsed.encoding);
+                for entry in parsed.entries.iter_mut().filter(|e| e.crc32 == crc) {
+                    entry.name = name.clone();
+                }
+                parsed
+                    .unresolved_names
+                    .retain(|&unresolved| unresolved != crc);
+            }
+        }
+        // An ignored checksum section leaves every other name unresolved, which its
+        // warning below says.
+        if !parsed.unresolved_names.is_empty() && parsed.checksum_error.is_none() {
+            let count = parsed.unresolved_names.len();
+            let names = parsed
+                .unresolved_names
+                .iter()
+                .map(|crc| format!("crc_0x{crc:08x}"))
+                .collect::<Vec<_>>();
+            let names = names.join(", ");
+            self.warn(
+                path.display(),
+                format!("{count} entry names could not be resolved: {names}"),
+            );
+        }
+        if let Some(err) = &parsed.checksum_error {
+            let reason = format!(
+                "ignored the checksum section ({err}); entries other than path rows are named \
+                 by their CRC32"
+            );
+            self.warn(path.display(), reason);
+        }
+        if let Some(recovery) = &parsed.recovery {
+            let reason = format!(
+                "recovered {} of {} entries; entry {} at 0x{:x}: {}",
+                recovery.entry, recovery.declared, recovery.entry, recovery.offset, recovery.reason
+            );
+            self.warn(path.display(), reason);
+        }
+        if !parsed.alignment.regular {
+            let reason = format!(
+                "the padding between sections fits no power-of-two alignment; a rebuilt table \
+                 pads them to 0x{:x}",
+                parsed.alignment.sections
+            );
+            self.warn(path.display(), reason);
+        }
+        for m in layout_mismatches(&parsed) {
+            let reason = format!(
+                "{} is 0x{:x}, but the surrounding sections imply 0x{:x}",
+                m.field, m.stored, m.expected
+            );
+            self.warn(path.display(), reason);
+        }
+        Ok(parsed)
+    }
+}
+
+impl SyncLog for Cli {
+    fn verbose(&self, level: u8, message: fmt::Arguments<'_>) {
+        verbose!(self, level, "{message}");
+    }
+
+    fn warn(&self, subject: &str, reason: &str) {
+        Cli::warn(self, subject, reason);
+    }
+
+    fn update(&self, key: &str, old: Size, new: Size) {
+        verbose!(self, 1, "A {key}: {old} -> {new}");
+    }
+
+    fn progress(&self, processed: usize, total: usize, matched: usize) {
+        let mut progress = self.progress.borrow_mut();
+        if processed >= total {
+            // Dropping the bar erases it.
+            *progress = None;
+            return;
+        }
+        let hidden = self.quiet || self.verbosity > 0;
+        progress
+            .get_or_insert_with(|| Progress::new(total, hidden))
+            .update(processed, matched);
+    }
+}
+
+/// Checks that a command got exactly `expected` path arguments and that the first
+/// `inputs` of them exist, exiting with an error otherwise.
+fn command_paths(
+    bin_name: &str,
+    command: &str,
+    args: &[String],
+    expected: usize,
+    inputs: usize,
+) -> Vec<PathBuf> {
+    if args.len() != expected {
+        let plural = if expected == 1 { "" } else { "s" };
+        eprintln!("Error: {command} requires exactly {expected} argument{plural}.");
+        print_usage(bin_name);
+        std::process::exit(1);
+    }
+    let paths = args.iter().map(PathBuf::from).collect::<Vec<_>>();
+    for path in &paths[..inputs] {
+        if !is_stdio(path) && !path.exists() {
+            eprintln!("Input file not found: {}", path.display());
+            std::process::exit(EXIT_IO);
+        }
+    }
+    paths
+}
+
+/// Removes `--filter <regex>` from `args` and compiles the pattern.
+fn take_filter(bin_name: &str, args: &mut Vec<String>) -> Option<Regex> {
+    let pos = args.iter().position(|a| a == "--filter")?;
+    args.remove(pos);
+    if pos == args.len() {
+        eprintln!("Error: --filter requires a regular expression.");
+        print_usage(bin_name);
+        std::process::exit(1);
+    }
+    let pattern = args.remove(pos);
+    match Regex::new(&pattern) {
+        Ok(filter) => Some(filter),
+        Err(err) => {
+            eprintln!("Error: invalid --filter pattern: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Removes `--schema <schema.toml>` from `args` and reads the schema; an empty schema
+/// when it is not given.
+fn take_schema(bin_name: &str, args: &mut Vec<String>) -> Schema {
+    let Some(pos) = args.iter().position(|a| a == "--schema") else {
+        return Schema::default();
+    };
+    args.remove(pos);
+    if pos == args.len() {
+        eprintln!("Error: --schema requires a TOML file.");
+        print_usage(bin_name);
+        std::process::exit(1);
+    }
+    let path = PathBuf::from(args.remove(pos));
+    let text = or_exit(
+        fs::read_to_string(&path)
+            .map_err(|e| Failure::new(EXIT_IO, format!("read {}: {e}", path.display()))),
+    );
+    or_exit(
+        parse_schema(&text)
+            .map_err(|e| Failure::new(EXIT_PARSE, format!("parse {}: {e}", path.display()))),
+    )
+}
+
+/// Removes `--sort-by <name|path>` from `args`.
+fn take_sort_by(bin_name: &str, args: &mut Vec<String>) -> Option<MatchBy> {
+    let pos = args.iter().position(|a| a == "--sort-by")?;
+    args.remove(pos);
+    let sort_by = match args.get(pos).map(String::as_str) {
+        Some("name") => MatchBy::Name,
+        Some("path") => MatchBy::Path,
+        _ => {
+            eprintln!("Error: --sort-by requires `name` or `path`.");
+            print_usage(bin_name);
+            std::process::exit(1);
+        }
+    };
+    args.remove(pos);
+    Some(sort_by)
+}
+
+/// Removes `--path-sep <keep|unix|win>` from `args`; `unix` when it is not given.
+fn take_path_sep(bin_name: &str, args: &mut Vec<String>) -> PathSep {
+    let Some(pos) = args.iter().position(|a| a == "--path-sep") else {
+        return PathSep::default();
+    };
+    args.remove(pos);
+    let sep = match args.get(pos).map(String::as_str) {
+        Some("keep") => PathSep::Keep,
+        Some("unix") => PathSep::Unix,
+        Some("win") => PathSep::Win,
+        _ => {
+            eprintln!("Error: --path-sep requires `keep`, `unix` or `win`.");
+            print_usage(bin_name);
+            std::process::exit(1);
+        }
+    };
+    args.remove(pos);
+    sep
+}
+
+/// Removes every `--entry-name <NAME>` from `args` and returns the names, or just
+/// `CPK_ITEM` when there are none.
+fn take_entry_names(bin_name: &str, args: &mut Vec<String>) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Some(pos) = args.iter().position(|a| a == "--entry-name") {
+        args.remove(pos);
+        match args.get(pos) {
+            Some(name) if !name.starts_with('-') => names.push(args.remove(pos)),
+            _ => {
+                eprintln!("Error: --entry-name requires an entry name.");
+                print_usage(bin_name);
+                std::process::exit(1);
+            }
+        }
+    }
+    if names.is_empty() {
+        names.push("CPK_ITEM".into());
+    }
+    names
+}
+
+/// Removes `--cpk-member <NAME>` from `args` and returns the name, by default
+/// `cpk_list.cfg.bin`.
+fn take_cpk_member(bin_name: &str, args: &mut Vec<String>) -> String {
+    let Some(pos) = args.iter().position(|a| a == "--cpk-member") else {
+        return "cpk_list.cfg.bin".into();
+    };
+    args.remove(pos);
+    match args.get(pos) {
+        Some(name) if !name.starts_with('-') => args.remove(pos),
+        _ => {
+            eprintln!("Error: --cpk-member requires a member name.");
+            print_usage(bin_name);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Whether `path` names a CPK archive, which is read through [`Cli::read_cpk_member`] and
+/// never written.
+fn is_cpk(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cpk"))
+}
+
+/// Removes `--color <auto|always|never>` (or `--color=<mode>`, or `--no-color`) from
+/// `args` and returns whether to color output. `auto` colors only when stdout is a
+/// terminal and `NO_COLOR` is unset.
+fn take_color(bin_name: &str, args: &mut Vec<String>) -> bool {
+    let mut mode = String::from("auto");
+    if let Some(pos) = args.iter().position(|a| a == "--no-color") {
+        args.remove(pos);
+        mode = "never".into();
+    }
+    if let Some(pos) = args
+        .iter()
+        .position(|a| a == "--color" || a.starts_with("--color="))
+    {
+        let arg = args.remove(pos);
+        mode = match arg.strip_prefix("--color=") {
+            Some(value) => value.to_string(),
+            None if pos < args.len() => args.remove(pos),
+            None => String::new(),
+        };
+    }
+    match mode.as_str() {
+        "always" => true,
+        "never" => false,
+        "auto" => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        _ => {
+            eprintln!("Error: --color requires `auto`, `always` or `never`.");
+            print_usage(bin_name);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Removes `--output-format <text|json>` from `args`; returns whether it is `json`.
+fn take_output_format(bin_name: &str, args: &mut Vec<String>) -> bool {
+    let Some(pos) = args.iter().position(|a| a == "--output-format") else {
+        return false;
+    };
+    args.remove(pos);
+    let format = (pos < args.len()).then(|| args.remove(pos));
+    match format.as_deref() {
+        Some("json") => true,
+        Some("text") => false,
+        _ => {
+            eprintln!("Error: --output-format requires `text` or `json`.");
+            print_usage(bin_name);
+            std::process::exit(1);
+        }
+    }
+}
 
-const MAGIC_T2B: u32 = 0x6232_7401;
+/// Removes `--encoding <sjis|utf8>`, `--value-length <int|long>`, `--lenient`,
+/// `--recover` and `--force-parse` from `args`.
+fn take_parse_options(bin_name: &str, args: &mut Vec<String>) -> ParseOptions {
+    let mut options = ParseOptions::default();
+    if let Some(pos) = args.iter().position(|a| a == "--lenient") {
+        args.remove(pos);
+        options.lenient = true;
+    }
+    if let Some(pos) = args.iter().position(|a| a == "--recover") {
+        args.remove(pos);
+        options.recover = true;
+    }
+    if let Some(pos) = args.iter().position(|a| a == "--force-parse") {
+        args.remove(pos);
+        options.force_parse = true;
+    }
+    if let Some(pos) = args.iter().position(|a| a == "--encoding") {
+        args.remove(pos);
+        options.encoding = match args.get(pos).and_then(|arg| encoding_arg(arg)) {
+            Some(encoding) => Some(encoding),
+            None => {
+                eprintln!("Error: --encoding requires `sjis`, `utf8` or `utf16le`.");
+                print_usage(bin_name);
+                std::process::exit(1);
+            }
+        };
+        args.remove(pos);
+    }
+    if let Some(pos) = args.iter().position(|a| a == "--value-length") {
+        args.remove(pos);
+        options.value_length = match args.get(pos).map(String::as_str) {
+            Some("int") => Some(ValueLength::Int),
+            Some("long") => Some(ValueLength::Long),
+            _ => {
+                eprintln!("Error: --value-length requires `int` or `long`.");
+                print_usage(bin_name);
+                std::process::exit(1);
+            }
+        };
+        args.remove(pos);
+    }
+    options
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ValueType {
-    String = 0,
-    Integer = 1,
-    FloatingPoint = 2,
+fn encoding_arg(arg: &str) -> Option<StringEncoding> {
+    match arg {
+        "sjis" => Some(StringEncoding::Sjis),
+        "utf8" => Some(StringEncoding::Utf8),
+        "utf16le" => Some(StringEncoding::Utf16Le),
+        _ => None,
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum ValueLength {
-    Int = 4,
-    Long = 8,
+/// Removes `--out-encoding <sjis|utf8|utf16le>` from `args`.
+fn take_out_encoding(bin_name: &str, args: &mut Vec<String>) -> Option<StringEncoding> {
+    let pos = args.iter().position(|a| a == "--out-encoding")?;
+    args.remove(pos);
+    let Some(encoding) = args.get(pos).and_then(|arg| encoding_arg(arg)) else {
+        eprintln!("Error: --out-encoding requires `sjis`, `utf8` or `utf16le`.");
+        print_usage(bin_name);
+        std::process::exit(1);
+    };
+    args.remove(pos);
+    Some(encoding)
 }
 
-#[derive(Debug, Clone, Copy)]
-enum StringEncoding {
-    Sjis,
-    Utf8,
+/// A sync as the command line asks for it: what to sync, and what to do with the result.
+#[derive(Debug, Clone, Default)]
+struct RunOptions {
+    sync: SyncOptions,
+    /// Report what would change without writing the output.
+    dry_run: bool,
+    /// Also list each update with the byte offset and width written.
+    show_offsets: bool,
+    /// Only count the original keys that find a size in B.
+    check: bool,
+    /// Guess unset size field indices with [`guess_size_index`].
+    auto_index: bool,
+    /// Skip the `.bak` copy made when the output overwrites an input.
+    no_backup: bool,
+    /// Replace an existing `.bak` file.
+    force: bool,
+    /// Give the output the permissions of the original.
+    preserve_mode: bool,
+    /// Report entry names whose stored CRC32 does not match the name.
+    verify_crc: bool,
+    /// List A keys without a size and B keys that matched nothing.
+    report_unmatched: bool,
 }
 
-#[derive(Debug, Clone)]
-enum ValueData {
-    Str(Option<String>),
-    Int(i64),
-    Float(f64),
+/// Splits sync arguments into options and positional paths.
+fn sync_args<'a>(cli: &Cli, bin_name: &str, args: &'a [String]) -> (RunOptions, Vec<&'a String>) {
+    let mut opts = RunOptions {
+        sync: cli.sync_options(),
+        ..RunOptions::default()
+    };
+    let mut positional = Vec::new();
+    let (mut src_indices, mut dst_indices) = (Vec::new(), Vec::new());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--dry-run" => opts.dry_run = true,
+            "--show-offsets" => opts.show_offsets = true,
+            "--check" => opts.check = true,
+            "--no-backup" => opts.no_backup = true,
+            "--preserve-mode" => opts.preserve_mode = true,
+            "--force" => opts.force = true,
+            "--strict" => opts.sync.strict = true,
+            "--verify-crc" => opts.verify_crc = true,
+            "--ignore-case" => opts.sync.ignore_case = true,
+            "--sync-strings" => opts.sync.sync_strings = true,
+            "--report-unmatched" => opts.report_unmatched = true,
+            "--add-missing" => opts.sync.add_missing = true,
+            "--auto-index" => opts.auto_index = true,
+            "--limit" => opts.sync.limit = Some(index_arg(bin_name, arg, iter.next())),
+            "--src-index" => src_indices = index_list_arg(bin_name, arg, iter.next()),
+            "--dst-index" => dst_indices = index_list_arg(bin_name, arg, iter.next()),
+            "--only" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("Error: --only requires a file with one path per line.");
+                    print_usage(bin_name);
+                    std::process::exit(1);
+                };
+                opts.sync.only = Some(or_exit(read_path_list(Path::new(path))));
+            }
+            "--on-duplicate" => {
+                opts.sync.on_duplicate = match iter.next().map(String::as_str) {
+                    Some("first") => Some(OnDuplicate::First),
+                    Some("last") => Some(OnDuplicate::Last),
+                    Some("error") => Some(OnDuplicate::Error),
+                    _ => {
+                        eprintln!("Error: --on-duplicate requires `first`, `last` or `error`.");
+                        print_usage(bin_name);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--unknown-as" => {
+                // The replacement is optional, so only a number is taken as one.
+                let size = iter.as_slice().first().and_then(|n| n.parse::<i64>().ok());
+                opts.sync.unknown_as = Some(match size {
+                    Some(n) => {
+                        iter.next();
+                        UnknownSize::Replace(n)
+                    }
+                    None => UnknownSize::Skip,
+                });
+            }
+            "--match-by" => {
+                opts.sync.match_by = match iter.next().map(String::as_str) {
+                    Some("path") => MatchBy::Path,
+                    Some("name") => MatchBy::Name,
+                    _ => {
+                        eprintln!("Error: --match-by requires `path` or `name`.");
+                        print_usage(bin_name);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if (src_indices.len() > 1 || dst_indices.len() > 1) && src_indices.len() != dst_indices.len() {
+        eprintln!("Error: --src-index and --dst-index lists must have the same length.");
+        print_usage(bin_name);
+        std::process::exit(1);
+    }
+    opts.sync.src_index = src_indices.first().copied();
+    opts.sync.dst_index = dst_indices.first().copied();
+    opts.sync.more_indices = src_indices.into_iter().zip(dst_indices).skip(1).collect();
+    (opts, positional)
 }
 
-#[derive(Debug, Clone)]
-struct ValueField {
-    typ: ValueType,
-    data: ValueData,
-    offset: usize,
+/// Reads the paths listed one per line in `path` for `--only`, ignoring blank lines
+/// and surrounding whitespace.
+fn read_path_list(path: &Path) -> Result<HashSet<String>, Failure> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Failure::new(EXIT_IO, format!("read {}: {e}", path.display())))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
-#[derive(Debug, Clone)]
-struct Entry {
-    name: String,
-    values: Vec<ValueField>,
+/// Expands `*` and `?` in the file name part of `pattern`, for shells that pass
+/// wildcards through unexpanded. Patterns without wildcards are returned unchanged;
+/// earlier `.synced.bin` outputs are never matched.
+fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(vec![path.to_path_buf()]);
+    };
+    if !name.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut matches = fs::read_dir(dir)
+        .map_err(|e| format!("read {}: {e}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| !n.ends_with(".synced.bin") && wildcard_match(name, n))
+        })
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        return Err(format!("no files match {pattern}"));
+    }
+    matches.sort();
+    Ok(matches)
 }
 
-#[derive(Debug, Clone)]
-struct ParsedT2b {
-    bytes: Vec<u8>,
-    value_length: ValueLength,
-    entries: Vec<Entry>,
+/// Matches `name` against a pattern where `*` is any run of characters and `?` is
+/// any single character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it is currently matched up to.
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p;
+            n = star_n + 1;
+            star = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
-fn main() {
-    let mut raw_args = std::env::args();
-    let bin_name = raw_args
-        .next()
-        .and_then(|p| std::path::Path::new(&p).file_name().map(|s| s.to_string_lossy().to_string()))
-        .unwrap_or_else(|| "cpk_size_sync".into());
-    let args = raw_args.collect::<Vec<_>>();
+/// `-` stands for stdin as an input and stdout as an output.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
 
-    if args.iter().any(|a| a == "-v" || a == "--version") {
-        print_version(&bin_name);
-        std::process::exit(0);
-    }
+/// The error for an output path inside a CPK archive, which this tool only reads.
+fn cpk_output_error(path: &Path) -> String {
+    format!(
+        "{} is a CPK archive, which is only read; give a .bin output path",
+        path.display()
+    )
+}
 
-    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
-        print_usage(&bin_name);
-        std::process::exit(0);
+/// Writes `bytes` to a temporary file next to `path` and renames it over `path`, so
+/// an interrupted write never leaves a truncated table behind.
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let temp = path.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        if let Ok(meta) = fs::metadata(path) {
+            fs::set_permissions(&temp, meta.permissions())?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
     }
+    result
+}
 
-    if args.len() != 3 {
-        eprintln!("Error: requires exactly 3 arguments.");
-        print_usage(&bin_name);
-        std::process::exit(1);
-    }
+/// Gives `to` the permissions of `from`: the mode bits on Unix, the read-only flag
+/// elsewhere. Ownership is left alone.
+fn copy_permissions(from: &Path, to: &Path) -> Result<(), Failure> {
+    let target = to.display();
+    fs::metadata(from)
+        .and_then(|meta| fs::set_permissions(to, meta.permissions()))
+        .map_err(|e| Failure::new(EXIT_IO, format!("copy permissions to {target}: {e}")))
+}
 
-    let path_a = PathBuf::from(&args[0]);
-    let path_b = PathBuf::from(&args[1]);
-    let path_c = PathBuf::from(&args[2]);
+fn or_exit<T>(result: Result<T, Failure>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("Failed: {err}");
+        std::process::exit(err.code);
+    })
+}
 
-    if !path_a.exists() {
-        eprintln!("Original file not found: {}", path_a.display());
-        std::process::exit(1);
-    }
-    if !path_b.exists() {
-        eprintln!("Modified file not found: {}", path_b.display());
-        std::process::exit(1);
+/// Splits the options of the commands that write sizes from a file, a directory or the
+/// command line rather than a patched table (`--dst-index`, `--strict`) off their
+/// positional arguments.
+fn edit_args(cli: &Cli, bin_name: &str, args: &[String]) -> (SyncOptions, Vec<String>) {
+    let mut opts = cli.sync_options();
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--strict" => opts.strict = true,
+            "--dst-index" => opts.dst_index = Some(index_arg(bin_name, arg, iter.next())),
+            _ => positional.push(arg.clone()),
+        }
     }
+    (opts, positional)
+}
 
-    match run(&path_a, &path_b, &path_c) {
-        Ok(updated) => {
-            println!(
-                "Updated {} entries. Output: {}",
-                updated,
-                path_c.display()
-            );
-        }
-        Err(err) => {
-            eprintln!("Failed: {err}");
+/// Parses the value of an index flag, exiting with usage on a missing or bad value.
+fn index_arg(bin_name: &str, flag: &str, value: Option<&String>) -> usize {
+    match value.map(|v| v.parse::<usize>()) {
+        Some(Ok(index)) => index,
+        _ => {
+            eprintln!("Error: {flag} requires a non-negative integer.");
+            print_usage(bin_name);
             std::process::exit(1);
         }
     }
 }
 
+/// Parses a comma-separated list of indices, as taken by `--src-index` and
+/// `--dst-index`.
+fn index_list_arg(bin_name: &str, flag: &str, value: Option<&String>) -> Vec<usize> {
+    let Some(value) = value else {
+        return vec![index_arg(bin_name, flag, None)];
+    };
+    value
+        .split(',')
+        .map(|part| index_arg(bin_name, flag, Some(&part.trim().to_string())))
+        .collect()
+}
+
 fn print_usage(bin_name: &str) {
     eprintln!("Synchronize file size entries in LEVEL5 cpk_list.cfg.bin tables.");
     eprintln!();
     eprintln!("Usage:");
-    eprintln!("  {bin_name} <original.bin> <patched.bin> <output.bin>");
+    eprintln!("  {bin_name} [options] <original.bin> <patched.bin> [output.bin]");
+    eprintln!("  {bin_name} batch [options] <patched.bin> <original.bin>...");
+    eprintln!("  {bin_name} dump <input.bin>");
+    eprintln!("  {bin_name} dump-json <input.bin>");
+    eprintln!("  {bin_name} inspect <input.bin>");
+    eprintln!(
+        "  {bin_name} apply-json <input.bin> <edits.json> <output.bin> [--dst-index N] [--strict]"
+    );
+    eprintln!("  {bin_name} export-csv <input.bin> <output.csv>");
+    eprintln!(
+        "  {bin_name} import-csv <input.bin> <sizes.csv> <output.bin> [--dst-index N] [--strict]"
+    );
+    eprintln!("  {bin_name} extract-strings <input.bin> <out.txt>");
+    eprintln!("  {bin_name} import-strings <input.bin> <strings.txt> <output.bin>");
+    eprintln!(
+        "  {bin_name} sync-from-dir <table.bin> <assets_dir> <output.bin> [--dst-index N] [--strict]"
+    );
+    eprintln!(
+        "  {bin_name} set-sizes <input.bin> <size|--clamp MAX> <output.bin> [--dst-index N] [--strict]"
+    );
+    eprintln!("  {bin_name} remove <input.bin> <regex> <output.bin>");
+    eprintln!("  {bin_name} diff [--format=unified] <a.bin> <b.bin>");
     eprintln!();
     eprintln!("Arguments:");
     eprintln!("  original.bin   Source table whose size fields will be updated");
     eprintln!("  patched.bin    Patched table that already contains correct sizes");
-    eprintln!("  output.bin     Required output path for the synchronized table");
+    eprintln!("  output.bin     Output path for the synchronized table; when omitted,");
+    eprintln!("                 original.bin is updated in place after a .bak backup");
+    eprintln!("  -              Read an input from stdin or write the output to stdout");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --dry-run      List sizes that would change and skip writing output.bin");
+    eprintln!("  --show-offsets List each size change with the byte offset and width written");
+    eprintln!("  --output-format F");
+    eprintln!("                 `json` prints the sync summary as JSON on stdout (default `text`)");
+    eprintln!("  --check        Only print how many original.bin entries match patched.bin;");
+    eprintln!("                 exits with 3 when none do");
+    eprintln!("  --src-index N  Read sizes from value N of patched.bin entries (default 4)");
+    eprintln!("  --dst-index N  Write sizes into value N of original.bin entries (default 4)");
+    eprintln!("                 Both take lists like 4,5 to sync several size fields at once");
+    eprintln!("  --limit N      Stop after writing N sizes, to bisect which update breaks a game");
+    eprintln!("  --unknown-as [N]");
+    eprintln!("                 Write N instead of patched.bin sizes of -1 (size unknown), or");
+    eprintln!("                 leave those entries unchanged when N is omitted");
+    eprintln!("  --auto-index   Guess unset size indices from each table's integer values");
+    eprintln!("  --no-backup    Do not copy output.bin to output.bin.bak when it is also an input");
+    eprintln!("  --preserve-mode");
+    eprintln!("                 Give output.bin the permissions of original.bin");
+    eprintln!("  --force        Replace an existing .bak backup");
+    eprintln!("  --strict       Fail unless every original.bin entry gets a size, listing any");
+    eprintln!("                 unmatched, non-numeric or out-of-range entries");
+    eprintln!("  --verify-crc   Warn about entry names whose stored CRC32 does not match");
+    eprintln!("  --match-by K   Pair entries by `path` (CPK_ITEM prefix+suffix, default)");
+    eprintln!("                 or by entry `name`");
+    eprintln!("  --ignore-case  Match keys that differ only in ASCII letter case");
+    eprintln!("  --path-sep S   Match paths with `unix` (/, the default) or `win` (\\)");
+    eprintln!("                 separators, or `keep` them as stored");
+    eprintln!("  --entry-name N Treat entries named N as path rows instead of CPK_ITEM;");
+    eprintln!("                 repeat for several names");
+    eprintln!("  --cpk-member M Read member M of .cpk inputs, by file name or dir/name path");
+    eprintln!("                 (default cpk_list.cfg.bin; needs the `cpk` feature)");
+    eprintln!("  --filter RE    Only process CPK_ITEM entries whose full path matches RE");
+    eprintln!("                 (also applies to dump, dump-json and export-csv)");
+    eprintln!("  --only FILE    Only update entries whose path is listed in FILE, one per line");
+    eprintln!("  --sync-strings Also copy changed string values from patched.bin; the table");
+    eprintln!("                 is rebuilt, since string lengths may change");
+    eprintln!("  --add-missing  Append patched.bin entries that original.bin lacks; the table");
+    eprintln!("                 is rebuilt");
+    eprintln!("  --on-duplicate P");
+    eprintln!("                 For keys repeated in patched.bin use the `first` or `last`");
+    eprintln!("                 size, or fail with `error` (also for repeats in original.bin)");
+    eprintln!("  --report-unmatched");
+    eprintln!("                 List original.bin paths without a patched size and");
+    eprintln!("                 patched.bin paths that matched nothing, by directory");
+    eprintln!("  --encoding E   Decode strings of every table as `sjis`, `utf8` or `utf16le`,");
+    eprintln!("                 ignoring the encoding declared in its footer");
+    eprintln!("  --out-encoding E");
+    eprintln!("                 Write every output table in `sjis`, `utf8` or `utf16le`,");
+    eprintln!("                 re-encoding its strings and setting its footer to match");
+    eprintln!("  --recover      Keep the entries before the first one that cannot be read");
+    eprintln!("                 instead of failing, to salvage a damaged table");
+    eprintln!("  --lenient      Name entries whose name cannot be resolved crc_0x<CRC32>");
+    eprintln!("                 instead of failing");
+    eprintln!("  --force-parse  Ignore a checksum section that is out of range or cannot");
+    eprintln!("                 name the entries, naming them by their CRC32 instead");
+    eprintln!("  --value-length L");
+    eprintln!("                 Parse every table with `int` (4-byte) or `long` (8-byte)");
+    eprintln!("                 values instead of detecting the width");
+    eprintln!("  --schema FILE  Name value indices from a TOML file of `4 = \"size\"` lines in");
+    eprintln!("                 dumps and exports; a `size` index becomes the default --dst-index");
+    eprintln!("  --sort-by K    Rebuild the output (sync or remove) with entries sorted by");
+    eprintln!("                 `name` or by `path`, for reproducible builds");
+    eprintln!("  --color WHEN   Color dump and diff output `auto` (when stdout is a terminal,");
+    eprintln!("                 the default), `always` or `never`; --no-color means never");
+    eprintln!("  -v, -vv        Explain each entry match; -vv also traces table parsing");
+    eprintln!(
+        "  -q, --quiet    Hide warnings and the progress bar shown while syncing large tables"
+    );
+    eprintln!("  --print-hash   Print the SHA-256 of every table written");
+    eprintln!("  --timings      Print how long each phase of a sync took to stderr");
+    eprintln!("  -V, --version  Print the version");
+    eprintln!("  --self-test    Round-trip and sync two embedded tables to check this build");
+    eprintln!();
+    eprintln!("Commands:");
+    eprintln!("  batch          Sync each original (file names may use * and ?) against");
+    eprintln!("                 patched.bin, writing <name>.synced.bin next to it");
+    eprintln!("  dump           Print every entry of input.bin with its indexed, typed values");
+    eprintln!("  dump-json      Print every parsed entry of input.bin as JSON to stdout");
+    eprintln!("  inspect        Print the header and footer fields of input.bin in decimal and");
+    eprintln!("                 hex, then whether it parses; works on tables that fail to parse");
+    eprintln!("  apply-json     Write sizes from a {{\"path\": size}} JSON object into input.bin;");
+    eprintln!("                 unknown paths and 32-bit overflows fail the run with --strict");
+    eprintln!("  export-csv     Write one row per CPK_ITEM (full path, then each value)");
+    eprintln!("  import-csv     Write sizes from a two-column path,size CSV into input.bin;");
+    eprintln!("                 unknown paths and 32-bit overflows fail the run with --strict");
+    eprintln!("  sync-from-dir  Set each CPK_ITEM size to the size of its file under assets_dir");
+    eprintln!("  set-sizes      Write one size into every CPK_ITEM, or with --clamp lower the");
+    eprintln!("                 sizes above MAX to MAX, to test a loader with wrong sizes");
+    eprintln!("  remove         Drop every CPK_ITEM whose full path matches regex and rebuild");
+    eprintln!("                 the table");
+    eprintln!("  diff           List differing values of CPK_ITEM entries matched by path,");
+    eprintln!("                 then the paths found only in a.bin or only in b.bin;");
+    eprintln!("                 --format=unified prints a unified diff of both dumps instead");
     eprintln!();
     eprintln!("Examples:");
     eprintln!("  {bin_name} original.bin patched.bin synced.bin");
+    eprintln!("  {bin_name} original.bin patched.bin");
+    eprintln!("  {bin_name} - patched.bin - < original.bin > synced.bin");
+    eprintln!("  {bin_name} batch patched.bin regions/*.bin");
+    eprintln!("  {bin_name} dump original.bin");
+    eprintln!("  {bin_name} dump-json original.bin > original.json");
+    eprintln!("  {bin_name} inspect broken.bin");
+    eprintln!("  {bin_name} apply-json original.bin sizes.json synced.bin");
+    eprintln!("  {bin_name} export-csv original.bin original.csv");
+    eprintln!("  {bin_name} import-csv original.bin sizes.csv synced.bin");
+    eprintln!("  {bin_name} sync-from-dir original.bin extracted/ synced.bin");
+    eprintln!("  {bin_name} set-sizes original.bin 0 zeroed.bin");
+    eprintln!("  {bin_name} remove original.bin '^debug/' stripped.bin");
+    eprintln!("  {bin_name} diff original.bin patched.bin");
+    eprintln!("  {bin_name} diff --format=unified original.bin patched.bin > patch.diff");
     eprintln!();
     eprintln!("Environment:");
-    eprintln!("  CPK_DEBUG=1    Print debug info about parsed entries");
+    eprintln!("  CPK_DEBUG=1    Same as -vv");
+    eprintln!();
+    eprintln!("Exit status:");
+    eprintln!("  0  Success");
+    eprintln!("  {EXIT_FAILURE}  Usage or other error (also a batch with any failed original)");
+    eprintln!("  {EXIT_PARSE}  A table, JSON or CSV input could not be parsed");
+    eprintln!("  {EXIT_NO_MATCHES}  No patched entries or, with --check, no matching entries");
+    eprintln!("  {EXIT_IO}  Reading an input or writing the output or backup failed");
+    eprintln!("  {EXIT_STRICT}  A --strict check failed");
 }
 
 fn print_version(bin_name: &str) {
     eprintln!("{bin_name} {}", env!("CARGO_PKG_VERSION"));
 }
 
-fn run(path_a: &PathBuf, path_b: &PathBuf, path_c: &PathBuf) -> Result<u32, String> {
-    let debug = std::env::var("CPK_DEBUG").is_ok();
+/// Outcome of syncing one original table.
+struct Synced {
+    updated: u32,
+    value_length: ValueLength,
+    encoding: StringEncoding,
+    /// Entries in the original.
+    entries: usize,
+    /// Original entries that found a size in B.
+    matched: usize,
+    /// What each A size index got, the `--dst-index` first.
+    indices: Vec<IndexSync>,
+    /// Matched B sizes of [`UNKNOWN_SIZE`] replaced or skipped by `--unknown-as`.
+    unknown: usize,
+    /// The `--limit`, when it stopped the sync before every entry was looked at.
+    limit: Option<usize>,
+    /// `(subject, reason)` of the warnings collected for the JSON summary.
+    warnings: Vec<(String, String)>,
+}
 
-    let parsed_a = parse_t2b(path_a).map_err(|e| format!("parse original: {e}"))?;
-    let parsed_b = parse_t2b(path_b).map_err(|e| format!("parse modified: {e}"))?;
+/// The `skipped` and `unmatched` lists of the JSON summary for `index`, indented by
+/// `indent`.
+fn json_lists(index: &IndexSync, indent: &str) -> String {
+    let mut out = String::from("\"skipped\": [");
+    for (i, (key, reason)) in index.skipped.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!(
+            "{indent}  {{ \"path\": {}, \"reason\": {} }}",
+            json_string(key),
+            json_string(reason)
+        ));
+    }
+    if !index.skipped.is_empty() {
+        out.push_str(&format!("\n{indent}"));
+    }
+    out.push_str(&format!("],\n{indent}\"unmatched\": ["));
+    for (i, key) in index.unmatched.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!("{indent}  {}", json_string(key)));
+    }
+    if !index.unmatched.is_empty() {
+        out.push_str(&format!("\n{indent}"));
+    }
+    out.push(']');
+    out
+}
 
-    const B_PRIMARY_SIZE_INDEX: usize = 4; // B의 5번째 줄 (패치된 항목만)
-    const A_PRIMARY_SIZE_INDEX: usize = 4; // A에서 기본 5번째 줄
-    const B_EMPTY_FIELD_INDEX_1: usize = 2; // B의 3번째 줄
-    const B_EMPTY_FIELD_INDEX_2: usize = 3; // B의 4번째 줄
+impl Synced {
+    /// How the original was parsed, e.g. `[value_length=Int, encoding=Utf8]`.
+    fn table_info(&self) -> String {
+        format!(
+            "[value_length={:?}, encoding={:?}]",
+            self.value_length, self.encoding
+        )
+    }
 
-    // Build size map from B (size: require numeric at index 4, and only when 3rd/4th fields are empty).
-    let mut size_map: HashMap<String, (i64, ValueLength)> = HashMap::new();
-    for entry in &parsed_b.entries {
-        if entry.name != "CPK_ITEM" {
-            continue;
+    /// Notes a `--limit` that cut the sync short, e.g. ` (stopped at --limit 3)`.
+    fn limit_note(&self) -> String {
+        match self.limit {
+            Some(limit) => format!(" (stopped at --limit {limit})"),
+            None => String::new(),
         }
+    }
 
-        let key = path_key(entry);
-        if key.is_none() {
-            continue;
+    /// The run summary printed by `--output-format json`. `unchanged`, `skipped` and
+    /// `unmatched` describe the first A index; `indices` has them for each.
+    fn to_json(&self, input: &Path, output: &Path, dry_run: bool) -> String {
+        let path_json = |path: &Path| json_string(&path.display().to_string());
+        let mut out = String::from("{\n");
+        out.push_str(&format!("  \"input\": {},\n", path_json(input)));
+        out.push_str(&format!("  \"output\": {},\n", path_json(output)));
+        out.push_str(&format!("  \"dry_run\": {dry_run},\n"));
+        out.push_str(&format!(
+            "  \"value_length\": \"{:?}\",\n",
+            self.value_length
+        ));
+        out.push_str(&format!("  \"encoding\": \"{:?}\",\n", self.encoding));
+        out.push_str(&format!("  \"entries\": {},\n", self.entries));
+        out.push_str(&format!("  \"matched\": {},\n", self.matched));
+        out.push_str(&format!("  \"updated\": {},\n", self.updated));
+        let first = &self.indices[0];
+        out.push_str(&format!("  \"unchanged\": {},\n", first.unchanged));
+        out.push_str(&format!("  \"unknown_sizes\": {},\n", self.unknown));
+        let limit = self.limit.map_or("null".into(), |n| n.to_string());
+        out.push_str(&format!("  \"limit\": {limit},\n"));
+        out.push_str(&format!("  {},\n", json_lists(first, "  ")));
+        out.push_str("  \"indices\": [");
+        for (i, index) in self.indices.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str(&format!("    {{\n      \"index\": {},\n", index.index));
+            out.push_str(&format!("      \"updated\": {},\n", index.updated));
+            out.push_str(&format!("      \"unchanged\": {},\n", index.unchanged));
+            out.push_str(&format!("      {}\n    }}", json_lists(index, "      ")));
         }
-        let (prefix, suffix) = key.unwrap();
-
-        let empty_field_2 = entry
-            .values
-            .get(B_EMPTY_FIELD_INDEX_1)
-            .map(is_empty_string_field)
-            .unwrap_or(false);
-        let empty_field_3 = entry
-            .values
-            .get(B_EMPTY_FIELD_INDEX_2)
-            .map(is_empty_string_field)
-            .unwrap_or(false);
-        if !(empty_field_2 && empty_field_3) {
-            continue;
+        out.push_str("\n  ],\n  \"warnings\": [");
+        for (i, (subject, reason)) in self.warnings.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str(&format!(
+                "    {{ \"path\": {}, \"reason\": {} }}",
+                json_string(subject),
+                json_string(reason)
+            ));
+        }
+        if !self.warnings.is_empty() {
+            out.push_str("\n  ");
+        }
+        out.push_str("]\n}\n");
+        out
+    }
+}
+
+/// Tables embedded for `--self-test`: an original and a patched copy in which three
+/// `CPK_ITEM` entries have new sizes.
+const SELF_TEST_ORIGINAL: &[u8] = include_bytes!("self_test/original.cfg.bin");
+const SELF_TEST_PATCHED: &[u8] = include_bytes!("self_test/patched.cfg.bin");
+/// The embedded original with the value count of entry 2 (at 0x40) set to 255.
+const SELF_TEST_BAD_VALUE_COUNT: &[u8] = include_bytes!("self_test/bad_value_count.cfg.bin");
+
+/// The sizes a sync of the embedded tables writes, by path.
+const SELF_TEST_UPDATES: &[(&str, i64)] =
+    &[("data/a.bin", 111), ("data/c.bin", 333), ("data/sub/", 444)];
+
+/// Round-trips the embedded original through parse and serialize, then syncs it
+/// against the embedded patched table in memory and checks that exactly the expected
+/// size fields changed. Finally checks that a table with an impossible value count is
+/// rejected as such.
+fn run_self_test() -> Result<(), String> {
+    let original = parse_t2b(SELF_TEST_ORIGINAL).map_err(|e| format!("parse original: {e}"))?;
+    let rebuilt = serialize(&original).map_err(|e| format!("serialize original: {e}"))?;
+    if rebuilt != SELF_TEST_ORIGINAL {
+        let at = rebuilt
+            .iter()
+            .zip(SELF_TEST_ORIGINAL)
+            .position(|(a, b)| a != b)
+            .unwrap_or(rebuilt.len().min(SELF_TEST_ORIGINAL.len()));
+        return Err(format!("round trip: serialized table differs at 0x{at:x}"));
+    }
+    println!(
+        "round trip: ok, {} entries, {} bytes",
+        original.entries.len(),
+        rebuilt.len()
+    );
+
+    let parsed_b = parse_t2b(SELF_TEST_PATCHED).map_err(|e| format!("parse patched: {e}"))?;
+    let opts = SyncOptions::default();
+    let patched = collect_patched(&parsed_b, &opts, &()).map_err(|e| e.to_string())?;
+    let written = write_sizes(&original, &patched.sizes, &opts, &()).map_err(|e| e.to_string())?;
+    let updates = written
+        .updates
+        .iter()
+        .map(|u| (u.path.as_str(), u.new))
+        .collect::<Vec<_>>();
+    let expected = SELF_TEST_UPDATES
+        .iter()
+        .map(|&(path, size)| (path, Size::Int(size)))
+        .collect::<Vec<_>>();
+    if updates != expected {
+        return Err(format!("sync: wrote {updates:?} instead of {expected:?}"));
+    }
+    // Only the bytes of the updated fields may differ.
+    let width = original.value_length as usize;
+    let stray = SELF_TEST_ORIGINAL
+        .iter()
+        .zip(&written.bytes)
+        .enumerate()
+        .find(|&(pos, (a, b))| {
+            a != b
+                && !written
+                    .updates
+                    .iter()
+                    .any(|u| (u.offset..u.offset + width).contains(&pos))
+        });
+    if let Some((pos, _)) = stray {
+        return Err(format!(
+            "sync: byte 0x{pos:x} changed outside the size fields"
+        ));
+    }
+    let mismatches = verify_updates(&written.bytes, &written.updates, ParseOptions::default())
+        .map_err(|e| format!("output does not parse back: {e}"))?;
+    if !mismatches.is_empty() {
+        for mismatch in &mismatches {
+            eprintln!("Verify: {mismatch}");
         }
+        return Err(format!(
+            "output verification failed for {} entries; self-test output is suspect",
+            mismatches.len()
+        ));
+    }
+    println!("sync: ok, {} sizes updated", written.updates.len());
+
+    match parse_t2b(SELF_TEST_BAD_VALUE_COUNT) {
+        Err(T2bError::BadValueCount {
+            entry: 2,
+            offset: 0x40,
+            count: 255,
+        }) => {}
+        Err(err) => return Err(format!("bad value count: failed with `{err}` instead")),
+        Ok(_) => return Err("bad value count: table parsed".to_string()),
+    }
+    println!("bad value count: ok, rejected");
+    Ok(())
+}
+
+fn run(
+    cli: &Cli,
+    path_a: &Path,
+    path_b: &Path,
+    path_c: &Path,
+    opts: &RunOptions,
+) -> Result<Synced, Failure> {
+    let patched = read_patched(cli, path_b, Some(path_a), path_c, opts)?;
+    sync_into(cli, path_a, path_b, path_c, &patched, opts)
+}
+
+/// Parses the patched table at `path_b` and collects its sizes. When B has none,
+/// `path_a` is checked for a [`swap_hint`]; `output` is where an `--auto-index` guess is
+/// reported.
+fn read_patched(
+    cli: &Cli,
+    path_b: &Path,
+    path_a: Option<&Path>,
+    output: &Path,
+    opts: &RunOptions,
+) -> Result<Patched, Failure> {
+    let parsed_b = cli
+        .timed("parse B", || cli.read_t2b(path_b))
+        .map_err(|e| e.context("parse modified"))?;
+    let mut opts_b = opts.sync.clone();
+    opts_b.src_index = resolve_index(
+        cli,
+        "B",
+        &parsed_b,
+        opts.sync.src_index,
+        opts.auto_index,
+        output,
+    )?;
+    if opts.verify_crc {
+        cli.report_crc_mismatches("B", &parsed_b);
+    }
+    cli.timed("map build", || collect_patched(&parsed_b, &opts_b, cli))
+        .map_err(|err| {
+            let err = Failure::from(err);
+            match path_a {
+                Some(path_a) => swap_hint(cli, err, &parsed_b, path_a, &opts.sync),
+                None => err,
+            }
+        })
+}
 
-        let full_path = prefix + &suffix;
+/// Returns `index`, or with `--auto-index` and no explicit index the one
+/// [`guess_size_index`] picks for table `label`, which is printed.
+fn resolve_index(
+    cli: &Cli,
+    label: &str,
+    parsed: &ParsedT2b,
+    index: Option<usize>,
+    auto: bool,
+    output: &Path,
+) -> Result<Option<usize>, Failure> {
+    if !auto || index.is_some() {
+        return Ok(index);
+    }
+    let guessed = guess_size_index(&parsed.entries, &cli.keys)
+        .ok_or_else(|| format!("--auto-index found no plausible size field in {label}"))?;
+    cli.print_summary(output, &format!("{label}: using size index {guessed}"));
+    Ok(Some(guessed))
+}
 
-        let size_field = entry
-            .values
-            .get(B_PRIMARY_SIZE_INDEX)
-            .ok_or_else(|| format!("B missing size field (index {}) for {}", B_PRIMARY_SIZE_INDEX, full_path))?;
+/// Counts the entries of `path_a` that would get a size from `path_b`: those a sync would
+/// update or find already up to date. [`write_sizes`] decides, so filters,
+/// `--unknown-as` and fields that cannot take the size count as in a sync; the table it
+/// returns is dropped.
+fn run_check(cli: &Cli, path_a: &Path, path_b: &Path, opts: &RunOptions) -> Result<usize, Failure> {
+    let patched = read_patched(cli, path_b, Some(path_a), path_a, opts)?;
+    let parsed_a = cli
+        .read_t2b(path_a)
+        .map_err(|e| e.context("parse original"))?;
+    let mut opts_a = opts.sync.clone();
+    opts_a.dst_index = resolve_index(
+        cli,
+        "A",
+        &parsed_a,
+        opts.sync.dst_index,
+        opts.auto_index,
+        path_a,
+    )?;
+    // Every entry counts, wherever a sync would stop.
+    opts_a.limit = None;
+    let written = write_sizes(&parsed_a, &patched.sizes, &opts_a, cli)?;
+    Ok(written.updates.len() + written.unchanged)
+}
 
-        let size_val = match &size_field.data {
-            ValueData::Int(n) => Some(*n),
-            ValueData::Str(Some(s)) => s.trim_matches('"').parse::<i64>().ok(),
-            _ => None,
-        };
+/// Syncs every original against one patched table, writing `<name>.synced.bin` next to
+/// each original. Prints a summary line per file and returns how many failed.
+fn run_batch(
+    cli: &Cli,
+    path_b: &Path,
+    originals: &[PathBuf],
+    opts: &RunOptions,
+) -> Result<usize, Failure> {
+    let first = originals.first().map(PathBuf::as_path);
+    let patched = read_patched(cli, path_b, first, path_b, opts)?;
+
+    let mut failed = 0;
+    for path_a in originals {
+        let stem = path_a.file_stem().unwrap_or_default().to_string_lossy();
+        let path_c = path_a.with_file_name(format!("{stem}.synced.bin"));
+        match sync_into(cli, path_a, path_b, &path_c, &patched, opts) {
+            Ok(synced) if opts.dry_run => {
+                let (updated, info, limit) =
+                    (synced.updated, synced.table_info(), synced.limit_note());
+                let input = path_a.display();
+                println!("{input}: would update {updated} entries {info}{limit}");
+            }
+            Ok(synced) => {
+                let (updated, info, limit) =
+                    (synced.updated, synced.table_info(), synced.limit_note());
+                let (input, output) = (path_a.display(), path_c.display());
+                println!("{input}: updated {updated} entries {info}{limit} -> {output}");
+            }
+            Err(err) => {
+                eprintln!("{}: failed: {err}", path_a.display());
+                failed += 1;
+            }
+        }
+    }
+    let total = originals.len();
+    println!("Synced {} of {total} files", total - failed);
+    Ok(failed)
+}
 
-        if let Some(n) = size_val {
-            size_map.insert(full_path, (n, parsed_b.value_length));
+/// Adds a hint to a failed [`collect_patched`] when B has no patched path rows but the
+/// original at `path_a` does, which usually means the two were given in the wrong
+/// order.
+fn swap_hint(
+    cli: &Cli,
+    err: Failure,
+    parsed_b: &ParsedT2b,
+    path_a: &Path,
+    opts: &SyncOptions,
+) -> Failure {
+    let count_patched = |parsed: &ParsedT2b| {
+        parsed
+            .entries
+            .iter()
+            .filter(|e| cli.keys.is_item(e) && is_patched(e))
+            .count()
+    };
+    if opts.match_by != MatchBy::Path || is_stdio(path_a) || count_patched(parsed_b) > 0 {
+        return err;
+    }
+    match cli.read_t2b(path_a) {
+        Ok(parsed_a) if count_patched(&parsed_a) > 0 => {
+            let count = count_patched(&parsed_a);
+            let path_a = path_a.display();
+            Failure {
+                message: format!(
+                    "{}; did you swap the arguments? {path_a} has {count} patched entries",
+                    err.message
+                ),
+                ..err
+            }
         }
+        _ => err,
     }
+}
 
-    if debug {
+/// Writes the patched values into the table at `path_a` and saves it to `path_c`.
+fn sync_into(
+    cli: &Cli,
+    path_a: &Path,
+    path_b: &Path,
+    path_c: &Path,
+    patched: &Patched,
+    opts: &RunOptions,
+) -> Result<Synced, Failure> {
+    let parsed_a = cli
+        .timed("parse A", || cli.read_t2b(path_a))
+        .map_err(|e| e.context("parse original"))?;
+    let mut sync_opts = opts.sync.clone();
+    sync_opts.dst_index = resolve_index(
+        cli,
+        "A",
+        &parsed_a,
+        opts.sync.dst_index,
+        opts.auto_index,
+        path_c,
+    )?;
+    let sync_opts = &sync_opts;
+
+    if cli.verbosity >= 2 {
+        let roundtrip = serialize(&parsed_a).map(|bytes| bytes == parsed_a.bytes);
+        eprintln!("A re-serializes byte-identically: {roundtrip:?}");
         eprintln!(
-            "B entries: total={}, CPK_ITEM={}",
-            parsed_b.entries.len(),
-            parsed_b
-                .entries
-                .iter()
-                .filter(|e| e.name.starts_with("CPK_ITEM"))
-                .count()
+            "A names with stored CRC32 differing from computed: {}/{}",
+            crc_mismatches(&parsed_a).len(),
+            parsed_a.name_crcs.len()
         );
-        for (i, entry) in parsed_b.entries.iter().take(3).enumerate() {
-            eprintln!(
-                "B entry[{i}] name={} values={} types={:?} vals={:?}",
-                entry.name,
-                entry.values.len(),
-                entry
-                    .values
-                    .iter()
-                    .map(|v| v.typ as u8)
-                    .collect::<Vec<_>>(),
-                entry
-                    .values
-                    .iter()
-                    .map(|v| match &v.data {
-                        ValueData::Str(s) => s.clone().unwrap_or_default(),
-                        ValueData::Int(n) => n.to_string(),
-                        ValueData::Float(f) => f.to_string(),
-                    })
-                    .collect::<Vec<_>>()
-            );
-        }
     }
 
-    if size_map.is_empty() {
-        return Err(
-            "No patched CPK_ITEM entries found in B (needs empty third/fourth fields and numeric fifth field)"
-                .into(),
+    if opts.verify_crc {
+        cli.report_crc_mismatches("A", &parsed_a);
+    }
+    if let Some(filter) = &sync_opts.filter {
+        report_filter(&parsed_a, filter, sync_opts.match_by, &cli.keys);
+    }
+
+    let name = path_a.display().to_string();
+    let TableSync {
+        mut written,
+        indices,
+        separator_matches,
+    } = cli.timed("update loop", || {
+        sync_sizes(&name, &parsed_a, patched, sync_opts, cli)
+    })?;
+    // The first index's updates come first.
+    let primary_updates = &written.updates[..indices[0].updated];
+    report_coverage(
+        cli,
+        path_c,
+        &parsed_a,
+        &written,
+        primary_updates.len(),
+        sync_opts.match_by,
+    );
+    if opts.report_unmatched {
+        report_unmatched(cli, path_c, &written, patched, sync_opts);
+    }
+    if sync_opts.strict {
+        check_all_updated(&indices)?;
+    }
+    if sync_opts.ignore_case {
+        let exact = written.pairs.len() - written.case_folded;
+        let folded = written.case_folded;
+        cli.print_summary(
+            path_c,
+            &format!("Matched {exact} entries exactly and {folded} more ignoring case"),
+        );
+    }
+    if separator_matches > 0 {
+        cli.print_summary(
+            path_c,
+            &format!("Matched {separator_matches} entries only after normalizing path separators"),
         );
     }
 
-    // Work on mutable copy of A bytes.
-    let mut out_bytes = parsed_a.bytes.clone();
-    let mut updated = 0u32;
+    if let Some(only) = &sync_opts.only {
+        let found = parsed_a
+            .entries
+            .iter()
+            .filter_map(|e| sync_opts.key(e))
+            .filter(|key| only.contains(key))
+            .collect::<HashSet<_>>()
+            .len();
+        let updated = primary_updates
+            .iter()
+            .map(|u| u.path.as_str())
+            .collect::<HashSet<_>>()
+            .len();
+        let requested = only.len();
+        cli.print_summary(
+            path_c,
+            &format!("--only: {found} of {requested} listed paths found, {updated} updated"),
+        );
+    }
 
-    for entry in &parsed_a.entries {
-        if entry.name != "CPK_ITEM" {
-            continue;
-        }
-        let key = path_key(entry);
-        if key.is_none() {
-            continue;
-        }
-        let (prefix, suffix) = key.unwrap();
-        let full_key = prefix + &suffix;
+    if sync_opts.unknown_as.is_some() {
+        let count = written.unknown;
+        cli.print_summary(
+            path_c,
+            &format!("Unknown sizes ({UNKNOWN_SIZE}) from B: {count}"),
+        );
+    }
+    if indices.len() > 1 {
+        let lines = indices.iter().map(IndexSync::summary).collect::<Vec<_>>();
+        cli.print_summary(path_c, &lines.join("\n"));
+    }
 
-        let Some((size_val, _)) = size_map.get(&full_key) else {
-            continue;
-        };
+    let mut string_changes = Vec::new();
+    if sync_opts.sync_strings {
+        let (bytes, changes) = write_strings(&written, &patched.strings, sync_opts)?;
+        written.bytes = bytes;
+        string_changes = changes;
+    }
 
-        let target_field = entry
-            .values
-            .get(A_PRIMARY_SIZE_INDEX)
-            .or_else(|| entry.values.last());
-        let Some(target_field) = target_field else { continue };
-        if target_field.typ != ValueType::Integer {
-            continue;
-        }
+    let mut added = Vec::new();
+    if sync_opts.add_missing {
+        let (bytes, keys) = add_missing(&written, patched, sync_opts, cli)?;
+        written.bytes = bytes;
+        added = keys;
+    }
 
-        // Write using A's value length to avoid corruption.
-        let len_bytes = parsed_a.value_length as usize;
-        let offset = target_field.offset;
-        if offset + len_bytes > out_bytes.len() {
-            continue;
-        }
+    if let Some(sort_by) = sync_opts.sort_by {
+        written.bytes = sort_table(&written.bytes, sort_by, &mut written.updates, sync_opts)?;
+    }
 
-        match parsed_a.value_length {
-            ValueLength::Int => {
-                let v = *size_val as i32;
-                out_bytes[offset..offset + 4].copy_from_slice(&v.to_le_bytes());
-            }
-            ValueLength::Long => {
-                let v = *size_val as i64;
-                out_bytes[offset..offset + 8].copy_from_slice(&v.to_le_bytes());
+    let width = parsed_a.value_length as usize;
+    let mut update_lines = Vec::new();
+    let mut updates = written.updates.iter();
+    for index in &indices {
+        let label = match indices.len() {
+            1 => String::new(),
+            _ => format!(" [{}]", index.index),
+        };
+        for update in updates.by_ref().take(index.updated) {
+            let mut line = format!("{}{label}: {} -> {}", update.path, update.old, update.new);
+            if opts.show_offsets {
+                line += &format!(" at 0x{:x} ({width} bytes)", update.offset);
             }
+            update_lines.push(line);
         }
-
-        updated += 1;
     }
 
-    fs::write(path_c, &out_bytes).map_err(|e| format!("write output: {e}"))?;
+    if opts.dry_run {
+        for line in &update_lines {
+            cli.print_report(line);
+        }
+        for change in &string_changes {
+            cli.print_report(change);
+        }
+        for key in &added {
+            cli.print_report(&format!("{key}: added"));
+        }
+    } else {
+        if !opts.no_backup && (same_file(path_c, path_a) || same_file(path_c, path_b)) {
+            backup(path_c, opts.force)?;
+        }
+        cli.timed("write", || cli.write_table(path_c, &written.bytes))?;
+        if opts.preserve_mode && !is_stdio(path_a) && !is_stdio(path_c) {
+            copy_permissions(path_a, path_c)?;
+        }
+        if opts.show_offsets && !update_lines.is_empty() {
+            cli.print_summary(path_c, &update_lines.join("\n"));
+        }
+        cli.timed("verify", || {
+            if is_stdio(path_c) {
+                return verify_output(cli, path_c, &written.bytes, &written);
+            }
+            let bytes = fs::read(path_c)
+                .map_err(|e| Failure::new(EXIT_IO, format!("read back output: {e}")))?;
+            verify_output(cli, path_c, &gunzip(path_c, bytes)?, &written)
+        })?;
+        if sync_opts.sync_strings {
+            let changed = string_changes.len();
+            cli.print_summary(path_c, &format!("Changed {changed} string fields"));
+        }
+        if sync_opts.add_missing {
+            let added = added.len();
+            cli.print_summary(path_c, &format!("Added {added} entries from B"));
+        }
+    }
 
-    Ok(updated)
+    Ok(Synced {
+        updated: written.updates.len() as u32,
+        value_length: parsed_a.value_length,
+        encoding: parsed_a.encoding,
+        entries: parsed_a.entries.len(),
+        matched: written.pairs.len(),
+        indices,
+        unknown: written.unknown,
+        limit: sync_opts.limit.filter(|_| written.limited),
+        warnings: Vec::new(),
+    })
 }
 
-fn path_key(entry: &Entry) -> Option<(String, String)> {
-    if entry.values.len() < 2 {
-        return None;
-    }
-    let prefix = match &entry.values[0].data {
-        ValueData::Str(Some(s)) => s.clone(),
-        _ => return None,
+/// Prints how much of A the sync covered, so a low update count can be told apart from
+/// a layout mismatch. `updated` counts the updates of the first A index.
+fn report_coverage(
+    cli: &Cli,
+    path_c: &Path,
+    parsed_a: &ParsedT2b,
+    written: &SizeWrite,
+    updated: usize,
+    match_by: MatchBy,
+) {
+    let keyed = match match_by {
+        MatchBy::Path => "CPK_ITEM entries",
+        MatchBy::Name => "named entries",
     };
-    let suffix = match &entry.values[1].data {
-        ValueData::Str(Some(s)) => s.clone(),
-        ValueData::Str(None) => String::new(),
-        _ => String::new(),
-    };
-    Some((prefix, suffix))
+    let lines = [
+        format!("Entries in A: {}", parsed_a.entries.len()),
+        format!("{keyed}: {}", written.keyed),
+        format!("Matched in B: {}", written.pairs.len()),
+        format!("Updated: {updated}"),
+        format!("Already up to date: {}", written.unchanged),
+        format!("Skipped, field not numeric: {}", written.type_skipped),
+    ];
+    cli.print_summary(path_c, &lines.join("\n"));
 }
 
-fn is_empty_string_field(field: &ValueField) -> bool {
-    match &field.data {
-        ValueData::Str(None) => true,
-        ValueData::Str(Some(s)) => s.trim_matches('"').is_empty(),
-        _ => false,
-    }
+/// Lists the A keys that found no size and the B keys that matched no entry, each
+/// grouped by directory and sorted.
+fn report_unmatched(
+    cli: &Cli,
+    path_c: &Path,
+    written: &SizeWrite,
+    patched: &Patched,
+    opts: &SyncOptions,
+) {
+    let unused = patched
+        .sizes
+        .keys()
+        .filter(|key| !written.matched.contains(*key))
+        .filter(|key| opts.passes_filter(key))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut report = format!("Unmatched in A ({}):", written.unmatched.len());
+    report += &group_by_dir(&written.unmatched);
+    report += &format!("\nUnused from B ({}):", unused.len());
+    report += &group_by_dir(&unused);
+    cli.print_summary(path_c, &report);
 }
 
-fn parse_t2b(path: &PathBuf) -> Result<ParsedT2b, String> {
-    let bytes = fs::read(path).map_err(|e| format!("read file: {e}"))?;
-    if bytes.len() < 0x30 {
-        return Err("file too small".into());
+/// Formats keys as sorted `  dir/` headings, each followed by its sorted file names.
+/// Keys without a `/` are listed on their own.
+fn group_by_dir(keys: &[String]) -> String {
+    let mut keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+    keys.sort_unstable();
+    let mut out = String::new();
+    let mut current_dir = None;
+    for key in keys {
+        let split = key.rfind('/').map_or(0, |i| i + 1);
+        let (dir, file) = key.split_at(split);
+        if dir.is_empty() {
+            out += &format!("\n  {file}");
+            continue;
+        }
+        if current_dir != Some(dir) {
+            out += &format!("\n  {dir}");
+            current_dir = Some(dir);
+        }
+        out += &format!("\n    {file}");
     }
+    out
+}
 
-    let footer_pos = bytes.len() - 0x10;
-    let magic = read_u32(&bytes, footer_pos).ok_or("footer read failed")?;
-    if magic != MAGIC_T2B {
-        return Err("invalid magic".into());
+/// Checks the written output at `path` with [`verify_updates`], so offset mistakes are
+/// caught before the table reaches the game, printing every field that reads back wrong.
+fn verify_output(cli: &Cli, path: &Path, bytes: &[u8], written: &SizeWrite) -> Result<(), Failure> {
+    let mismatches = verify_updates(bytes, &written.updates, cli.parse)
+        .map_err(|e| format!("output does not parse back: {e}"))?;
+    for mismatch in &mismatches {
+        eprintln!("Verify: {mismatch}");
     }
-    let encoding_raw = read_i16(&bytes, footer_pos + 6).ok_or("footer encoding")?;
-    let encoding = match encoding_raw {
-        0 => StringEncoding::Sjis,
-        1 | 256 | 257 => StringEncoding::Utf8,
-        _ => return Err(format!("unknown encoding {encoding_raw}")),
-    };
-
-    // Entry header
-    let entry_count = read_u32(&bytes, 0).ok_or("entryCount")? as usize;
-    let string_data_offset = read_u32(&bytes, 4).ok_or("stringDataOffset")? as usize;
-    let string_data_length = read_u32(&bytes, 8).ok_or("stringDataLength")? as usize;
+    if !mismatches.is_empty() {
+        return Err(format!(
+            "output verification failed for {} entries; {} is suspect",
+            mismatches.len(),
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
 
-    // Detect value length
-    let value_length = detect_value_length(&bytes, entry_count, string_data_offset)
-        .ok_or("failed to detect value length")?;
+/// Keeps only the entries whose `match_by` key matches `filter`, reporting how many of
+/// the keyed entries were kept. The table bytes are left untouched.
+fn apply_filter(
+    parsed: &mut ParsedT2b,
+    filter: Option<&Regex>,
+    match_by: MatchBy,
+    keys: &PathKeys,
+) {
+    let Some(filter) = filter else {
+        return;
+    };
+    report_filter(parsed, filter, match_by, keys);
+    parsed.entries.retain(|e| {
+        match_by
+            .key(e, keys)
+            .is_some_and(|key| filter.is_match(&key))
+    });
+}
 
-    let (entries_raw, entries_end_pos) =
-        parse_entries(&bytes, entry_count, string_data_offset, value_length)
-            .ok_or("failed to parse entries")?;
+fn report_filter(parsed: &ParsedT2b, filter: &Regex, match_by: MatchBy, keys: &PathKeys) {
+    let keys = parsed
+        .entries
+        .iter()
+        .filter_map(|e| match_by.key(e, keys))
+        .collect::<Vec<_>>();
+    let matched = keys.iter().filter(|key| filter.is_match(key)).count();
+    eprintln!("Filter matched {matched} of {} entries", keys.len());
+}
 
-    if string_data_offset + string_data_length > bytes.len() {
-        return Err("string data out of range".into());
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
     }
-    let value_string_data = &bytes[string_data_offset..string_data_offset + string_data_length];
+}
 
-    let checksum_pos = align_up(string_data_offset + string_data_length, 0x10);
-    if checksum_pos + 0x10 > bytes.len() {
-        return Err("checksum header out of range".into());
+/// Copies `path` to `<path>.bak`, refusing to replace an existing backup unless `force`.
+fn backup(path: &Path, force: bool) -> Result<(), Failure> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    let bak = path.with_file_name(name);
+    if bak.exists() && !force {
+        return Err(format!(
+            "backup {} already exists (use --force to replace it)",
+            bak.display()
+        )
+        .into());
     }
-    let _checksum_size = read_u32(&bytes, checksum_pos).ok_or("checksum size")? as usize;
-    let checksum_count = read_u32(&bytes, checksum_pos + 4).ok_or("checksum count")? as usize;
-    let checksum_string_offset =
-        read_u32(&bytes, checksum_pos + 8).ok_or("checksum string offset")? as usize;
-    let checksum_string_size =
-        read_u32(&bytes, checksum_pos + 12).ok_or("checksum string size")? as usize;
+    fs::copy(path, &bak).map_err(|e| Failure::new(EXIT_IO, format!("write backup: {e}")))?;
+    eprintln!("Backed up {} to {}", path.display(), bak.display());
+    Ok(())
+}
 
-    let checksum_entries_pos = checksum_pos + 0x10;
-    let checksum_strings_pos = checksum_pos + checksum_string_offset;
+/// Tables with at least this many entries show a progress indicator while syncing.
+const PROGRESS_THRESHOLD: usize = 10_000;
+
+/// A progress bar with processed and matched counts, redrawn in place on stderr and
+/// erased when dropped so it never mixes with the summary lines. It stays hidden for
+/// small tables, when `hidden` (by `-q` or `-v`), and unless stdout and stderr are
+/// terminals.
+struct Progress {
+    total: usize,
+    /// Redraw every `step` entries, about once per percent.
+    step: usize,
+    /// Entry count at which to redraw next.
+    next: Cell<usize>,
+    shown: bool,
+}
 
-    if checksum_entries_pos + checksum_count * 8 > bytes.len()
-        || checksum_strings_pos + checksum_string_size > bytes.len()
-    {
-        return Err("checksum section out of range".into());
-    }
-
-    let mut checksum_entries = Vec::with_capacity(checksum_count);
-    for i in 0..checksum_count {
-        let p = checksum_entries_pos + i * 8;
-        let crc = read_u32(&bytes, p).ok_or("checksum entry crc")?;
-        let str_off = read_u32(&bytes, p + 4).ok_or("checksum entry offset")?;
-        checksum_entries.push((crc, str_off));
-    }
-
-    let checksum_string_data =
-        &bytes[checksum_strings_pos..checksum_strings_pos + checksum_string_size];
-
-    // Map crc -> name offset (relative to first string offset)
-    let base_offset = checksum_entries
-        .first()
-        .map(|e| e.1)
-        .ok_or("no checksum entries")?;
-    let mut crc_to_name_offset = HashMap::new();
-    for (crc, off) in &checksum_entries {
-        crc_to_name_offset.insert(*crc, (*off as i64 - base_offset as i64) as usize);
-    }
-
-    let mut entries = Vec::with_capacity(entries_raw.len());
-    for raw in entries_raw {
-        let name_offset = *crc_to_name_offset
-            .get(&raw.crc32)
-            .ok_or("missing name offset")?;
-        let name = read_string(checksum_string_data, name_offset, encoding)
-            .ok_or("name read failed")?;
-
-        let mut values = Vec::with_capacity(raw.types.len());
-        for (idx, typ) in raw.types.iter().enumerate() {
-            let offset = raw.value_offsets[idx];
-            let val = match typ {
-                ValueType::String => {
-                    let val_off = raw.values[idx];
-                    if val_off < 0 {
-                        ValueData::Str(None)
-                    } else {
-                        let v = read_string(
-                            value_string_data,
-                            val_off as usize,
-                            encoding,
-                        );
-                        ValueData::Str(v)
-                    }
-                }
-                ValueType::Integer => {
-                    ValueData::Int(raw.values[idx])
-                }
-                ValueType::FloatingPoint => match value_length {
-                    ValueLength::Int => {
-                        let bits = raw.values[idx] as u32;
-                        ValueData::Float(f32::from_bits(bits) as f64)
-                    }
-                    ValueLength::Long => {
-                        let bits = raw.values[idx] as u64;
-                        ValueData::Float(f64::from_bits(bits))
-                    }
-                },
-            };
-            values.push(ValueField {
-                typ: *typ,
-                data: val,
-                offset,
-            });
+impl Progress {
+    fn new(total: usize, hidden: bool) -> Self {
+        let shown = total >= PROGRESS_THRESHOLD
+            && !hidden
+            && std::io::stdout().is_terminal()
+            && std::io::stderr().is_terminal();
+        Progress {
+            total,
+            step: (total / 100).max(1),
+            next: Cell::new(0),
+            shown,
         }
-
-        entries.push(Entry { name, values });
     }
 
-    // entries_end_pos check optional
-    let _ = entries_end_pos;
+    fn update(&self, processed: usize, matched: usize) {
+        if !self.shown || processed < self.next.get() {
+            return;
+        }
+        self.next.set(processed + self.step);
+        const WIDTH: usize = 30;
+        let filled = processed * WIDTH / self.total;
+        let bar = "#".repeat(filled) + &" ".repeat(WIDTH - filled);
+        let total = self.total;
+        eprint!("\r[{bar}] {processed}/{total} entries, {matched} matched");
+    }
+}
 
-    Ok(ParsedT2b {
-        bytes,
-        value_length,
-        entries,
-    })
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.shown {
+            eprint!("\r\x1b[2K");
+        }
+    }
 }
 
-#[derive(Debug)]
-struct RawEntry {
-    crc32: u32,
-    types: Vec<ValueType>,
-    values: Vec<i64>,
-    value_offsets: Vec<usize>,
+/// Prints the header and footer fields of a table as stored, then whether it parses.
+/// The fields come first and do not depend on parsing, so a table that fails to parse
+/// can still be examined.
+fn run_inspect(cli: &Cli, path: &Path) -> Result<(), Failure> {
+    let bytes = gunzip(path, cli.read_input(path)?)?;
+    let header = read_header(&bytes);
+    fn number<T: fmt::Display + fmt::LowerHex>(value: Option<T>) -> String {
+        match value {
+            Some(v) => format!("{v} (0x{v:x})"),
+            None => "outside the table".to_string(),
+        }
+    }
+    println!("table size:            {}", number(Some(header.table_len)));
+    let magic = match (header.magic, header.endian) {
+        (Some(magic), Some(endian)) => format!("0x{magic:08x} ({endian:?} endian)"),
+        (Some(magic), None) => format!("0x{magic:08x} (unknown; reading as little endian)"),
+        (None, _) => "outside the table".to_string(),
+    };
+    println!("footer magic:          {magic}");
+    let encoding = match (header.encoding_raw, header.encoding) {
+        (Some(raw), Some(encoding)) => format!("{raw} (0x{raw:x}, {encoding:?})"),
+        (Some(raw), None) => format!("{raw} (0x{raw:x}, unknown)"),
+        (None, _) => "outside the table".to_string(),
+    };
+    println!("encoding:              {encoding}");
+    println!("entry count:           {}", number(header.entry_count));
+    println!(
+        "string data offset:    {}",
+        number(header.string_data_offset)
+    );
+    println!(
+        "string data length:    {}",
+        number(header.string_data_length)
+    );
+    println!("string count:          {}", number(header.string_count));
+    let value_length = match header.value_length {
+        Some(length) => format!("{length:?} ({} bytes)", length as usize),
+        None => "not detected".to_string(),
+    };
+    println!("value length:          {value_length}");
+    println!("checksum section:      {}", number(header.checksum_pos));
+    println!("checksum size:         {}", number(header.checksum_size));
+    println!("checksum count:        {}", number(header.checksum_count));
+    println!(
+        "checksum names offset: {}",
+        number(header.checksum_names_offset)
+    );
+    println!(
+        "checksum names size:   {}",
+        number(header.checksum_names_size)
+    );
+
+    let parsed = parse_t2b_owned(bytes, cli.parse, &mut |_| {})
+        .map_err(|e| Failure::new(EXIT_PARSE, e.to_string()))?;
+    println!(
+        "parse:                 ok, {} entries",
+        parsed.entries.len()
+    );
+    Ok(())
 }
 
-fn detect_value_length(
-    bytes: &[u8],
-    entry_count: usize,
-    string_offset: usize,
-) -> Option<ValueLength> {
-    if try_parse_entries(bytes, entry_count, string_offset, ValueLength::Int).is_some() {
-        return Some(ValueLength::Int);
+/// Writes sizes from a `{"path": size}` JSON object into `path_in`, at
+/// `opts.dst_index`. With `opts.strict`, paths missing from the table fail the run.
+fn apply_json(
+    cli: &Cli,
+    path_in: &Path,
+    path_edits: &Path,
+    path_out: &Path,
+    opts: &SyncOptions,
+) -> Result<u32, Failure> {
+    let parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    let text = fs::read_to_string(path_edits)
+        .map_err(|e| Failure::new(EXIT_IO, format!("read edits: {e}")))?;
+    let edits = parse_size_edits(&text)
+        .map_err(|e| Failure::new(EXIT_PARSE, format!("parse edits: {e}")))?;
+
+    let (written, unknown) = write_edits(&parsed, edits, opts, cli)?;
+    for key in &unknown {
+        eprintln!("Unknown path: {key}");
     }
-    if try_parse_entries(bytes, entry_count, string_offset, ValueLength::Long).is_some() {
-        return Some(ValueLength::Long);
+    if opts.strict && !unknown.is_empty() {
+        let count = unknown.len();
+        return Err(Failure::new(
+            EXIT_STRICT,
+            format!("{count} unknown paths in edits"),
+        ));
     }
-    None
+
+    cli.write_table(path_out, &written.bytes)?;
+
+    Ok(written.updates.len() as u32)
 }
 
-fn parse_entries(
-    bytes: &[u8],
-    entry_count: usize,
-    string_offset: usize,
-    value_length: ValueLength,
-) -> Option<(Vec<RawEntry>, usize)> {
-    try_parse_entries(bytes, entry_count, string_offset, value_length)
+/// Writes sizes from a `path,size` CSV into `path_in`, at `opts.dst_index`. With
+/// `opts.strict`, paths missing from the table fail the import.
+fn import_csv(
+    cli: &Cli,
+    path_in: &Path,
+    path_sizes: &Path,
+    path_out: &Path,
+    opts: &SyncOptions,
+) -> Result<u32, Failure> {
+    let parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    let text = fs::read_to_string(path_sizes)
+        .map_err(|e| Failure::new(EXIT_IO, format!("read sizes: {e}")))?;
+    let rows =
+        parse_csv(&text).map_err(|e| Failure::new(EXIT_PARSE, format!("parse sizes: {e}")))?;
+    let edits = parse_size_rows(&rows)?;
+
+    let (written, unknown) = write_edits(&parsed, edits, opts, cli)?;
+    if !unknown.is_empty() {
+        let count = unknown.len();
+        eprintln!("{count} paths not found in {}:", path_in.display());
+        for key in &unknown {
+            eprintln!("  {key}");
+        }
+        if opts.strict {
+            return Err(Failure::new(
+                EXIT_STRICT,
+                format!("{count} unknown paths in sizes"),
+            ));
+        }
+    }
+    cli.write_table(path_out, &written.bytes)?;
+    Ok(written.updates.len() as u32)
 }
 
-fn try_parse_entries(
-    bytes: &[u8],
-    entry_count: usize,
-    string_offset: usize,
-    value_length: ValueLength,
-) -> Option<(Vec<RawEntry>, usize)> {
-    let mut pos = 0x10; // after entry header
-    let mut entries = Vec::with_capacity(entry_count);
-
-    for _ in 0..entry_count {
-        if pos + 5 > bytes.len() || pos + 5 > string_offset {
-            return None;
-        }
-        let crc32 = read_u32(bytes, pos)?;
-        pos += 4;
-        let value_count = bytes.get(pos)?; // entryCount
-        pos += 1;
-
-        let mut types = Vec::with_capacity(*value_count as usize);
-        for j in (0..*value_count).step_by(4) {
-            if pos >= bytes.len() || pos >= string_offset {
-                return None;
-            }
-            let type_chunk = *bytes.get(pos)?;
-            pos += 1;
-            for h in 0..4 {
-                if j + h >= *value_count {
-                    break;
-                }
-                let t = (type_chunk >> (h * 2)) & 0x3;
-                if t == 3 {
-                    return None;
-                }
-                types.push(match t {
-                    0 => ValueType::String,
-                    1 => ValueType::Integer,
-                    2 => ValueType::FloatingPoint,
-                    _ => return None,
-                });
+/// Sets every path row's size at `opts.dst_index` to the length of the file at
+/// `assets_dir` joined with the entry's path. Entries without a regular file on disk are
+/// reported and left unchanged; with `opts.strict` they fail the run.
+fn sync_from_dir(
+    cli: &Cli,
+    path_in: &Path,
+    assets_dir: &Path,
+    path_out: &Path,
+    opts: &SyncOptions,
+) -> Result<u32, Failure> {
+    let parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    if !assets_dir.is_dir() {
+        return Err(format!("not a directory: {}", assets_dir.display()).into());
+    }
+
+    let mut sizes = std::collections::HashMap::new();
+    let mut missing = Vec::new();
+    for entry in parsed.cpk_items_with_keys(&cli.keys) {
+        let Some(full_path) = cli.keys.key(entry) else {
+            continue;
+        };
+        if sizes.contains_key(&full_path) {
+            continue;
+        }
+        // Table paths are relative to the archive root even when they start with a separator.
+        let file = assets_dir.join(full_path.trim_start_matches(['/', '\\']));
+        match fs::metadata(&file) {
+            Ok(meta) if meta.is_file() => {
+                sizes.insert(full_path, meta.len() as i64);
             }
+            _ => missing.push(full_path),
+        }
+    }
+
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        let count = missing.len();
+        eprintln!("{count} paths not found in {}:", assets_dir.display());
+        for path in &missing {
+            eprintln!("  {path}");
+        }
+        if opts.strict {
+            return Err(Failure::new(
+                EXIT_STRICT,
+                format!("{count} paths not found in {}", assets_dir.display()),
+            ));
         }
+    }
+
+    let (written, _) = write_edits(&parsed, sizes, opts, cli)?;
+    cli.write_table(path_out, &written.bytes)?;
+    Ok(written.updates.len() as u32)
+}
 
-        pos = align_up(pos, 4);
+/// What `set-sizes` writes into the size fields.
+#[derive(Debug, Clone, Copy)]
+enum SizeSetting {
+    /// The same size everywhere.
+    Fixed(i64),
+    /// Sizes above the maximum lowered to it; the others left as they are.
+    Clamp(i64),
+}
 
-        let mut values = Vec::with_capacity(types.len());
-        let mut value_offsets = Vec::with_capacity(types.len());
-        for _ in 0..types.len() {
-            if pos + value_length as usize > bytes.len()
-                || pos + value_length as usize > string_offset
-            {
-                return None;
+/// Writes `setting` into the size field at `opts.dst_index` of every path row through
+/// [`write_edits`], so each size is stored at the table's value length and a size that
+/// does not fit is reported as in a sync. Returns the number of changed entries.
+fn set_sizes(
+    cli: &Cli,
+    path_in: &Path,
+    setting: SizeSetting,
+    path_out: &Path,
+    opts: &SyncOptions,
+) -> Result<u32, Failure> {
+    let parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    let mut sizes = std::collections::HashMap::new();
+    for entry in parsed.cpk_items_with_keys(&cli.keys) {
+        let Some(full_path) = cli.keys.key(entry) else {
+            continue;
+        };
+        let size = match setting {
+            SizeSetting::Fixed(size) => size,
+            SizeSetting::Clamp(max) => {
+                let index = opts.size_index();
+                let above = match (entry.integer(index), entry.float(index)) {
+                    (Some(n), _) => n > max,
+                    (_, Some(x)) => x > max as f64,
+                    _ => false,
+                };
+                // Rows without the field go to write_sizes, which skips and reports them.
+                if !above && index < entry.values.len() {
+                    continue;
+                }
+                max
             }
-            value_offsets.push(pos);
-            let v = match value_length {
-                ValueLength::Int => read_i32(bytes, pos)? as i64,
-                ValueLength::Long => read_i64(bytes, pos)?,
-            };
-            values.push(v);
-            pos += value_length as usize;
-        }
-
-        entries.push(RawEntry {
-            crc32,
-            types,
-            values,
-            value_offsets,
-        });
+        };
+        sizes.insert(full_path, size);
     }
 
-    if pos > string_offset || string_offset.saturating_sub(pos) >= 0x10 {
-        return None;
+    let (written, _) = write_edits(&parsed, sizes, opts, cli)?;
+    cli.write_table(path_out, &written.bytes)?;
+    Ok(written.updates.len() as u32)
+}
+
+/// Drops every path row whose full path matches `pattern` and writes the table, rebuilt
+/// with [`serialize`], to `path_out`. Returns the number of removed entries.
+fn remove_entries(
+    cli: &Cli,
+    path_in: &Path,
+    pattern: &Regex,
+    path_out: &Path,
+    sort_by: Option<MatchBy>,
+) -> Result<usize, Failure> {
+    let mut parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    let before = parsed.entries.len();
+    parsed.entries.retain(|entry| match cli.keys.key(entry) {
+        Some(key) if pattern.is_match(&key) => {
+            verbose!(cli, 1, "{key}: removed");
+            false
+        }
+        _ => true,
+    });
+    let removed = before - parsed.entries.len();
+    if let Some(sort_by) = sort_by {
+        sort_entries(&mut parsed.entries, sort_by, &cli.keys);
     }
 
-    Some((entries, pos))
+    let bytes = serialize(&parsed).map_err(|e| format!("rebuild table: {e}"))?;
+    parse_t2b_with(&bytes, cli.parse, &mut |_| {})
+        .map_err(|e| format!("output does not parse back: {e}"))?;
+    cli.write_table(path_out, &bytes)?;
+    Ok(removed)
 }
 
-fn read_string(data: &[u8], offset: usize, enc: StringEncoding) -> Option<String> {
-    if offset >= data.len() {
-        return None;
-    }
-    let mut end = offset;
-    while end < data.len() && data[end] != 0 {
-        end += 1;
+/// Prints, for every path found in both tables, the value indices whose type or data
+/// differ, followed by the paths present in only one of them.
+fn run_diff(cli: &Cli, path_a: &Path, path_b: &Path) -> Result<(), Failure> {
+    let parsed_a = cli
+        .read_t2b(path_a)
+        .map_err(|e| e.context(format!("parse {}", path_a.display())))?;
+    let parsed_b = cli
+        .read_t2b(path_b)
+        .map_err(|e| e.context(format!("parse {}", path_b.display())))?;
+
+    let diff = diff_tables(&parsed_a, &parsed_b, &cli.keys);
+    for (path, diffs) in &diff.changed {
+        println!("{}", cli.paint(path, BOLD));
+        for (i, a, b) in diffs {
+            println!("  [{i}] {} -> {}", cli.paint(a, RED), cli.paint(b, GREEN));
+        }
     }
-    let slice = &data[offset..end];
-    match enc {
-        StringEncoding::Utf8 => std::str::from_utf8(slice).ok().map(|s| s.to_string()),
-        // Fallback: treat SJIS bytes as lossless Latin-1-ish to keep ASCII paths readable.
-        StringEncoding::Sjis => Some(slice.iter().map(|b| *b as char).collect()),
+
+    let summary = format!(
+        "{} changed, {} only in A, {} only in B",
+        diff.changed.len(),
+        diff.only_a.len(),
+        diff.only_b.len()
+    );
+    for (title, only, color) in [
+        ("only in A", &diff.only_a, RED),
+        ("only in B", &diff.only_b, GREEN),
+    ] {
+        if only.is_empty() {
+            continue;
+        }
+        println!("{}", cli.paint(&format!("{title}:"), BOLD));
+        for path in only {
+            println!("  {}", cli.paint(path, color));
+        }
     }
+    eprintln!("{summary}");
+    Ok(())
 }
 
-fn align_up(pos: usize, align: usize) -> usize {
-    (pos + (align - 1)) & !(align - 1)
+/// Prints a unified diff, with three lines of context, between the dumps of two tables.
+/// Prints nothing when the dumps are identical.
+fn run_unified_diff(cli: &Cli, path_a: &Path, path_b: &Path) -> Result<(), Failure> {
+    let parsed_a = cli
+        .read_t2b(path_a)
+        .map_err(|e| e.context(format!("parse {}", path_a.display())))?;
+    let parsed_b = cli
+        .read_t2b(path_b)
+        .map_err(|e| e.context(format!("parse {}", path_b.display())))?;
+    let dump_a = to_dump(&parsed_a, &cli.schema);
+    let dump_b = to_dump(&parsed_b, &cli.schema);
+    let hunks = unified_hunks(&dump_a, &dump_b, 3);
+    if hunks.is_empty() {
+        return Ok(());
+    }
+    println!("{}", cli.paint(&format!("--- {}", path_a.display()), BOLD));
+    println!("{}", cli.paint(&format!("+++ {}", path_b.display()), BOLD));
+    for line in hunks.iter().flat_map(|hunk| hunk.lines()) {
+        let color = match line.chars().next() {
+            Some('@') => CYAN,
+            Some('-') => RED,
+            Some('+') => GREEN,
+            _ => {
+                println!("{line}");
+                continue;
+            }
+        };
+        println!("{}", cli.paint(line, color));
+    }
+    Ok(())
 }
 
-fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
-    if offset + 4 > data.len() {
-        None
-    } else {
-        Some(u32::from_le_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]))
-    }
+/// Writes one CSV row per path row: the full path followed by every value.
+fn export_csv(
+    cli: &Cli,
+    path_in: &Path,
+    path_out: &Path,
+    filter: Option<&Regex>,
+) -> Result<usize, Failure> {
+    let mut parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    apply_filter(&mut parsed, filter, MatchBy::Path, &cli.keys);
+    let (out, rows) = to_csv(&parsed, &cli.keys, &cli.schema)?;
+    cli.write_output(path_out, &out)?;
+    Ok(rows)
 }
 
-fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
-    read_u32(data, offset).map(|v| v as i32)
+/// Writes every stored string of the table as a [`strings_file`].
+fn extract_strings(cli: &Cli, path_in: &Path, path_out: &Path) -> Result<usize, Failure> {
+    let parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    let strings = stored_strings(&parsed).map_err(|e| Failure::new(EXIT_PARSE, e.to_string()))?;
+    cli.write_output(path_out, strings_file(&strings).as_bytes())?;
+    Ok(strings.len())
 }
 
-fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
-    if offset + 2 > data.len() {
-        None
-    } else {
-        Some(i16::from_le_bytes([data[offset], data[offset + 1]]))
+/// Replaces the strings of the table with those of a file in the `extract-strings`
+/// format and rebuilds the table; see [`parse_strings_file`] and [`apply_string_edits`].
+/// Returns the number of changed strings.
+fn import_strings(
+    cli: &Cli,
+    path_in: &Path,
+    path_strings: &Path,
+    path_out: &Path,
+) -> Result<usize, Failure> {
+    let mut parsed = cli
+        .read_t2b(path_in)
+        .map_err(|e| e.context("parse input"))?;
+    let stored = stored_strings(&parsed).map_err(|e| Failure::new(EXIT_PARSE, e.to_string()))?;
+    let text = fs::read_to_string(path_strings)
+        .map_err(|e| Failure::new(EXIT_IO, format!("read strings: {e}")))?;
+    let edits = parse_strings_file(&text, &stored).map_err(|e| Failure::new(EXIT_PARSE, e))?;
+
+    let unencodable = edits
+        .changed
+        .iter()
+        .filter(|(_, text)| !parsed.encoding.can_encode(text))
+        .collect::<Vec<_>>();
+    if !unencodable.is_empty() {
+        let count = unencodable.len();
+        eprintln!(
+            "{count} strings cannot be encoded as {:?}:",
+            parsed.encoding
+        );
+        for (line, text) in &unencodable {
+            eprintln!("  line {line}: {text:?}");
+        }
+        return Err(format!("{count} strings cannot be encoded as {:?}", parsed.encoding).into());
     }
+
+    apply_string_edits(&mut parsed, &edits);
+    let rebuilt = serialize(&parsed).map_err(|e| format!("rebuild table: {e}"))?;
+    cli.write_table(path_out, &rebuilt)?;
+    Ok(edits.values.len() + edits.names.len())
 }
 
-fn read_i64(data: &[u8], offset: usize) -> Option<i64> {
-    if offset + 8 > data.len() {
-        None
-    } else {
-        Some(i64::from_le_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-            data[offset + 4],
-            data[offset + 5],
-            data[offset + 6],
-            data[offset + 7],
-        ]))
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decompresses `bytes` if they start with the gzip magic, and returns them unchanged
+/// otherwise.
+fn gunzip(path: &Path, bytes: Vec<u8>) -> Result<Vec<u8>, Failure> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
     }
+    let mut out = Vec::new();
+    MultiGzDecoder::new(bytes.as_slice())
+        .read_to_end(&mut out)
+        .map_err(|e| Failure::new(EXIT_PARSE, format!("decompress {}: {e}", path.display())))?;
+    Ok(out)
 }
@@ -0,0 +1,1200 @@
+//! Syncs the sizes of a patched table into the original it was made from, as the command
+//! line does: entries are paired by path or name, sizes are read from the patched
+//! entries and written into a copy of the original.
+//!
+//! Nothing here reads or writes files. The caller parses both tables, writes out the
+//! bytes a sync returns, and hears about match decisions, warnings and progress through
+//! a [`SyncLog`].
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use regex::Regex;
+
+use crate::formats::{field_types, value_text};
+use crate::{
+    is_patched, normalize_path_with, parse_t2b_with, path_key, serialize, Entry, ParseOptions,
+    ParsedT2b, PathKeys, PathSep, Size, T2bError, ValueData, ValueLength, ValueType,
+};
+
+/// Size field index of A entries when no `dst_index` is given.
+pub const A_PRIMARY_SIZE_INDEX: usize = 4; // A에서 기본 5번째 줄
+
+/// Size field index of B entries when no `src_index` is given.
+pub const B_PRIMARY_SIZE_INDEX: usize = 4; // B의 5번째 줄 (패치된 항목만)
+
+/// The size some tables store when a file's size is unknown.
+pub const UNKNOWN_SIZE: i64 = -1;
+
+/// Below this share of common path rows the two tables are probably unrelated.
+const MIN_PATH_OVERLAP: f64 = 0.1;
+
+/// Receives what a sync has to say while it runs. Every method does nothing by
+/// default, so `&()` is a silent log.
+pub trait SyncLog {
+    /// An explanation at `level`: 1 for the match decision of each entry, 2 for details
+    /// about the tables as a whole.
+    fn verbose(&self, _level: u8, _message: fmt::Arguments<'_>) {}
+
+    /// A problem that does not stop the sync, about `subject`: an entry key or a table.
+    fn warn(&self, _subject: &str, _reason: &str) {}
+
+    /// The size of entry `key` changed from `old` to `new`.
+    fn update(&self, _key: &str, _old: Size, _new: Size) {}
+
+    /// `processed` of `total` entries were looked at and `matched` of them found a size.
+    /// Called once more with `processed == total` when the loop ends, however it ends.
+    fn progress(&self, _processed: usize, _total: usize, _matched: usize) {}
+}
+
+impl SyncLog for () {}
+
+/// How a sync pairs entries and what it writes. The defaults sync value 4 of the patched
+/// `CPK_ITEM` entries of B into value 4 of the `CPK_ITEM` entries of A with the same
+/// path.
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Size field index in B; must hold a number when set.
+    pub src_index: Option<usize>,
+    /// Size field index in A; must hold a number when set.
+    pub dst_index: Option<usize>,
+    /// Further (B index, A index) pairs synced after the first, from index lists.
+    pub more_indices: Vec<(usize, usize)>,
+    /// Stop updating once this many sizes have been written.
+    pub limit: Option<usize>,
+    /// Fail instead of warning when a size does not fit in a 32-bit field, and when the
+    /// tables look unrelated.
+    pub strict: bool,
+    pub match_by: MatchBy,
+    /// Path rows and the separator form of their paths.
+    pub keys: PathKeys,
+    /// Fall back to ASCII case-insensitive matching of keys.
+    pub ignore_case: bool,
+    /// Only update original entries whose key matches.
+    pub filter: Option<Regex>,
+    /// Only update original entries whose key is listed.
+    pub only: Option<HashSet<String>>,
+    /// Also collect B's string fields for [`write_strings`].
+    pub sync_strings: bool,
+    /// Also collect B's entries for [`add_missing`].
+    pub add_missing: bool,
+    /// Which size a key repeated in B gets. When unset, path keys keep the last size
+    /// and repeated names are dropped.
+    pub on_duplicate: Option<OnDuplicate>,
+    /// Rebuild the output with its entries sorted by this key; see [`sort_table`].
+    pub sort_by: Option<MatchBy>,
+    /// What to write for B sizes of [`UNKNOWN_SIZE`]; copied as is when unset.
+    pub unknown_as: Option<UnknownSize>,
+    /// Overrides the tables were parsed with, used again for the tables a sync rebuilds.
+    pub parse: ParseOptions,
+}
+
+impl SyncOptions {
+    /// The A value index sizes are written into.
+    pub fn size_index(&self) -> usize {
+        self.dst_index.unwrap_or(A_PRIMARY_SIZE_INDEX)
+    }
+
+    /// The key `entry` is matched on, if it has one.
+    pub fn key(&self, entry: &Entry) -> Option<String> {
+        self.match_by.key(entry, &self.keys)
+    }
+
+    /// Whether `key` passes `only` and `filter`.
+    pub fn passes_filter(&self, key: &str) -> bool {
+        let listed = match &self.only {
+            Some(only) => only.contains(key),
+            None => true,
+        };
+        listed
+            && match &self.filter {
+                Some(filter) => filter.is_match(key),
+                None => true,
+            }
+    }
+
+    /// Parses a table the sync wrote, with the same overrides as its input.
+    pub fn reparse(&self, bytes: &[u8]) -> Result<ParsedT2b, T2bError> {
+        parse_t2b_with(bytes, self.parse, &mut |_| {})
+    }
+}
+
+/// What `unknown_as` does with a matched B size of [`UNKNOWN_SIZE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownSize {
+    /// Write this size instead.
+    Replace(i64),
+    /// Leave the A entry unchanged.
+    Skip,
+}
+
+/// Policy for keys that occur more than once in a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Use the size of the first occurrence in B.
+    First,
+    /// Use the size of the last occurrence in B.
+    Last,
+    /// Fail when either table repeats a key.
+    Error,
+}
+
+/// How entries of the original and patched tables are paired up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchBy {
+    /// Path rows keyed on their prefix+suffix path fields.
+    #[default]
+    Path,
+    /// Every entry keyed on its name.
+    Name,
+}
+
+impl MatchBy {
+    /// The key of `entry`: its path under `keys`, or its name.
+    pub fn key(self, entry: &Entry, keys: &PathKeys) -> Option<String> {
+        match self {
+            MatchBy::Path => keys.key(entry),
+            MatchBy::Name => Some(entry.name.clone()),
+        }
+    }
+}
+
+/// Error returned when a sync cannot be done as asked.
+#[derive(Debug)]
+pub enum SyncError {
+    /// B has no entries to take sizes from: no patched path rows, or with
+    /// [`MatchBy::Name`] no entry with a number at `index`.
+    NoSizes { match_by: MatchBy, index: usize },
+    /// Keys that occur more than once in `table` (`A` or `B`), with their number of
+    /// occurrences, under [`OnDuplicate::Error`].
+    Duplicates {
+        table: &'static str,
+        keys: Vec<(String, usize)>,
+    },
+    /// A strict sync found the tables themselves at odds; says how.
+    Strict(String),
+    /// Entries a strict sync could not update, each as `key: reason`.
+    NotUpdated(Vec<String>),
+    /// A size field is missing or cannot take a size; says which.
+    Field(String),
+    /// A table the sync rebuilt could not be serialized or parsed back.
+    Table {
+        action: &'static str,
+        source: T2bError,
+    },
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::NoSizes {
+                match_by: MatchBy::Name,
+                index,
+            } => write!(f, "No entries with a numeric field {index} found in B"),
+            SyncError::NoSizes { .. } => f.write_str(
+                "No patched CPK_ITEM entries found in B (needs empty third/fourth fields and \
+                 numeric fifth field)",
+            ),
+            SyncError::Duplicates { table, keys } => {
+                write!(f, "{} keys occur more than once in {table}", keys.len())
+            }
+            SyncError::Strict(problem) | SyncError::Field(problem) => f.write_str(problem),
+            SyncError::NotUpdated(problems) => {
+                write!(f, "{} entries were not updated", problems.len())
+            }
+            SyncError::Table { action, source } => write!(f, "{action}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SyncError::Table { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Sizes by key, each with the value length of the table it was read from.
+pub type SizeMap = HashMap<String, (Size, ValueLength)>;
+
+/// Values of B's patched entries, keyed by [`SyncOptions::key`]. Collected once per B
+/// and shared by every original a batch syncs.
+pub struct Patched {
+    pub sizes: SizeMap,
+    /// A index and sizes for each further index pair of `more_indices`.
+    pub more_sizes: Vec<(usize, SizeMap)>,
+    /// Value length B was parsed with.
+    pub value_length: ValueLength,
+    /// Non-empty string fields by value index, collected with `sync_strings`.
+    pub strings: HashMap<String, Vec<(usize, String)>>,
+    /// Index in B and copy of each patched entry, collected with `add_missing`.
+    pub entries: HashMap<String, (usize, Entry)>,
+    /// Full path of every path row in B, patched or not, for the overlap check.
+    pub item_paths: HashSet<String>,
+    /// Full paths of the patched entries with their separators as stored, to count the
+    /// matches only the separator conversion made.
+    pub stored_paths: HashSet<String>,
+}
+
+/// Collects the sizes (and with `sync_strings` the strings) of B's patched path rows,
+/// keyed by full path, or of every entry keyed by name.
+pub fn collect_patched(
+    parsed_b: &ParsedT2b,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<Patched, SyncError> {
+    // Build size map from B (size: require numeric at index 4, and only when 3rd/4th fields are empty).
+    let mut size_map = SizeMap::new();
+    let mut strings = HashMap::new();
+    let mut entries = HashMap::new();
+    let mut patched_keys = Vec::new();
+    let mut stored_paths = HashSet::new();
+    let mut more_sizes = vec![SizeMap::new(); opts.more_indices.len()];
+    let keep_first = opts.on_duplicate == Some(OnDuplicate::First);
+    let size_index = opts.src_index.unwrap_or(B_PRIMARY_SIZE_INDEX);
+    let explicit = opts.src_index.is_some();
+    for (index, entry) in parsed_b.entries.iter().enumerate() {
+        let Some(full_path) = opts.key(entry) else {
+            continue;
+        };
+        if opts.match_by == MatchBy::Path && !is_patched(entry) {
+            log.verbose(
+                1,
+                format_args!("B {full_path}: skipped, not a patched entry"),
+            );
+            continue;
+        }
+
+        let Some(n) = patched_size(entry, &full_path, size_index, explicit, opts, log)? else {
+            continue;
+        };
+        log.verbose(1, format_args!("B {full_path}: size {n}"));
+        patched_keys.push(full_path.clone());
+        if let Some((prefix, suffix)) = path_key(entry) {
+            stored_paths.insert(normalize_path_with(&prefix, &suffix, PathSep::Keep));
+        }
+        if keep_first && size_map.contains_key(&full_path) {
+            continue;
+        }
+        for ((src_index, _), sizes) in opts.more_indices.iter().zip(&mut more_sizes) {
+            if let Some(n) = patched_size(entry, &full_path, *src_index, true, opts, log)? {
+                sizes.insert(full_path.clone(), (n, parsed_b.value_length));
+            }
+        }
+        if opts.sync_strings {
+            // Empty strings mark patched entries in B rather than carry values.
+            let fields = (0..entry.values.len())
+                .filter_map(|i| {
+                    let s = entry.string(i).filter(|s| !s.is_empty())?;
+                    Some((i, s.to_string()))
+                })
+                .collect::<Vec<_>>();
+            strings.insert(full_path.clone(), fields);
+        }
+        if opts.add_missing {
+            entries.insert(full_path.clone(), (index, entry.clone()));
+        }
+        size_map.insert(full_path, (n, parsed_b.value_length));
+    }
+    let duplicates = duplicate_keys(patched_keys);
+    match opts.on_duplicate {
+        Some(OnDuplicate::Error) if !duplicates.is_empty() => {
+            return Err(SyncError::Duplicates {
+                table: "B",
+                keys: duplicates,
+            });
+        }
+        // Without a policy a name that occurs more than once in B has no single size.
+        None if opts.match_by == MatchBy::Name => {
+            for (name, _) in &duplicates {
+                size_map.remove(name);
+                for sizes in &mut more_sizes {
+                    sizes.remove(name);
+                }
+                strings.remove(name);
+                entries.remove(name);
+                log.warn(name, "skipped: the name occurs more than once in B");
+            }
+        }
+        policy => {
+            let which = match policy {
+                Some(OnDuplicate::First) => "first",
+                _ => "last",
+            };
+            for (key, count) in &duplicates {
+                let reason = format!("occurs {count} times in B; using the {which} size");
+                log.warn(key, &reason);
+            }
+        }
+    }
+
+    log.verbose(
+        2,
+        format_args!(
+            "B entries: total={}, sizes={}",
+            parsed_b.entries.len(),
+            size_map.len()
+        ),
+    );
+
+    if size_map.is_empty() {
+        return Err(SyncError::NoSizes {
+            match_by: opts.match_by,
+            index: size_index,
+        });
+    }
+    let more_sizes = opts
+        .more_indices
+        .iter()
+        .map(|&(_, dst_index)| dst_index)
+        .zip(more_sizes)
+        .collect();
+    Ok(Patched {
+        sizes: size_map,
+        more_sizes,
+        value_length: parsed_b.value_length,
+        strings,
+        entries,
+        item_paths: item_paths(parsed_b, opts),
+        stored_paths,
+    })
+}
+
+/// Reads the size at `size_index` of the patched B entry `full_path`. An `explicit`
+/// index must name a numeric field of a path-keyed entry; otherwise entries without a
+/// number there are skipped with `Ok(None)`.
+fn patched_size(
+    entry: &Entry,
+    full_path: &str,
+    size_index: usize,
+    explicit: bool,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<Option<Size>, SyncError> {
+    let size_field = match entry.values.get(size_index) {
+        Some(field) => field,
+        // Entries of a name-keyed table need not share one layout.
+        None if opts.match_by == MatchBy::Name => {
+            log.verbose(
+                1,
+                format_args!("B {full_path}: skipped, no field {size_index}"),
+            );
+            return Ok(None);
+        }
+        None => {
+            return Err(SyncError::Field(format!(
+                "B missing size field (index {size_index}) for {full_path}"
+            )))
+        }
+    };
+    if explicit && opts.match_by == MatchBy::Path && size_field.typ == ValueType::String {
+        return Err(SyncError::Field(format!(
+            "B field {size_index} of {full_path} is not numeric (fields: {})",
+            field_types(entry)
+        )));
+    }
+
+    let size_val = entry.size(size_index);
+    if size_val.is_none() {
+        log.verbose(
+            1,
+            format_args!("B {full_path}: skipped, field {size_index} is not a number"),
+        );
+    }
+    Ok(size_val)
+}
+
+/// Returns the keys that occur more than once, sorted, with their number of occurrences.
+fn duplicate_keys(keys: impl IntoIterator<Item = String>) -> Vec<(String, usize)> {
+    let mut counts = HashMap::new();
+    for key in keys {
+        *counts.entry(key).or_insert(0) += 1;
+    }
+    let mut duplicates = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    duplicates
+}
+
+/// Picks the value index that most plausibly holds file sizes: one that is an integer
+/// in every path row and never negative, with the most distinct values, then the
+/// largest total. Ties go to the lower index.
+pub fn guess_size_index(entries: &[Entry], keys: &PathKeys) -> Option<usize> {
+    let items = entries
+        .iter()
+        .filter(|e| keys.is_item(e))
+        .collect::<Vec<_>>();
+    let columns = items.iter().map(|e| e.values.len()).min()?;
+    (0..columns)
+        .filter_map(|i| {
+            let values = items
+                .iter()
+                .map(|e| e.integer(i))
+                .collect::<Option<Vec<_>>>()?;
+            if values.iter().any(|&v| v < 0) {
+                return None;
+            }
+            let distinct = values.iter().collect::<HashSet<_>>().len();
+            let total = values.iter().map(|&v| v as i128).sum::<i128>();
+            Some(((distinct, total), i))
+        })
+        .max_by(|(a, i), (b, j)| a.cmp(b).then(j.cmp(i)))
+        .map(|(_, i)| i)
+}
+
+/// Result of writing a size map into a table.
+pub struct SizeWrite {
+    pub bytes: Vec<u8>,
+    pub updates: Vec<SizeUpdate>,
+    /// Keys of the size map that matched at least one entry.
+    pub matched: HashSet<String>,
+    /// Index into `parsed.entries` and B key of every entry that found a size.
+    pub pairs: Vec<(usize, String)>,
+    /// How many of `pairs` only matched by ignoring case.
+    pub case_folded: usize,
+    /// Entries that have a key, before filtering.
+    pub keyed: usize,
+    /// Keys of entries that passed the filter but found no size.
+    pub unmatched: Vec<String>,
+    /// Matched entries skipped because their size field could not take the size.
+    pub type_skipped: usize,
+    /// Matched entries whose size field already held the size, left as they were.
+    pub unchanged: usize,
+    /// Key and reason for every matched entry left unchanged, including sizes that do
+    /// not fit when `strict` is set.
+    pub skipped: Vec<(String, String)>,
+    /// Matched sizes of [`UNKNOWN_SIZE`] that `unknown_as` replaced or skipped.
+    pub unknown: usize,
+    /// Whether `limit` stopped the loop before the last entry.
+    pub limited: bool,
+}
+
+pub struct SizeUpdate {
+    pub path: String,
+    /// Absolute byte offset of the written field.
+    pub offset: usize,
+    pub old: Size,
+    pub new: Size,
+}
+
+/// Writes matched sizes into the primary size field of every entry keyed by
+/// `opts.match_by` in a copy of `parsed`. An explicit `dst_index` must name a numeric
+/// field in every matched entry; otherwise the default index is used and unsuitable
+/// entries are skipped. With `ignore_case`, entries without an exact match fall back to
+/// an ASCII case-insensitive lookup.
+///
+/// Sizes are written with `ParsedT2b::set_size`, as `apply_sizes` writes them.
+/// Integer fields only take integer sizes. Float fields take either; NaN and infinite
+/// sizes are skipped with a warning. Integer sizes outside the `i32` range and float
+/// sizes beyond the `f32` range in a 32-bit table are written truncated with a
+/// warning, or left unchanged and listed in `skipped` when `strict` is set. Negative
+/// sizes that fit, such as [`UNKNOWN_SIZE`], are written as they are.
+///
+/// [`SyncLog::update`] hears of each written field.
+pub fn write_sizes(
+    parsed: &ParsedT2b,
+    sizes: &SizeMap,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<SizeWrite, SyncError> {
+    let result = write_size_loop(parsed, sizes, opts, log);
+    let total = parsed.entries.len();
+    log.progress(total, total, result.as_ref().map_or(0, |w| w.pairs.len()));
+    result
+}
+
+fn write_size_loop(
+    parsed: &ParsedT2b,
+    sizes: &SizeMap,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<SizeWrite, SyncError> {
+    // Work on a copy of A; its bytes become the output.
+    let mut table = parsed.clone();
+    let mut updates = Vec::new();
+    let mut matched = HashSet::new();
+    let mut pairs = Vec::new();
+    let mut case_folded = 0;
+    let mut keyed = 0;
+    let mut unmatched = Vec::new();
+    let mut skipped = Vec::new();
+    let mut type_skipped = 0;
+    let mut unchanged = 0;
+    let mut unknown = 0;
+    let mut limited = false;
+
+    // Folded key -> B key; `None` when several B keys fold to the same key.
+    let mut folded_sizes = HashMap::new();
+    if opts.ignore_case {
+        for key in sizes.keys() {
+            folded_sizes
+                .entry(key.to_ascii_lowercase())
+                .and_modify(|k| *k = None)
+                .or_insert(Some(key));
+        }
+    }
+
+    let total = parsed.entries.len();
+    for (index, entry) in parsed.entries.iter().enumerate() {
+        if opts.limit.is_some_and(|limit| updates.len() >= limit) {
+            log.verbose(
+                1,
+                format_args!("Stopping after {} updates (--limit)", updates.len()),
+            );
+            limited = true;
+            break;
+        }
+        log.progress(index, total, pairs.len());
+        let Some(full_key) = opts.key(entry) else {
+            continue;
+        };
+        keyed += 1;
+        if !opts.passes_filter(&full_key) {
+            continue;
+        }
+
+        let size_key = if sizes.contains_key(&full_key) {
+            &full_key
+        } else if let Some(Some(key)) = folded_sizes.get(&full_key.to_ascii_lowercase()) {
+            log.verbose(1, format_args!("A {full_key}: matched {key} ignoring case"));
+            case_folded += 1;
+            *key
+        } else {
+            log.verbose(1, format_args!("A {full_key}: no size in B"));
+            unmatched.push(full_key);
+            continue;
+        };
+        let (mut size_val, _) = sizes[size_key];
+        matched.insert(size_key.clone());
+        pairs.push((index, size_key.clone()));
+
+        let sentinel =
+            size_val == Size::Int(UNKNOWN_SIZE) || size_val == Size::Float(UNKNOWN_SIZE as f64);
+        match opts.unknown_as {
+            Some(UnknownSize::Replace(n)) if sentinel => {
+                log.verbose(
+                    1,
+                    format_args!("A {full_key}: size {size_val} from B is unknown, writing {n}"),
+                );
+                unknown += 1;
+                size_val = Size::Int(n);
+            }
+            Some(UnknownSize::Skip) if sentinel => {
+                log.verbose(
+                    1,
+                    format_args!("A {full_key}: skipped, size {size_val} from B is unknown"),
+                );
+                unknown += 1;
+                skipped.push((full_key, format!("size {size_val} from B is unknown")));
+                continue;
+            }
+            _ => {}
+        }
+
+        let value_index = opts.size_index();
+        let explicit = opts.dst_index.is_some();
+        let target_field = match entry.values.get(value_index) {
+            Some(field) if explicit && field.typ == ValueType::String => {
+                return Err(SyncError::Field(format!(
+                    "A field {value_index} of {full_key} is not numeric (fields: {})",
+                    field_types(entry)
+                )))
+            }
+            Some(field) => field,
+            None if explicit => {
+                return Err(SyncError::Field(format!(
+                    "A missing size field (index {value_index}) for {full_key} (fields: {})",
+                    field_types(entry)
+                )))
+            }
+            None => {
+                log.verbose(
+                    1,
+                    format_args!("A {full_key}: skipped, no field {value_index}"),
+                );
+                skipped.push((full_key, format!("no field {value_index}")));
+                continue;
+            }
+        };
+        let (int, float) = (entry.integer(value_index), entry.float(value_index));
+        let (old, new) = match (int, float, size_val) {
+            (Some(old), _, Size::Int(n)) => (Size::Int(old), Size::Int(n)),
+            (_, Some(old), Size::Int(n)) => (Size::Float(old), Size::Float(n as f64)),
+            (_, Some(old), Size::Float(x)) => {
+                if !x.is_finite() {
+                    log.warn(
+                        &full_key,
+                        &format!("skipped: size {x} from B is not finite"),
+                    );
+                    skipped.push((full_key, format!("size {x} is not finite")));
+                    continue;
+                }
+                (Size::Float(old), Size::Float(x))
+            }
+            _ => {
+                log.verbose(
+                    1,
+                    format_args!(
+                        "A {full_key}: skipped, {:?} field cannot take size {size_val}",
+                        target_field.typ
+                    ),
+                );
+                type_skipped += 1;
+                let typ = target_field.typ;
+                skipped.push((full_key, format!("{typ:?} field cannot take {size_val}")));
+                continue;
+            }
+        };
+
+        // A 32-bit table stores only the `f32` nearest to a float size.
+        let stored = match (parsed.value_length, new) {
+            (ValueLength::Int, Size::Float(x)) => Size::Float(x as f32 as f64),
+            _ => new,
+        };
+        if old == stored {
+            log.verbose(1, format_args!("A {full_key}: unchanged, already {old}"));
+            unchanged += 1;
+            continue;
+        }
+
+        // 32-bit tables store out-of-range integer sizes truncated, with a warning.
+        let mut written = new;
+        match (parsed.value_length, new) {
+            (ValueLength::Int, Size::Int(n)) if i32::try_from(n).is_err() => {
+                let v = n as i32;
+                let problem = format!("size {n} does not fit in 32 bits");
+                if opts.strict {
+                    skipped.push((full_key, problem));
+                    continue;
+                }
+                log.warn(&full_key, &format!("{problem} (written as {v})"));
+                written = Size::Int(i64::from(v));
+            }
+            (ValueLength::Int, Size::Float(x)) if !(x as f32).is_finite() => {
+                let problem = format!("size {x:?} does not fit in a 32-bit float");
+                if opts.strict {
+                    skipped.push((full_key, problem));
+                    continue;
+                }
+                log.warn(&full_key, &format!("{problem} (written as {})", x as f32));
+            }
+            _ => {}
+        }
+
+        let offset = target_field.offset;
+        match table.set_size(index, value_index, written) {
+            Ok(_) => {}
+            Err(T2bError::OutOfRange { .. }) => {
+                log.verbose(
+                    1,
+                    format_args!("A {full_key}: skipped, field at 0x{offset:x} is out of range"),
+                );
+                skipped.push((full_key, format!("field at 0x{offset:x} is out of range")));
+                continue;
+            }
+            Err(err) => return Err(SyncError::Field(format!("A {full_key}: {err}"))),
+        }
+
+        log.update(&full_key, old, new);
+        updates.push(SizeUpdate {
+            path: full_key,
+            offset,
+            old,
+            new,
+        });
+    }
+
+    Ok(SizeWrite {
+        bytes: table.bytes,
+        updates,
+        matched,
+        pairs,
+        case_folded,
+        keyed,
+        unmatched,
+        type_skipped,
+        unchanged,
+        skipped,
+        unknown,
+        limited,
+    })
+}
+
+/// What a sync wrote into one A size index.
+pub struct IndexSync {
+    pub index: usize,
+    pub updated: usize,
+    /// Matched entries that already held their size.
+    pub unchanged: usize,
+    /// Key and reason for every matched entry that could not be updated.
+    pub skipped: Vec<(String, String)>,
+    /// Original keys without a size in B for this index.
+    pub unmatched: Vec<String>,
+}
+
+impl IndexSync {
+    pub fn new(index: usize, written: &SizeWrite) -> Self {
+        IndexSync {
+            index,
+            updated: written.updates.len(),
+            unchanged: written.unchanged,
+            skipped: written.skipped.clone(),
+            unmatched: written.unmatched.clone(),
+        }
+    }
+
+    /// One line on this index of a multi-index sync.
+    pub fn summary(&self) -> String {
+        format!(
+            "Index {}: updated {}, unchanged {}, skipped {}, unmatched {}",
+            self.index,
+            self.updated,
+            self.unchanged,
+            self.skipped.len(),
+            self.unmatched.len()
+        )
+    }
+}
+
+/// A table synced by [`sync_sizes`].
+pub struct TableSync {
+    /// The write into the primary index, with the bytes and updates of every index.
+    pub written: SizeWrite,
+    /// What each A index got, the primary first, in the order of `written.updates`.
+    pub indices: Vec<IndexSync>,
+    /// Matched entries whose stored path differs from every patched one in its
+    /// separators alone.
+    pub separator_matches: usize,
+}
+
+/// Writes the sizes of `patched` into a copy of `parsed_a`: the primary index, then
+/// each of `patched.more_sizes`, with `limit` counting the updates of all of them.
+///
+/// Warns about `name`, the original as the caller calls it, when the value lengths
+/// differ or the tables share few paths, and fails instead with `strict`. Keys that
+/// occur more than once in A are warned about, or fail the sync under
+/// [`OnDuplicate::Error`]. Entries a strict sync cannot update are left for
+/// [`check_all_updated`].
+pub fn sync_sizes(
+    name: &str,
+    parsed_a: &ParsedT2b,
+    patched: &Patched,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<TableSync, SyncError> {
+    if parsed_a.value_length != patched.value_length {
+        let (a, b) = (parsed_a.value_length, patched.value_length);
+        let problem = format!(
+            "value lengths differ: original is {a:?} ({} bytes), patched is {b:?} ({} bytes)",
+            a as usize, b as usize
+        );
+        if opts.strict {
+            return Err(SyncError::Strict(problem));
+        }
+        log.warn(
+            name,
+            &format!("{problem}; sizes are written at the original's width"),
+        );
+    }
+    check_overlap(name, parsed_a, patched, opts, log)?;
+
+    let keys_a = parsed_a
+        .entries
+        .iter()
+        .filter_map(|e| opts.key(e))
+        .filter(|key| opts.passes_filter(key));
+    let duplicates = duplicate_keys(keys_a);
+    if opts.on_duplicate == Some(OnDuplicate::Error) && !duplicates.is_empty() {
+        return Err(SyncError::Duplicates {
+            table: "A",
+            keys: duplicates,
+        });
+    }
+    for (key, count) in &duplicates {
+        let reason = format!("occurs {count} times in A; all are matched alike");
+        log.warn(key, &reason);
+    }
+
+    // Every destination of a multi-index sync must be an integer field.
+    let dst_indices = opts
+        .dst_index
+        .into_iter()
+        .filter(|_| !patched.more_sizes.is_empty());
+    for dst_index in dst_indices.chain(patched.more_sizes.iter().map(|(i, _)| *i)) {
+        require_integer_field(parsed_a, dst_index, opts)?;
+    }
+
+    let mut written = write_sizes(parsed_a, &patched.sizes, opts, log)?;
+    let separator_matches = separator_matches(parsed_a, &written, patched, opts);
+    let mut indices = vec![IndexSync::new(opts.size_index(), &written)];
+    let mut table = parsed_a.clone();
+    for (dst_index, sizes) in &patched.more_sizes {
+        let pair_opts = SyncOptions {
+            dst_index: Some(*dst_index),
+            limit: opts.limit.map(|n| n.saturating_sub(written.updates.len())),
+            ..opts.clone()
+        };
+        table.bytes = std::mem::take(&mut written.bytes);
+        let more = write_sizes(&table, sizes, &pair_opts, log)?;
+        indices.push(IndexSync::new(*dst_index, &more));
+        written.limited |= more.limited;
+        written.unknown += more.unknown;
+        written.bytes = more.bytes;
+        written.updates.extend(more.updates);
+    }
+    Ok(TableSync {
+        written,
+        indices,
+        separator_matches,
+    })
+}
+
+/// Counts the entries of `written` that matched only because the separator conversion
+/// of `opts.keys`: their path as stored is not the stored path of any patched entry.
+fn separator_matches(
+    parsed_a: &ParsedT2b,
+    written: &SizeWrite,
+    patched: &Patched,
+    opts: &SyncOptions,
+) -> usize {
+    if opts.match_by != MatchBy::Path || opts.keys.sep == PathSep::Keep {
+        return 0;
+    }
+    written
+        .pairs
+        .iter()
+        .filter(|(index, b_key)| {
+            path_key(&parsed_a.entries[*index]).is_some_and(|(prefix, suffix)| {
+                opts.keys.path(&prefix, &suffix) == *b_key
+                    && !patched.stored_paths.contains(&normalize_path_with(
+                        &prefix,
+                        &suffix,
+                        PathSep::Keep,
+                    ))
+            })
+        })
+        .count()
+}
+
+/// Fails with the entries a strict sync left unchanged in any index: A keys without a
+/// size in B and entries whose size could not be written.
+pub fn check_all_updated(indices: &[IndexSync]) -> Result<(), SyncError> {
+    let problems = indices
+        .iter()
+        .flat_map(|index| {
+            let unmatched = index
+                .unmatched
+                .iter()
+                .map(|key| format!("{key}: no size in B"));
+            let skipped = index
+                .skipped
+                .iter()
+                .map(|(key, reason)| format!("{key}: {reason}"));
+            unmatched.chain(skipped)
+        })
+        .collect::<Vec<_>>();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(SyncError::NotUpdated(problems))
+}
+
+/// Warns, or fails with `strict`, when A and B share few path rows, which usually means
+/// the wrong files were passed. The share is taken of the smaller table, so a patched
+/// table that lists only some of the files still passes.
+fn check_overlap(
+    name: &str,
+    parsed_a: &ParsedT2b,
+    patched: &Patched,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<(), SyncError> {
+    let paths_a = item_paths(parsed_a, opts);
+    let smaller = paths_a.len().min(patched.item_paths.len());
+    if smaller == 0 {
+        return Ok(());
+    }
+    let shared = paths_a.intersection(&patched.item_paths).count();
+    let share = shared as f64 / smaller as f64;
+    log.verbose(
+        2,
+        format_args!("CPK_ITEM paths shared by A and B: {shared} of {smaller}"),
+    );
+    if share >= MIN_PATH_OVERLAP {
+        return Ok(());
+    }
+    let problem = format!(
+        "the tables share only {shared} of {smaller} CPK_ITEM paths ({:.0}%); they may be \
+         unrelated files",
+        share * 100.0
+    );
+    if opts.strict {
+        return Err(SyncError::Strict(problem));
+    }
+    log.warn(name, &problem);
+    Ok(())
+}
+
+/// Full paths of the path rows, in ASCII lowercase with `ignore_case`.
+fn item_paths(parsed: &ParsedT2b, opts: &SyncOptions) -> HashSet<String> {
+    parsed
+        .cpk_items_with_keys(&opts.keys)
+        .filter_map(path_key)
+        .map(|(prefix, suffix)| opts.keys.path(&prefix, &suffix))
+        .map(|path| {
+            if opts.ignore_case {
+                path.to_ascii_lowercase()
+            } else {
+                path
+            }
+        })
+        .collect()
+}
+
+/// Checks that field `index` of every A entry keyed by `opts.match_by` is an integer.
+fn require_integer_field(
+    parsed: &ParsedT2b,
+    index: usize,
+    opts: &SyncOptions,
+) -> Result<(), SyncError> {
+    for entry in &parsed.entries {
+        let Some(key) = opts.key(entry).filter(|key| opts.passes_filter(key)) else {
+            continue;
+        };
+        match entry.values.get(index) {
+            Some(field) if field.typ == ValueType::Integer => {}
+            Some(_) => {
+                return Err(SyncError::Field(format!(
+                    "A field {index} of {key} is not an integer (fields: {})",
+                    field_types(entry)
+                )))
+            }
+            None => {
+                return Err(SyncError::Field(format!(
+                    "A missing size field (index {index}) for {key} (fields: {})",
+                    field_types(entry)
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes externally supplied `(path, size)` edits into `parsed`, returning the result
+/// and the sorted paths that matched no entry. Matched entries that cannot take their
+/// size are warned about, or fail the write with `opts.strict`.
+pub fn write_edits(
+    parsed: &ParsedT2b,
+    edits: impl IntoIterator<Item = (String, i64)>,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<(SizeWrite, Vec<String>), SyncError> {
+    let sizes = edits
+        .into_iter()
+        .map(|(path, size)| (path, (Size::Int(size), ValueLength::Long)))
+        .collect::<SizeMap>();
+    let written = write_sizes(parsed, &sizes, opts, log)?;
+    if opts.strict && !written.skipped.is_empty() {
+        let problems = written
+            .skipped
+            .iter()
+            .map(|(key, reason)| format!("{key}: {reason}"))
+            .collect();
+        return Err(SyncError::NotUpdated(problems));
+    }
+    for (key, reason) in &written.skipped {
+        log.warn(key, &format!("not updated: {reason}"));
+    }
+
+    let mut unknown = sizes
+        .into_keys()
+        .filter(|k| !written.matched.contains(k))
+        .collect::<Vec<_>>();
+    unknown.sort();
+    Ok((written, unknown))
+}
+
+/// Copies B's string fields into the entries `written` matched, rebuilding the table
+/// with [`serialize`] when any string differs. Returns the new bytes, unchanged when
+/// no string differs, and one `path: [index] old -> new` line per changed field.
+pub fn write_strings(
+    written: &SizeWrite,
+    strings: &HashMap<String, Vec<(usize, String)>>,
+    opts: &SyncOptions,
+) -> Result<(Vec<u8>, Vec<String>), SyncError> {
+    // Re-parse so the entries carry the sizes just written.
+    let mut edited = opts
+        .reparse(&written.bytes)
+        .map_err(|source| SyncError::Table {
+            action: "re-parse sizes",
+            source,
+        })?;
+    let mut changes = Vec::new();
+    for (index, b_key) in &written.pairs {
+        let Some(fields) = strings.get(b_key) else {
+            continue;
+        };
+        let entry = &mut edited.entries[*index];
+        let key = opts.key(entry).unwrap_or_default();
+        for (i, new) in fields {
+            let is_string = entry
+                .values
+                .get(*i)
+                .is_some_and(|f| f.typ == ValueType::String);
+            if !is_string || entry.string(*i) == Some(new.as_str()) {
+                continue;
+            }
+            let old = value_text(entry, *i);
+            changes.push(format!("{key}: [{i}] {old} -> {new:?}"));
+            entry.values[*i].data = ValueData::Str(Some(new.clone()));
+        }
+    }
+    if changes.is_empty() {
+        return Ok((written.bytes.clone(), changes));
+    }
+    let bytes = serialize(&edited).map_err(|source| SyncError::Table {
+        action: "rebuild strings",
+        source,
+    })?;
+    Ok((bytes, changes))
+}
+
+/// Appends a copy of every patched entry whose key matched nothing in `written`, in
+/// B's order, and rebuilds the table with [`serialize`] so the new entries take A's
+/// value length and encoding. Returns the new bytes, unchanged when nothing is added,
+/// and the added keys.
+pub fn add_missing(
+    written: &SizeWrite,
+    patched: &Patched,
+    opts: &SyncOptions,
+    log: &dyn SyncLog,
+) -> Result<(Vec<u8>, Vec<String>), SyncError> {
+    let mut missing = patched
+        .entries
+        .iter()
+        .filter(|(key, _)| !written.matched.contains(*key) && opts.passes_filter(key))
+        .map(|(key, (index, entry))| (*index, key, entry))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok((written.bytes.clone(), Vec::new()));
+    }
+    missing.sort_by_key(|(index, _, _)| *index);
+
+    let mut edited = opts
+        .reparse(&written.bytes)
+        .map_err(|source| SyncError::Table {
+            action: "re-parse sizes",
+            source,
+        })?;
+    let mut added = Vec::with_capacity(missing.len());
+    for (_, key, entry) in missing {
+        log.verbose(1, format_args!("A {key}: added from B"));
+        edited.entries.push(entry.clone());
+        added.push(key.clone());
+    }
+    let bytes = serialize(&edited).map_err(|source| SyncError::Table {
+        action: "rebuild with added entries",
+        source,
+    })?;
+    Ok((bytes, added))
+}
+
+/// Rebuilds the table with [`serialize`], its entries stably sorted by their `sort_by`
+/// key (entries without one, such as `CPK_HEAD` under `path`, first), and moves the
+/// offsets of `updates` along with their entries.
+pub fn sort_table(
+    bytes: &[u8],
+    sort_by: MatchBy,
+    updates: &mut [SizeUpdate],
+    opts: &SyncOptions,
+) -> Result<Vec<u8>, SyncError> {
+    let mut parsed = opts.reparse(bytes).map_err(|source| SyncError::Table {
+        action: "re-parse sizes",
+        source,
+    })?;
+    let unsorted = parsed.entries.clone();
+    sort_entries(&mut parsed.entries, sort_by, &opts.keys);
+    let bytes = serialize(&parsed).map_err(|source| SyncError::Table {
+        action: "rebuild sorted table",
+        source,
+    })?;
+
+    let sorted = opts.reparse(&bytes).map_err(|source| SyncError::Table {
+        action: "sorted table does not parse",
+        source,
+    })?;
+    let order = sort_order(&unsorted, sort_by, &opts.keys);
+    let moved = order
+        .iter()
+        .zip(&sorted.entries)
+        .flat_map(|(&old, new)| unsorted[old].values.iter().zip(&new.values))
+        .map(|(old, new)| (old.offset, new.offset))
+        .collect::<HashMap<_, _>>();
+    for update in updates {
+        if let Some(&offset) = moved.get(&update.offset) {
+            update.offset = offset;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Stably sorts `entries` by their `sort_by` key.
+pub fn sort_entries(entries: &mut [Entry], sort_by: MatchBy, keys: &PathKeys) {
+    entries.sort_by_cached_key(|e| sort_by.key(e, keys));
+}
+
+/// Indices of `entries` in the order [`sort_entries`] puts them.
+fn sort_order(entries: &[Entry], sort_by: MatchBy, keys: &PathKeys) -> Vec<usize> {
+    let mut order = (0..entries.len()).collect::<Vec<_>>();
+    order.sort_by_cached_key(|&i| sort_by.key(&entries[i], keys));
+    order
+}
+
+/// Re-parses a written table and checks that every updated field holds its new value,
+/// so offset mistakes are caught before the table reaches the game. Returns one line per
+/// field that reads back wrong, empty when all are right.
+pub fn verify_updates(
+    bytes: &[u8],
+    updates: &[SizeUpdate],
+    parse: ParseOptions,
+) -> Result<Vec<String>, T2bError> {
+    let parsed = parse_t2b_with(bytes, parse, &mut |_| {})?;
+    // Entry and value index of every field, by offset.
+    let fields = parsed
+        .entries
+        .iter()
+        .flat_map(|e| {
+            e.values
+                .iter()
+                .enumerate()
+                .map(move |(i, v)| (v.offset, (e, i)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut mismatches = Vec::new();
+    for update in updates {
+        // Compare at the precision the table stores.
+        let expected = match (update.new, parsed.value_length) {
+            (Size::Int(n), ValueLength::Int) => Size::Int(n as i32 as i64),
+            (Size::Float(x), ValueLength::Int) => Size::Float(x as f32 as f64),
+            (size, ValueLength::Long) => size,
+        };
+        let actual = fields.get(&update.offset);
+        let ok = actual.is_some_and(|&(entry, i)| match expected {
+            Size::Int(e) => entry.integer(i) == Some(e),
+            Size::Float(e) => entry.float(i).is_some_and(|a| a.to_bits() == e.to_bits()),
+        });
+        if !ok {
+            let found = actual.map_or_else(|| "nothing".into(), |&(e, i)| value_text(e, i));
+            mismatches.push(format!(
+                "{} at offset {:#x} reads {found} instead of {expected}",
+                update.path, update.offset,
+            ));
+        }
+    }
+    Ok(mismatches)
+}
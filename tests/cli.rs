@@ -0,0 +1,1032 @@
+//! Runs the command-line tool on tables built in a temporary directory.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use cpk_size_sync::{
+    build_minimal_table, parse_t2b, read_header, serialize, ParsedT2b, StringEncoding, ValueData,
+    ValueLength,
+};
+
+/// A fresh directory under the system temp dir, removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(test: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("cpk_size_sync-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// The path of `name` in the directory, as an argument.
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A `CPK_ITEM` with empty 3rd and 4th fields, so it counts as patched, followed by
+/// `sizes` from index 4 on.
+fn item(prefix: &str, suffix: &str, sizes: &[i64]) -> (String, Vec<ValueData>) {
+    let text = |s: &str| ValueData::Str(Some(s.to_string()));
+    let mut values = vec![text(prefix), text(suffix), text(""), text("")];
+    values.extend(sizes.iter().map(|&n| ValueData::Int(n)));
+    ("CPK_ITEM".to_string(), values)
+}
+
+/// Writes a little-endian UTF-8 table with 4-byte values to `path`.
+fn write_table(path: &str, entries: &[(String, Vec<ValueData>)]) {
+    let bytes = build_minimal_table(entries, ValueLength::Int, StringEncoding::Utf8).unwrap();
+    fs::write(path, bytes).unwrap();
+}
+
+/// The integer at `index` of every `CPK_ITEM` in the table at `path`, by path.
+fn sizes(path: &str, index: usize) -> Vec<(String, Option<i64>)> {
+    let bytes = fs::read(path).unwrap();
+    let parsed = parse_t2b(&bytes).unwrap();
+    parsed
+        .cpk_items()
+        .map(|e| {
+            let path = format!("{}{}", e.string(0).unwrap(), e.string(1).unwrap());
+            (path, e.integer(index))
+        })
+        .collect()
+}
+
+/// Parses the table at `path`.
+fn read(path: &str) -> ParsedT2b {
+    parse_t2b(&fs::read(path).unwrap()).unwrap()
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cpk_size_sync"))
+        .args(args)
+        .env_remove("CPK_DEBUG")
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn import_csv_writes_quoted_crlf_rows_into_dst_index() {
+    let dir = TempDir::new("import-csv-dst-index");
+    let (input, csv, output) = (
+        dir.path("in.bin"),
+        dir.path("sizes.csv"),
+        dir.path("out.bin"),
+    );
+    write_table(
+        &input,
+        &[
+            item("data/", "a,b.bin", &[10, 11]),
+            item("data/", "c.bin", &[20, 21]),
+        ],
+    );
+    fs::write(
+        &csv,
+        "path,size\r\n\"data/a,b.bin\",100\r\n\r\ndata/c.bin, 200\r\n",
+    )
+    .unwrap();
+
+    let out = run(&["import-csv", &input, &csv, &output, "--dst-index", "5"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(
+        sizes(&output, 5),
+        [
+            ("data/a,b.bin".into(), Some(100)),
+            ("data/c.bin".into(), Some(200))
+        ]
+    );
+    assert_eq!(
+        sizes(&output, 4),
+        [
+            ("data/a,b.bin".into(), Some(10)),
+            ("data/c.bin".into(), Some(20))
+        ]
+    );
+}
+
+#[test]
+fn import_csv_takes_the_schema_size_index() {
+    let dir = TempDir::new("import-csv-schema");
+    let (input, csv, output) = (
+        dir.path("in.bin"),
+        dir.path("sizes.csv"),
+        dir.path("out.bin"),
+    );
+    let schema = dir.path("schema.toml");
+    write_table(&input, &[item("data/", "a.bin", &[10, 11])]);
+    fs::write(&csv, "data/a.bin,100\n").unwrap();
+    fs::write(&schema, "5 = \"size\"\n").unwrap();
+
+    let out = run(&["import-csv", &input, &csv, &output, "--schema", &schema]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 5), [("data/a.bin".into(), Some(100))]);
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(10))]);
+}
+
+#[test]
+fn import_csv_strict_fails_on_unknown_paths_without_writing() {
+    let dir = TempDir::new("import-csv-strict");
+    let (input, csv, output) = (
+        dir.path("in.bin"),
+        dir.path("sizes.csv"),
+        dir.path("out.bin"),
+    );
+    write_table(&input, &[item("data/", "a.bin", &[10])]);
+    fs::write(&csv, "data/a.bin,100\ndata/missing.bin,5\n").unwrap();
+
+    let out = run(&["import-csv", &input, &csv, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("data/missing.bin"));
+    fs::remove_file(&output).unwrap();
+
+    let out = run(&["import-csv", "--strict", &input, &csv, &output]);
+    assert_eq!(out.status.code(), Some(5), "{}", stderr(&out));
+    assert!(!PathBuf::from(&output).exists());
+}
+
+#[test]
+fn encoding_override_is_only_reported_with_verbose() {
+    let dir = TempDir::new("encoding-verbose");
+    let input = dir.path("in.bin");
+    write_table(&input, &[item("data/", "a.bin", &[10])]);
+
+    let out = run(&["dump", "--encoding", "utf8", &input]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(
+        !stderr(&out).contains("decoding strings"),
+        "{}",
+        stderr(&out)
+    );
+
+    let out = run(&["dump", "--encoding", "utf8", "-v", &input]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("decoding strings as Utf8"));
+}
+
+#[test]
+fn apply_json_writes_into_dst_index() {
+    let dir = TempDir::new("apply-json-dst-index");
+    let (input, edits, output) = (
+        dir.path("in.bin"),
+        dir.path("edits.json"),
+        dir.path("out.bin"),
+    );
+    write_table(&input, &[item("data/", "a.bin", &[10, 11])]);
+    fs::write(&edits, r#"{ "data/a.bin": 100 }"#).unwrap();
+
+    let out = run(&["apply-json", &input, &edits, &output, "--dst-index", "5"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(10))]);
+    assert_eq!(sizes(&output, 5), [("data/a.bin".into(), Some(100))]);
+}
+
+#[test]
+fn set_sizes_fails_on_a_dst_index_past_the_values() {
+    let dir = TempDir::new("set-sizes-dst-index");
+    let (input, output) = (dir.path("in.bin"), dir.path("out.bin"));
+    write_table(&input, &[item("data/", "a.bin", &[10, 11])]);
+
+    let out = run(&["set-sizes", &input, "0", &output, "--dst-index", "6"]);
+    assert_eq!(out.status.code(), Some(1), "{}", stderr(&out));
+    assert!(stderr(&out).contains("index 6"), "{}", stderr(&out));
+    assert!(!PathBuf::from(&output).exists());
+}
+
+#[test]
+fn set_sizes_reports_rows_without_a_size_field() {
+    let dir = TempDir::new("set-sizes-short-rows");
+    let (input, output) = (dir.path("in.bin"), dir.path("out.bin"));
+    write_table(
+        &input,
+        &[
+            item("data/", "a.bin", &[10]),
+            item("data/", "short.bin", &[]),
+            item("data/", "b.bin", &[300]),
+        ],
+    );
+    let expected = |a, b| {
+        vec![
+            ("data/a.bin".to_string(), Some(a)),
+            ("data/short.bin".to_string(), None),
+            ("data/b.bin".to_string(), Some(b)),
+        ]
+    };
+
+    let out = run(&["set-sizes", &input, "7", &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("[data/short.bin] not updated: no field 4"));
+    assert_eq!(sizes(&output, 4), expected(7, 7));
+
+    let out = run(&["set-sizes", &input, "--clamp", "100", &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("[data/short.bin] not updated: no field 4"));
+    assert_eq!(sizes(&output, 4), expected(10, 100));
+
+    fs::remove_file(&output).unwrap();
+    let out = run(&["set-sizes", "--strict", &input, "7", &output]);
+    assert_eq!(out.status.code(), Some(5), "{}", stderr(&out));
+    assert!(!PathBuf::from(&output).exists());
+}
+
+#[test]
+fn sizes_beyond_32_bits_warn_and_fail_with_strict_but_minus_one_does_not() {
+    let dir = TempDir::new("truncation");
+    let (input, edits, output) = (
+        dir.path("in.bin"),
+        dir.path("edits.json"),
+        dir.path("out.bin"),
+    );
+    write_table(
+        &input,
+        &[
+            item("data/", "big.bin", &[10]),
+            item("data/", "unknown.bin", &[20]),
+        ],
+    );
+    fs::write(
+        &edits,
+        r#"{ "data/big.bin": 3000000000, "data/unknown.bin": -1 }"#,
+    )
+    .unwrap();
+
+    let out = run(&["apply-json", &input, &edits, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(
+        stderr(&out).contains(
+            "[data/big.bin] size 3000000000 does not fit in 32 bits (written as -1294967296)"
+        ),
+        "{}",
+        stderr(&out)
+    );
+    assert!(!stderr(&out).contains("unknown.bin"), "{}", stderr(&out));
+    assert_eq!(
+        sizes(&output, 4),
+        [
+            ("data/big.bin".into(), Some(-1294967296)),
+            ("data/unknown.bin".into(), Some(-1))
+        ]
+    );
+
+    fs::remove_file(&output).unwrap();
+    let out = run(&["apply-json", "--strict", &input, &edits, &output]);
+    assert_eq!(out.status.code(), Some(5), "{}", stderr(&out));
+    assert!(
+        stderr(&out).contains("data/big.bin: size 3000000000 does not fit in 32 bits"),
+        "{}",
+        stderr(&out)
+    );
+    assert!(!stderr(&out).contains("unknown.bin"), "{}", stderr(&out));
+    assert!(!PathBuf::from(&output).exists());
+}
+
+#[test]
+fn sync_from_dir_takes_file_sizes_under_the_assets_dir() {
+    let dir = TempDir::new("sync-from-dir");
+    let (input, assets, output) = (dir.path("in.bin"), dir.path("assets"), dir.path("out.bin"));
+    write_table(
+        &input,
+        &[
+            item("data/", "a.bin", &[1, 2]),
+            // Paths are relative to the assets dir even with a leading separator.
+            item("/data/sub/", "b.bin", &[3, 4]),
+            item("data/", "missing.bin", &[5, 6]),
+            item("data/", "huge.bin", &[7, 8]),
+        ],
+    );
+    fs::create_dir_all(format!("{assets}/data/sub")).unwrap();
+    fs::write(format!("{assets}/data/a.bin"), [0; 100]).unwrap();
+    fs::write(format!("{assets}/data/sub/b.bin"), [0; 200]).unwrap();
+    // Sparse, so it takes no disk space.
+    fs::File::create(format!("{assets}/data/huge.bin"))
+        .unwrap()
+        .set_len(3_000_000_000)
+        .unwrap();
+
+    let out = run(&[
+        "sync-from-dir",
+        &input,
+        &assets,
+        &output,
+        "--dst-index",
+        "5",
+    ]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let err = stderr(&out);
+    assert!(err.contains("1 paths not found in"), "{err}");
+    assert!(err.contains("  data/missing.bin"), "{err}");
+    assert!(
+        err.contains("[data/huge.bin] size 3000000000 does not fit in 32 bits"),
+        "{err}"
+    );
+    assert_eq!(
+        sizes(&output, 5),
+        [
+            ("data/a.bin".into(), Some(100)),
+            ("/data/sub/b.bin".into(), Some(200)),
+            ("data/missing.bin".into(), Some(6)),
+            (
+                "data/huge.bin".into(),
+                Some(3_000_000_000_i64 as i32 as i64)
+            ),
+        ]
+    );
+    // Value 4 is left alone.
+    assert_eq!(
+        sizes(&output, 4)
+            .iter()
+            .map(|(_, n)| *n)
+            .collect::<Vec<_>>(),
+        [Some(1), Some(3), Some(5), Some(7)]
+    );
+
+    fs::remove_file(&output).unwrap();
+    let out = run(&["sync-from-dir", "--strict", &input, &assets, &output]);
+    assert_eq!(out.status.code(), Some(5), "{}", stderr(&out));
+    assert!(!PathBuf::from(&output).exists());
+}
+
+#[test]
+fn check_counts_only_the_entries_a_sync_would_write() {
+    let dir = TempDir::new("check-predicate");
+    let (original, patched) = (dir.path("a.bin"), dir.path("b.bin"));
+    let (name, mut values) = item("data/", "text.bin", &[]);
+    values.push(ValueData::Str(Some("not a size".into())));
+    write_table(
+        &original,
+        &[
+            item("data/", "a.bin", &[10]),
+            (name, values),
+            item("data/", "c.bin", &[30]),
+        ],
+    );
+    write_table(
+        &patched,
+        &[
+            item("data/", "a.bin", &[11]),
+            item("data/", "text.bin", &[12]),
+            item("data/", "c.bin", &[-1]),
+        ],
+    );
+
+    // All three match by path, but the string field of text.bin cannot take a size.
+    let out = run(&["--check", &original, &patched]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "2 matching entries\n");
+
+    // Nor does c.bin get one when unknown sizes are skipped.
+    let out = run(&["--check", "--unknown-as", &original, &patched]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "1 matching entries\n");
+}
+
+#[test]
+fn index_lists_report_each_index_in_the_json_summary() {
+    let dir = TempDir::new("index-lists");
+    let (original, patched, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("c.bin"));
+    write_table(
+        &original,
+        &[
+            item("data/", "a.bin", &[10, 20]),
+            item("data/", "b.bin", &[30, 40]),
+            item("data/", "c.bin", &[50, 60]),
+        ],
+    );
+    write_table(
+        &patched,
+        &[
+            item("data/", "a.bin", &[10, 25]),
+            item("data/", "b.bin", &[35, -1]),
+        ],
+    );
+
+    let out = run(&[
+        &original,
+        &patched,
+        &output,
+        "--src-index",
+        "4,5",
+        "--dst-index",
+        "4,5",
+        "--unknown-as",
+        "--output-format",
+        "json",
+    ]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let summary: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(summary["updated"], 2);
+    assert_eq!(
+        summary["indices"],
+        serde_json::json!([
+            {
+                "index": 4,
+                "updated": 1,
+                "unchanged": 1,
+                "skipped": [],
+                "unmatched": ["data/c.bin"],
+            },
+            {
+                "index": 5,
+                "updated": 1,
+                "unchanged": 0,
+                "skipped": [{ "path": "data/b.bin", "reason": "size -1 from B is unknown" }],
+                "unmatched": ["data/c.bin"],
+            },
+        ])
+    );
+    assert_eq!(summary["skipped"], summary["indices"][0]["skipped"]);
+    assert_eq!(sizes(&output, 4)[1], ("data/b.bin".into(), Some(35)));
+    assert_eq!(sizes(&output, 5)[0], ("data/a.bin".into(), Some(25)));
+
+    let out = run(&[
+        &original,
+        &patched,
+        &output,
+        "--src-index",
+        "4,5",
+        "--dst-index",
+        "4,5",
+        "--unknown-as",
+    ]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("Index 4: updated 1, unchanged 1, skipped 0, unmatched 1\n"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Index 5: updated 1, unchanged 0, skipped 1, unmatched 1\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn apply_json_reports_unknown_paths_and_fails_on_them_with_strict() {
+    let dir = TempDir::new("apply-json-unknown");
+    let (input, edits, output) = (
+        dir.path("in.bin"),
+        dir.path("edits.json"),
+        dir.path("out.bin"),
+    );
+    write_table(&input, &[item("data/", "a.bin", &[10])]);
+    fs::write(&edits, r#"{"data/a.bin": 11, "data/gone.bin": 12}"#).unwrap();
+
+    let out = run(&["apply-json", &input, &edits, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("data/gone.bin"), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(11))]);
+
+    fs::remove_file(&output).unwrap();
+    let out = run(&["apply-json", &input, &edits, &output, "--strict"]);
+    assert_eq!(out.status.code(), Some(5), "{}", stderr(&out));
+    assert!(!fs::exists(&output).unwrap());
+}
+
+#[test]
+fn dry_run_lists_changes_without_writing() {
+    let dir = TempDir::new("dry-run");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(
+        &a,
+        &[item("data/", "a.bin", &[10]), item("data/", "b.bin", &[20])],
+    );
+    write_table(
+        &b,
+        &[item("data/", "a.bin", &[11]), item("data/", "b.bin", &[20])],
+    );
+
+    let out = run(&[&a, &b, &output, "--dry-run"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let text = stdout(&out);
+    assert!(text.contains("data/a.bin: 10 -> 11"), "{text}");
+    assert!(!text.contains("data/b.bin"), "{text}");
+    assert!(text.contains("Would update 1 entries"), "{text}");
+    assert!(!fs::exists(&output).unwrap());
+}
+
+#[test]
+fn diff_lists_changed_values_and_paths_only_in_one_table() {
+    let dir = TempDir::new("diff");
+    let (a, b) = (dir.path("a.bin"), dir.path("b.bin"));
+    write_table(
+        &a,
+        &[item("data/", "a.bin", &[10]), item("data/", "x.bin", &[1])],
+    );
+    write_table(
+        &b,
+        &[item("data/", "a.bin", &[11]), item("data/", "y.bin", &[2])],
+    );
+
+    let out = run(&["diff", &a, &b, "--color", "never"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let text = stdout(&out);
+    let changed = text.find("data/a.bin").unwrap();
+    let only_a = text.find("only in A").unwrap();
+    let only_b = text.find("only in B").unwrap();
+    assert!(changed < only_a && only_a < only_b, "{text}");
+    assert!(text[only_a..only_b].contains("data/x.bin"), "{text}");
+    assert!(text[only_b..].contains("data/y.bin"), "{text}");
+
+    let out = run(&["diff", "--format=unified", &a, &b]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let text = stdout(&out);
+    assert!(text.starts_with("--- "), "{text}");
+    assert!(text.contains("\n+") && text.contains("\n-"), "{text}");
+}
+
+#[test]
+fn export_csv_quotes_paths_with_commas_and_quotes() {
+    let dir = TempDir::new("export-csv");
+    let (input, csv) = (dir.path("in.bin"), dir.path("out.csv"));
+    write_table(&input, &[item("data/", "a,\"b\".bin", &[10])]);
+
+    let out = run(&["export-csv", &input, &csv]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let text = fs::read_to_string(&csv).unwrap();
+    let mut lines = text.lines();
+    assert!(lines.next().unwrap().starts_with("path,"), "{text}");
+    assert_eq!(
+        lines.next().unwrap(),
+        "\"data/a,\"\"b\"\".bin\",data/,\"a,\"\"b\"\".bin\",,,10"
+    );
+}
+
+#[test]
+fn in_place_sync_backs_up_once_unless_forced() {
+    let dir = TempDir::new("backup");
+    let (a, b, backup) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("a.bin.bak"));
+    write_table(&a, &[item("data/", "a.bin", &[10])]);
+    write_table(&b, &[item("data/", "a.bin", &[11])]);
+    let original = fs::read(&a).unwrap();
+
+    let out = run(&[&a, &b]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(fs::read(&backup).unwrap(), original);
+    assert_eq!(sizes(&a, 4), [("data/a.bin".into(), Some(11))]);
+
+    let synced = fs::read(&a).unwrap();
+    let out = run(&[&a, &b]);
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("--force"), "{}", stderr(&out));
+    assert_eq!(fs::read(&a).unwrap(), synced);
+
+    let out = run(&[&a, &b, "--force"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(fs::read(&backup).unwrap(), synced);
+
+    fs::remove_file(&backup).unwrap();
+    let out = run(&[&a, &b, "--no-backup"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(!fs::exists(&backup).unwrap());
+}
+
+#[test]
+fn batch_writes_a_synced_copy_next_to_each_original() {
+    let dir = TempDir::new("batch");
+    fs::create_dir(dir.path("regions")).unwrap();
+    let patched = dir.path("patched.bin");
+    write_table(&patched, &[item("data/", "a.bin", &[11])]);
+    write_table(
+        &dir.path("regions/eu.bin"),
+        &[item("data/", "a.bin", &[10])],
+    );
+    write_table(
+        &dir.path("regions/us.bin"),
+        &[item("data/", "a.bin", &[12])],
+    );
+
+    let out = run(&["batch", &patched, &dir.path("regions/*.bin")]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    for region in ["eu", "us"] {
+        let synced = dir.path(&format!("regions/{region}.synced.bin"));
+        assert_eq!(sizes(&synced, 4), [("data/a.bin".into(), Some(11))]);
+    }
+}
+
+#[test]
+fn match_by_name_pairs_entries_by_their_name() {
+    let dir = TempDir::new("match-by-name");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    let entry = |name: &str, size| {
+        let values = vec![ValueData::Str(Some("x".into())), ValueData::Int(size)];
+        (name.to_string(), values)
+    };
+    write_table(&a, &[entry("SIZE_A", 10), entry("SIZE_B", 20)]);
+    write_table(&b, &[entry("SIZE_B", 21), entry("SIZE_C", 30)]);
+
+    let out = run(&[
+        &a,
+        &b,
+        &output,
+        "--match-by",
+        "name",
+        "--src-index",
+        "1",
+        "--dst-index",
+        "1",
+    ]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let sizes: Vec<_> = read(&output)
+        .entries()
+        .map(|e| (e.name.clone(), e.integer(1)))
+        .collect();
+    assert_eq!(
+        sizes,
+        [("SIZE_A".into(), Some(10)), ("SIZE_B".into(), Some(21))]
+    );
+}
+
+#[test]
+fn ignore_case_matches_paths_that_differ_in_case_and_says_so() {
+    let dir = TempDir::new("ignore-case");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(&a, &[item("Data/", "A.bin", &[10])]);
+    write_table(&b, &[item("data/", "a.bin", &[11])]);
+
+    let out = run(&[&a, &b, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("Data/A.bin".into(), Some(10))]);
+
+    let out = run(&[&a, &b, &output, "--ignore-case", "--force"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stdout(&out).contains("ignoring case"), "{}", stdout(&out));
+    assert_eq!(sizes(&output, 4), [("Data/A.bin".into(), Some(11))]);
+}
+
+#[test]
+fn sync_strings_copies_changed_strings_from_patched() {
+    let dir = TempDir::new("sync-strings");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    let with_label = |size, label: &str| {
+        let (name, mut values) = item("data/", "a.bin", &[size]);
+        values.push(ValueData::Str(Some(label.to_string())));
+        (name, values)
+    };
+    write_table(&a, &[with_label(10, "old")]);
+    write_table(&b, &[with_label(11, "a longer label")]);
+
+    let out = run(&[&a, &b, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(read(&output).entries[0].string(5), Some("old"));
+
+    let out = run(&[&a, &b, &output, "--sync-strings"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let parsed = read(&output);
+    assert_eq!(parsed.entries[0].string(5), Some("a longer label"));
+    assert_eq!(parsed.entries[0].integer(4), Some(11));
+}
+
+#[test]
+fn on_duplicate_picks_the_first_or_last_size_or_fails() {
+    let dir = TempDir::new("on-duplicate");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(&a, &[item("data/", "a.bin", &[10])]);
+    write_table(
+        &b,
+        &[item("data/", "a.bin", &[11]), item("data/", "a.bin", &[12])],
+    );
+
+    let out = run(&[&a, &b, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("data/a.bin"), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(12))]);
+
+    let out = run(&[&a, &b, &output, "--on-duplicate", "first"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(11))]);
+
+    fs::remove_file(&output).unwrap();
+    let out = run(&[&a, &b, &output, "--on-duplicate", "error"]);
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("data/a.bin"), "{}", stderr(&out));
+    assert!(!fs::exists(&output).unwrap());
+}
+
+#[test]
+fn add_missing_appends_entries_only_in_patched() {
+    let dir = TempDir::new("add-missing");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(&a, &[item("data/", "a.bin", &[10])]);
+    write_table(
+        &b,
+        &[
+            item("data/", "a.bin", &[11]),
+            item("data/", "new.bin", &[5]),
+        ],
+    );
+
+    let out = run(&[&a, &b, &output, "--add-missing"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(
+        sizes(&output, 4),
+        [
+            ("data/a.bin".into(), Some(11)),
+            ("data/new.bin".into(), Some(5))
+        ]
+    );
+}
+
+#[test]
+fn remove_drops_matching_paths_and_keeps_the_layout_otherwise() {
+    let dir = TempDir::new("remove");
+    let (input, output) = (dir.path("in.bin"), dir.path("out.bin"));
+    write_table(
+        &input,
+        &[
+            item("data/", "a.bin", &[10]),
+            item("debug/", "log.bin", &[20]),
+        ],
+    );
+
+    let out = run(&["remove", &input, "^debug/", &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(10))]);
+
+    // A table padding its sections to 8 bytes rebuilds byte for byte. Dropping the
+    // CPK_HEAD name makes serialize rebuild the checksum section with that padding too.
+    let head = ("CPK_HEAD".to_string(), vec![ValueData::Int(1)]);
+    write_table(&input, &[head, item("data/", "a.bin", &[10])]);
+    let mut parsed = read(&input);
+    parsed.entries.remove(0);
+    parsed.alignment.sections = 8;
+    let unusual = dir.path("unusual.bin");
+    fs::write(&unusual, serialize(&parsed).unwrap()).unwrap();
+    assert_eq!(read(&unusual).alignment.sections, 8);
+    let out = run(&["remove", &unusual, "^nothing/", &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&unusual).unwrap());
+}
+
+#[test]
+fn sort_by_path_rebuilds_the_output_in_path_order() {
+    let dir = TempDir::new("sort-by");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(
+        &a,
+        &[
+            item("data/", "c.bin", &[30]),
+            item("data/", "a.bin", &[10]),
+            item("data/", "b.bin", &[20]),
+        ],
+    );
+    write_table(&b, &[item("data/", "a.bin", &[11])]);
+
+    let out = run(&[&a, &b, &output, "--sort-by", "path"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(
+        sizes(&output, 4),
+        [
+            ("data/a.bin".into(), Some(11)),
+            ("data/b.bin".into(), Some(20)),
+            ("data/c.bin".into(), Some(30))
+        ]
+    );
+}
+
+#[test]
+fn gzip_inputs_are_read_and_gz_outputs_compressed() {
+    use std::io::{Read, Write};
+
+    let dir = TempDir::new("gzip");
+    let (a, b, output) = (
+        dir.path("a.bin.gz"),
+        dir.path("b.bin"),
+        dir.path("out.bin.gz"),
+    );
+    let table = build_minimal_table(
+        &[item("data/", "a.bin", &[10])],
+        ValueLength::Int,
+        StringEncoding::Utf8,
+    )
+    .unwrap();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&table).unwrap();
+    fs::write(&a, gz.finish().unwrap()).unwrap();
+    write_table(&b, &[item("data/", "a.bin", &[11])]);
+
+    let out = run(&[&a, &b, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let mut synced = Vec::new();
+    flate2::read::GzDecoder::new(fs::File::open(&output).unwrap())
+        .read_to_end(&mut synced)
+        .unwrap();
+    let parsed = parse_t2b(&synced).unwrap();
+    assert_eq!(parsed.cpk_items().next().unwrap().integer(4), Some(11));
+}
+
+#[test]
+fn json_summary_goes_to_stdout() {
+    let dir = TempDir::new("json-summary");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(
+        &a,
+        &[item("data/", "a.bin", &[10]), item("data/", "b.bin", &[20])],
+    );
+    write_table(&b, &[item("data/", "a.bin", &[11])]);
+
+    let out = run(&[&a, &b, &output, "--output-format", "json"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let summary: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(summary["updated"], 1);
+    assert_eq!(summary["unmatched"], serde_json::json!(["data/b.bin"]));
+}
+
+#[test]
+fn only_limits_updates_to_the_listed_paths() {
+    let dir = TempDir::new("only");
+    let (a, b, list, output) = (
+        dir.path("a.bin"),
+        dir.path("b.bin"),
+        dir.path("only.txt"),
+        dir.path("out.bin"),
+    );
+    write_table(
+        &a,
+        &[item("data/", "a.bin", &[10]), item("data/", "b.bin", &[20])],
+    );
+    write_table(
+        &b,
+        &[item("data/", "a.bin", &[11]), item("data/", "b.bin", &[21])],
+    );
+    fs::write(&list, "data/a.bin\ndata/missing.bin\n").unwrap();
+
+    let out = run(&[&a, &b, &output, "--only", &list]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(
+        sizes(&output, 4),
+        [
+            ("data/a.bin".into(), Some(11)),
+            ("data/b.bin".into(), Some(20))
+        ]
+    );
+    let report = stdout(&out) + &stderr(&out);
+    assert!(report.contains("1 of 2"), "{report}");
+}
+
+#[test]
+fn limit_stops_after_n_sizes_and_says_so() {
+    let dir = TempDir::new("limit");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    let table = |base| {
+        ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| item("data/", &format!("{name}.bin"), &[base + i as i64]))
+            .collect::<Vec<_>>()
+    };
+    write_table(&a, &table(10));
+    write_table(&b, &table(20));
+
+    let out = run(&[&a, &b, &output, "--limit", "2"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stdout(&out).contains("--limit 2"), "{}", stdout(&out));
+    assert_eq!(
+        sizes(&output, 4),
+        [
+            ("data/a.bin".into(), Some(20)),
+            ("data/b.bin".into(), Some(21)),
+            ("data/c.bin".into(), Some(12))
+        ]
+    );
+}
+
+#[test]
+fn recover_keeps_the_entries_before_a_damaged_one() {
+    let input = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/self_test/bad_value_count.cfg.bin"
+    );
+
+    let out = run(&["dump", input]);
+    assert_eq!(out.status.code(), Some(2), "{}", stderr(&out));
+
+    let out = run(&["dump", input, "--recover", "--color", "never"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stderr(&out).contains("2 of 5"), "{}", stderr(&out));
+}
+
+#[test]
+fn extracted_strings_import_back_with_edits() {
+    let dir = TempDir::new("strings");
+    let (input, strings, output) = (
+        dir.path("in.bin"),
+        dir.path("strings.txt"),
+        dir.path("out.bin"),
+    );
+    write_table(&input, &[item("data/", "a.bin", &[10])]);
+
+    let out = run(&["extract-strings", &input, &strings]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let text = fs::read_to_string(&strings).unwrap();
+    assert!(text.lines().any(|l| l.ends_with("\tCPK_ITEM")), "{text}");
+    assert!(text.lines().any(|l| l.ends_with("\ta.bin")), "{text}");
+
+    fs::write(&strings, text.replace("\ta.bin", "\trenamed.bin")).unwrap();
+    let out = run(&["import-strings", &input, &strings, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/renamed.bin".into(), Some(10))]);
+
+    let first_line = text.lines().next().unwrap();
+    fs::write(&strings, format!("{first_line}\n")).unwrap();
+    let out = run(&["import-strings", &input, &strings, &output]);
+    assert_eq!(out.status.code(), Some(2), "{}", stderr(&out));
+}
+
+#[test]
+fn out_encoding_re_encodes_the_strings() {
+    let dir = TempDir::new("out-encoding");
+    let (input, output) = (dir.path("in.bin"), dir.path("out.bin"));
+    write_table(&input, &[item("データ/", "a.bin", &[10])]);
+
+    let out = run(&[
+        "set-sizes",
+        &input,
+        "5",
+        &output,
+        "--out-encoding",
+        "utf16le",
+    ]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let parsed = read(&output);
+    assert!(matches!(parsed.encoding, StringEncoding::Utf16Le));
+    assert_eq!(sizes(&output, 4), [("データ/a.bin".into(), Some(5))]);
+
+    write_table(&input, &[item("data/", "🙂.bin", &[10])]);
+    fs::remove_file(&output).unwrap();
+    let out = run(&["set-sizes", &input, "5", &output, "--out-encoding", "sjis"]);
+    assert!(!out.status.success());
+    assert!(!fs::exists(&output).unwrap());
+}
+
+#[test]
+fn force_parse_names_entries_despite_a_broken_checksum_section() {
+    let dir = TempDir::new("force-parse");
+    let input = dir.path("in.bin");
+    let mut table = build_minimal_table(
+        &[item("data/", "a.bin", &[10])],
+        ValueLength::Int,
+        StringEncoding::Utf8,
+    )
+    .unwrap();
+    let count = read_header(&table).checksum_pos.unwrap() + 4;
+    table[count..count + 4].copy_from_slice(&0x00ff_ffffu32.to_le_bytes());
+    fs::write(&input, &table).unwrap();
+
+    let out = run(&["dump", &input]);
+    assert_eq!(out.status.code(), Some(2), "{}", stderr(&out));
+
+    let out = run(&["dump", &input, "--force-parse", "--color", "never"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stdout(&out).contains("a.bin"), "{}", stdout(&out));
+}
+
+#[test]
+fn unknown_as_replaces_minus_one_sizes_and_counts_them() {
+    let dir = TempDir::new("unknown-as");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(&a, &[item("data/", "a.bin", &[10])]);
+    write_table(&b, &[item("data/", "a.bin", &[-1])]);
+
+    let out = run(&[&a, &b, &output]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(-1))]);
+
+    let out = run(&[&a, &b, &output, "--unknown-as", "0"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(
+        stdout(&out).contains("Unknown sizes (-1) from B: 1"),
+        "{}",
+        stdout(&out)
+    );
+    assert_eq!(sizes(&output, 4), [("data/a.bin".into(), Some(0))]);
+}
+
+#[test]
+fn timings_print_each_phase_to_stderr() {
+    let dir = TempDir::new("timings");
+    let (a, b, output) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("out.bin"));
+    write_table(&a, &[item("data/", "a.bin", &[10])]);
+    write_table(&b, &[item("data/", "a.bin", &[11])]);
+
+    let out = run(&[&a, &b, &output, "--timings"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(!stdout(&out).contains("total"), "{}", stdout(&out));
+    let text = stderr(&out);
+    assert!(text.contains("parse") && text.contains("total"), "{text}");
+}